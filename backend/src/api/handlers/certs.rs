@@ -1,11 +1,17 @@
 use crate::acme::AcmeManager;
 use crate::api::{
+    sync_state,
     types::{CertRes, CreateCertReq, CreateDnsProviderReq, DnsProviderRes},
     ApiState,
 };
 use crate::auth::Claims;
+use crate::constants;
 use crate::db;
 use crate::error::AppError;
+use crate::state::{
+    parse_pem_certs, parse_upstream_client_cert, upstream_ca_bundle_file,
+    upstream_client_cert_files, validate_upstream_cert_name,
+};
 use axum::{
     extract::{Json, Multipart, Path as AxumPath, State},
     http::StatusCode,
@@ -138,6 +144,102 @@ pub async fn upload_cert(
     ))
 }
 
+/// Stores a client certificate/key pair and/or a CA bundle for upstream TLS
+/// under `name`, which hosts and locations refer to in `upstream_tls`.
+pub async fn upload_upstream_cert(
+    claims: Claims,
+    State(state): State<ApiState>,
+    mut multipart: Multipart,
+) -> Result<StatusCode, AppError> {
+    if !claims.can_manage_hosts() {
+        return Err(AppError::Forbidden("Insufficient permissions".to_string()));
+    }
+
+    let mut name = None;
+    let mut cert_data = None;
+    let mut key_data = None;
+    let mut ca_data = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(e.to_string()))?
+    {
+        let field_name = field.name().unwrap_or("").to_string();
+        if field_name == "name" {
+            if let Ok(txt) = field.text().await {
+                name = Some(txt);
+            }
+        } else if let Ok(bytes) = field.bytes().await {
+            match field_name.as_str() {
+                "cert" => cert_data = Some(bytes),
+                "key" => key_data = Some(bytes),
+                "ca" => ca_data = Some(bytes),
+                _ => {}
+            }
+        }
+    }
+
+    let name = name.ok_or_else(|| AppError::BadRequest("Missing name".to_string()))?;
+    validate_upstream_cert_name(&name).map_err(AppError::BadRequest)?;
+    let client_pair = match (cert_data, key_data) {
+        (Some(cert), Some(key)) => {
+            parse_upstream_client_cert(&cert, &key)
+                .map_err(|e| AppError::BadRequest(format!("Client certificate: {}", e)))?;
+            Some((cert, key))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(AppError::BadRequest(
+                "cert and key must be uploaded together".to_string(),
+            ))
+        }
+    };
+    if let Some(ca) = &ca_data {
+        parse_pem_certs(ca).map_err(|e| AppError::BadRequest(format!("CA bundle: {}", e)))?;
+    }
+    if client_pair.is_none() && ca_data.is_none() {
+        return Err(AppError::BadRequest(
+            "Missing cert and key, or ca".to_string(),
+        ));
+    }
+
+    let dir = Path::new(constants::cert::UPSTREAM_DIR);
+    fs::create_dir_all(dir)?;
+    let mut uploaded = Vec::new();
+    if let Some((cert, key)) = client_pair {
+        let (cert_file, key_file) = upstream_client_cert_files(&name);
+        fs::write(dir.join(cert_file), cert)?;
+        fs::write(dir.join(key_file), key)?;
+        uploaded.push("client certificate");
+    }
+    if let Some(ca) = ca_data {
+        fs::write(dir.join(upstream_ca_bundle_file(&name)), ca)?;
+        uploaded.push("CA bundle");
+    }
+
+    let _ = db::insert_audit_log(
+        &state.db_pool,
+        &claims.sub,
+        Some(claims.user_id),
+        "upload",
+        "upstream_certificate",
+        Some(&name),
+        Some(&format!(
+            "Uploaded upstream {} for {}",
+            uploaded.join(" and "),
+            name
+        )),
+        None,
+    )
+    .await;
+
+    tracing::info!("💾 Upstream TLS files uploaded for {}", name);
+    // Hosts that already refer to the name pick up the new files
+    sync_state(&state).await;
+    Ok(StatusCode::CREATED)
+}
+
 pub async fn list_dns_providers(
     _: Claims,
     State(state): State<ApiState>,
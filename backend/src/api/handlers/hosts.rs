@@ -1,18 +1,28 @@
 use crate::api::{
     sync_state,
     types::{
-        CreateHeaderReq, CreateHostReq, CreateLocationReq, DeleteLocationQuery, HeaderRes, HostRes,
-        LocationRes,
+        CachePurgeReq, CachePurgeRes, CreateHeaderReq, CreateHostReq, CreateLocationReq,
+        DeleteLocationQuery, HeaderRes, HostRes, LocationRes, UpstreamHealthRes,
     },
     ApiState,
 };
 use crate::auth::Claims;
+use crate::constants;
 use crate::db;
 use crate::error::AppError;
+use crate::proxy::{cache, header_vars, target_groups};
+use crate::state::{
+    validate_host_pattern, CacheConfig, CompressionConfig, ForwardedHeadersConfig, HeaderOperation,
+    HealthCheckConfig, HostConfig, LbHashKey, LbMethod, LocationConfig, LocationMatchType,
+    MaintenanceConfig, MirrorConfig, RateLimitConfig, RetryPolicy, RewriteRule, StaticFilesConfig,
+    StickySessionConfig, TargetGroupsConfig, UpstreamConnectionConfig, UpstreamProtocol,
+    UpstreamTarget, UpstreamTlsConfig,
+};
 use axum::{
     extract::{Json, Path as AxumPath, Query, State},
-    http::StatusCode,
+    http::{HeaderName, StatusCode},
 };
+use std::path::Path;
 
 fn to_i64_opt(value: Option<u64>) -> Option<i64> {
     value.and_then(|v| i64::try_from(v).ok())
@@ -22,6 +32,220 @@ fn sanitize_optional_i64(value: Option<i64>) -> Option<i64> {
     value.filter(|v| *v > 0)
 }
 
+fn parse_match_type(raw: &str) -> Result<LocationMatchType, AppError> {
+    LocationMatchType::parse(raw).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Invalid match_type '{}': expected prefix, exact, regex or regex_insensitive",
+            raw
+        ))
+    })
+}
+
+/// Validates the target list and load-balancing settings shared by hosts and
+/// locations.
+fn parse_lb_settings(
+    target: &str,
+    lb_method: Option<&str>,
+    lb_hash_key: Option<&str>,
+) -> Result<(Option<LbMethod>, Option<LbHashKey>), AppError> {
+    UpstreamTarget::parse_list(target).map_err(AppError::BadRequest)?;
+
+    let method = lb_method
+        .map(|raw| {
+            LbMethod::parse(raw).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Invalid lb_method '{}': expected random, round_robin, weighted_round_robin, least_requests, ip_hash or consistent_hash",
+                    raw
+                ))
+            })
+        })
+        .transpose()?;
+    let hash_key = lb_hash_key
+        .filter(|raw| !raw.is_empty())
+        .map(|raw| {
+            LbHashKey::parse(raw).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Invalid lb_hash_key '{}': expected header:<name> or cookie:<name>",
+                    raw
+                ))
+            })
+        })
+        .transpose()?;
+
+    if method == Some(LbMethod::ConsistentHash) && hash_key.is_none() {
+        return Err(AppError::BadRequest(
+            "lb_method consistent_hash requires lb_hash_key".to_string(),
+        ));
+    }
+
+    Ok((method, hash_key))
+}
+
+/// Validates a health check and encodes it for storage.
+fn encode_health_check(check: Option<&HealthCheckConfig>) -> Result<Option<String>, AppError> {
+    check
+        .map(|check| {
+            check.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(check).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_sticky_session(sticky: Option<&StickySessionConfig>) -> Result<Option<String>, AppError> {
+    sticky
+        .map(|sticky| {
+            sticky.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(sticky).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_retry_policy(policy: Option<&RetryPolicy>) -> Result<Option<String>, AppError> {
+    policy
+        .map(|policy| {
+            policy.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(policy).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_cache(cache: Option<&CacheConfig>) -> Result<Option<String>, AppError> {
+    cache
+        .map(|cache| {
+            cache.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(cache).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_compression(compression: Option<&CompressionConfig>) -> Result<Option<String>, AppError> {
+    compression
+        .map(|compression| {
+            compression.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(compression).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_rate_limit(rate_limit: Option<&RateLimitConfig>) -> Result<Option<String>, AppError> {
+    rate_limit
+        .map(|rate_limit| {
+            rate_limit.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(rate_limit).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_forwarded_headers(
+    forwarded_headers: Option<&ForwardedHeadersConfig>,
+) -> Result<Option<String>, AppError> {
+    forwarded_headers
+        .map(|forwarded_headers| {
+            forwarded_headers.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(forwarded_headers).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_rewrite_rules(rules: Option<&[RewriteRule]>) -> Result<Option<String>, AppError> {
+    rules
+        .filter(|rules| !rules.is_empty())
+        .map(|rules| {
+            for rule in rules {
+                rule.validate().map_err(AppError::BadRequest)?;
+            }
+            serde_json::to_string(rules).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_static_files(
+    static_files: Option<&StaticFilesConfig>,
+) -> Result<Option<String>, AppError> {
+    static_files
+        .map(|static_files| {
+            static_files.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(static_files).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_maintenance(maintenance: Option<&MaintenanceConfig>) -> Result<Option<String>, AppError> {
+    maintenance
+        .map(|maintenance| {
+            maintenance.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(maintenance).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_mirror(mirror: Option<&MirrorConfig>) -> Result<Option<String>, AppError> {
+    mirror
+        .map(|mirror| {
+            mirror.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(mirror).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_target_groups(
+    target_groups: Option<&TargetGroupsConfig>,
+) -> Result<Option<String>, AppError> {
+    target_groups
+        .map(|target_groups| {
+            target_groups.validate().map_err(AppError::BadRequest)?;
+            serde_json::to_string(target_groups).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_upstream_tls(
+    upstream_tls: Option<&UpstreamTlsConfig>,
+) -> Result<Option<String>, AppError> {
+    upstream_tls
+        .map(|upstream_tls| {
+            upstream_tls.validate().map_err(AppError::BadRequest)?;
+            // Reject missing or mismatched files now rather than at the next reload
+            upstream_tls
+                .load_material(Path::new(constants::cert::UPSTREAM_DIR))
+                .map_err(AppError::BadRequest)?;
+            serde_json::to_string(upstream_tls).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
+fn encode_upstream_protocol(
+    raw: Option<&str>,
+    scheme: Option<&str>,
+) -> Result<UpstreamProtocol, AppError> {
+    let protocol = match raw {
+        Some(raw) => UpstreamProtocol::parse(raw).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Invalid upstream_protocol '{}': expected http1, h2 or h2c",
+                raw
+            ))
+        })?,
+        None => UpstreamProtocol::default(),
+    };
+    protocol
+        .validate_scheme(scheme.unwrap_or("http"))
+        .map_err(AppError::BadRequest)?;
+    Ok(protocol)
+}
+
+fn encode_upstream_connection(
+    upstream_connection: Option<&UpstreamConnectionConfig>,
+) -> Result<Option<String>, AppError> {
+    upstream_connection
+        .map(|upstream_connection| {
+            upstream_connection
+                .validate()
+                .map_err(AppError::BadRequest)?;
+            serde_json::to_string(upstream_connection).map_err(|e| AppError::Config(e.to_string()))
+        })
+        .transpose()
+}
+
 pub async fn list_hosts(
     _: Claims,
     State(state): State<ApiState>,
@@ -33,7 +257,7 @@ pub async fn list_hosts(
         .map(|(d, c)| HostRes {
             domain: d.clone(),
             // Join Vec<String> back to String for API compatibility
-            target: c.targets.join(","),
+            target: UpstreamTarget::join(&c.targets),
             scheme: c.scheme.clone(),
             ssl_forced: c.ssl_forced,
             verify_ssl: c.verify_ssl,
@@ -50,7 +274,8 @@ pub async fn list_hosts(
                 .iter()
                 .map(|loc| LocationRes {
                     path: loc.path.clone(),
-                    target: loc.targets.join(","),
+                    match_type: loc.match_type.as_str().to_string(),
+                    target: UpstreamTarget::join(&loc.targets),
                     scheme: loc.scheme.clone(),
                     rewrite: loc.rewrite,
                     verify_ssl: loc.verify_ssl,
@@ -59,6 +284,26 @@ pub async fn list_hosts(
                     read_timeout_ms: to_i64_opt(loc.read_timeout_ms),
                     write_timeout_ms: to_i64_opt(loc.write_timeout_ms),
                     max_request_body_bytes: to_i64_opt(loc.max_request_body_bytes),
+                    lb_method: loc.lb_method.map(|m| m.as_str().to_string()),
+                    lb_hash_key: loc.lb_hash_key.as_ref().map(|k| k.to_string()),
+                    health_check: loc.health_check.clone(),
+                    sticky_session: loc.sticky_session.clone(),
+                    retry_policy: loc.retry_policy.clone(),
+                    cache: loc.cache.clone(),
+                    compression: loc.compression.clone(),
+                    rate_limit: loc.rate_limit.clone(),
+                    access_list_id: loc.access_list_id,
+                    redirect_to: loc.redirect_to.clone(),
+                    redirect_status: loc.redirect_status,
+                    rewrite_rules: loc.rewrite_rules.clone(),
+                    static_files: loc.static_files.clone(),
+                    mirror: loc.mirror.clone(),
+                    target_groups: loc.target_groups.clone(),
+                    upstream_tls: loc.upstream_tls.clone(),
+                    upstream_protocol: loc.upstream_protocol.as_str().to_string(),
+                    websocket_idle_timeout_ms: to_i64_opt(loc.websocket_idle_timeout_ms),
+                    websocket_max_lifetime_ms: to_i64_opt(loc.websocket_max_lifetime_ms),
+                    upstream_connection: loc.upstream_connection,
                 })
                 .collect(),
             access_list_id: c.access_list_id,
@@ -70,8 +315,33 @@ pub async fn list_hosts(
                     name: h.name.clone(),
                     value: h.value.clone(),
                     target: h.target.clone(),
+                    operation: h.operation.as_str().to_string(),
+                    location: h.location.as_ref().map(|l| l.path.clone()),
+                    match_type: h
+                        .location
+                        .as_ref()
+                        .map(|l| l.match_type.as_str().to_string()),
                 })
                 .collect(),
+            is_default: c.is_default,
+            lb_method: c.lb_method.as_str().to_string(),
+            lb_hash_key: c.lb_hash_key.as_ref().map(|k| k.to_string()),
+            health_check: c.health_check.clone(),
+            sticky_session: c.sticky_session.clone(),
+            retry_policy: c.retry_policy.clone(),
+            cache: c.cache.clone(),
+            compression: c.compression.clone(),
+            rate_limit: c.rate_limit.clone(),
+            forwarded_headers: c.forwarded_headers.clone(),
+            rewrite_rules: c.rewrite_rules.clone(),
+            maintenance: c.maintenance.clone(),
+            mirror: c.mirror.clone(),
+            target_groups: c.target_groups.clone(),
+            upstream_tls: c.upstream_tls.clone(),
+            upstream_protocol: c.upstream_protocol.as_str().to_string(),
+            websocket_idle_timeout_ms: to_i64_opt(c.websocket_idle_timeout_ms),
+            websocket_max_lifetime_ms: to_i64_opt(c.websocket_max_lifetime_ms),
+            upstream_connection: c.upstream_connection,
         })
         .collect();
     Ok(Json(res))
@@ -86,14 +356,43 @@ pub async fn add_host(
         return Err(AppError::Forbidden("Insufficient permissions".to_string()));
     }
 
+    validate_host_pattern(&payload.domain).map_err(AppError::BadRequest)?;
+    let (lb_method, lb_hash_key) = parse_lb_settings(
+        &payload.target,
+        payload.lb_method.as_deref(),
+        payload.lb_hash_key.as_deref(),
+    )?;
+    let lb_method = lb_method.unwrap_or_default();
+    let lb_hash_key = lb_hash_key.map(|k| k.to_string());
+    let health_check = encode_health_check(payload.health_check.as_ref())?;
+    let sticky_session = encode_sticky_session(payload.sticky_session.as_ref())?;
+    let retry_policy = encode_retry_policy(payload.retry_policy.as_ref())?;
+    let cache = encode_cache(payload.cache.as_ref())?;
+    let compression = encode_compression(payload.compression.as_ref())?;
+    let rate_limit = encode_rate_limit(payload.rate_limit.as_ref())?;
+    let forwarded_headers = encode_forwarded_headers(payload.forwarded_headers.as_ref())?;
+    let rewrite_rules = encode_rewrite_rules(payload.rewrite_rules.as_deref())?;
+    let maintenance = encode_maintenance(payload.maintenance.as_ref())?;
+    let mirror = encode_mirror(payload.mirror.as_ref())?;
+    let target_groups = encode_target_groups(payload.target_groups.as_ref())?;
+    let upstream_tls = encode_upstream_tls(payload.upstream_tls.as_ref())?;
+    let upstream_connection = encode_upstream_connection(payload.upstream_connection.as_ref())?;
+    let upstream_protocol = encode_upstream_protocol(
+        payload.upstream_protocol.as_deref(),
+        payload.scheme.as_deref(),
+    )?;
+
     let scheme = payload.scheme.clone().unwrap_or_else(|| "http".to_string());
     let ssl_forced = payload.ssl_forced.unwrap_or(false);
     let verify_ssl = payload.verify_ssl.unwrap_or(true);
     let redirect_status = payload.redirect_status.unwrap_or(301);
+    let is_default = payload.is_default.unwrap_or(false);
     let connection_timeout_ms = sanitize_optional_i64(payload.connection_timeout_ms);
     let read_timeout_ms = sanitize_optional_i64(payload.read_timeout_ms);
     let write_timeout_ms = sanitize_optional_i64(payload.write_timeout_ms);
     let max_request_body_bytes = sanitize_optional_i64(payload.max_request_body_bytes);
+    let websocket_idle_timeout_ms = sanitize_optional_i64(payload.websocket_idle_timeout_ms);
+    let websocket_max_lifetime_ms = sanitize_optional_i64(payload.websocket_max_lifetime_ms);
 
     let is_update = db::get_host_id(&state.db_pool, &payload.domain)
         .await?
@@ -115,13 +414,32 @@ pub async fn add_host(
             redirect_to: payload.redirect_to.as_deref(),
             redirect_status,
             access_list_id: payload.access_list_id,
+            is_default,
+            lb_method: lb_method.as_str(),
+            lb_hash_key: lb_hash_key.as_deref(),
+            health_check: health_check.as_deref(),
+            sticky_session: sticky_session.as_deref(),
+            retry_policy: retry_policy.as_deref(),
+            cache: cache.as_deref(),
+            compression: compression.as_deref(),
+            rate_limit: rate_limit.as_deref(),
+            forwarded_headers: forwarded_headers.as_deref(),
+            rewrite_rules: rewrite_rules.as_deref(),
+            maintenance: maintenance.as_deref(),
+            mirror: mirror.as_deref(),
+            target_groups: target_groups.as_deref(),
+            upstream_tls: upstream_tls.as_deref(),
+            upstream_protocol: upstream_protocol.as_str(),
+            websocket_idle_timeout_ms,
+            websocket_max_lifetime_ms,
+            upstream_connection: upstream_connection.as_deref(),
         },
     )
     .await?;
 
     let action = if is_update { "update" } else { "create" };
     let details = format!(
-        "domain={}, target={}, scheme={}, ssl_forced={}, verify_ssl={}, upstream_sni={:?}, connection_timeout_ms={:?}, read_timeout_ms={:?}, write_timeout_ms={:?}, max_request_body_bytes={:?}, redirect_to={:?}, access_list_id={:?}",
+        "domain={}, target={}, scheme={}, ssl_forced={}, verify_ssl={}, upstream_sni={:?}, connection_timeout_ms={:?}, read_timeout_ms={:?}, write_timeout_ms={:?}, max_request_body_bytes={:?}, redirect_to={:?}, access_list_id={:?}, is_default={}, lb_method={}, lb_hash_key={:?}, health_check={:?}, sticky_session={:?}, retry_policy={:?}, cache={:?}, compression={:?}, rate_limit={:?}, forwarded_headers={:?}, rewrite_rules={:?}, maintenance={:?}, mirror={:?}, target_groups={:?}, upstream_tls={:?}, upstream_protocol={}, websocket_idle_timeout_ms={:?}, websocket_max_lifetime_ms={:?}, upstream_connection={:?}",
         payload.domain,
         payload.target,
         scheme,
@@ -133,7 +451,26 @@ pub async fn add_host(
         write_timeout_ms,
         max_request_body_bytes,
         payload.redirect_to,
-        payload.access_list_id
+        payload.access_list_id,
+        is_default,
+        lb_method.as_str(),
+        lb_hash_key,
+        health_check,
+        sticky_session,
+        retry_policy,
+        cache,
+        compression,
+        rate_limit,
+        forwarded_headers,
+        rewrite_rules,
+        maintenance,
+        mirror,
+        target_groups,
+        upstream_tls,
+        upstream_protocol.as_str(),
+        websocket_idle_timeout_ms,
+        websocket_max_lifetime_ms,
+        upstream_connection
     );
     let _ = db::insert_audit_log(
         &state.db_pool,
@@ -192,6 +529,43 @@ pub async fn add_location(
         .await?
         .ok_or_else(|| AppError::NotFound(format!("Host {} not found", domain)))?;
 
+    let match_type = payload
+        .match_type
+        .as_deref()
+        .map(parse_match_type)
+        .transpose()?
+        .unwrap_or_default();
+    match_type
+        .compile(&payload.path)
+        .map_err(|e| AppError::BadRequest(format!("Invalid location regex: {}", e)))?;
+    let (lb_method, lb_hash_key) = parse_lb_settings(
+        &payload.target,
+        payload.lb_method.as_deref(),
+        payload.lb_hash_key.as_deref(),
+    )?;
+    let lb_hash_key = lb_hash_key.map(|k| k.to_string());
+    let health_check = encode_health_check(payload.health_check.as_ref())?;
+    let sticky_session = encode_sticky_session(payload.sticky_session.as_ref())?;
+    let retry_policy = encode_retry_policy(payload.retry_policy.as_ref())?;
+    let cache = encode_cache(payload.cache.as_ref())?;
+    let compression = encode_compression(payload.compression.as_ref())?;
+    let rate_limit = encode_rate_limit(payload.rate_limit.as_ref())?;
+    let rewrite_rules = encode_rewrite_rules(payload.rewrite_rules.as_deref())?;
+    let static_files = encode_static_files(payload.static_files.as_ref())?;
+    let mirror = encode_mirror(payload.mirror.as_ref())?;
+    let target_groups = encode_target_groups(payload.target_groups.as_ref())?;
+    let upstream_tls = encode_upstream_tls(payload.upstream_tls.as_ref())?;
+    let upstream_connection = encode_upstream_connection(payload.upstream_connection.as_ref())?;
+    let upstream_protocol = encode_upstream_protocol(
+        payload.upstream_protocol.as_deref(),
+        payload.scheme.as_deref(),
+    )?;
+    if static_files.is_none() && payload.target.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "target is required unless static_files is set".to_string(),
+        ));
+    }
+
     let scheme = payload.scheme.clone().unwrap_or_else(|| "http".to_string());
     let rewrite = payload.rewrite.unwrap_or(false);
     let verify_ssl = payload.verify_ssl.unwrap_or(true);
@@ -199,12 +573,15 @@ pub async fn add_location(
     let read_timeout_ms = sanitize_optional_i64(payload.read_timeout_ms);
     let write_timeout_ms = sanitize_optional_i64(payload.write_timeout_ms);
     let max_request_body_bytes = sanitize_optional_i64(payload.max_request_body_bytes);
+    let websocket_idle_timeout_ms = sanitize_optional_i64(payload.websocket_idle_timeout_ms);
+    let websocket_max_lifetime_ms = sanitize_optional_i64(payload.websocket_max_lifetime_ms);
 
     db::upsert_location(
         &state.db_pool,
         db::UpsertLocationParams {
             host_id,
             path: &payload.path,
+            match_type: match_type.as_str(),
             target: &payload.target, // DB expects String (CSV)
             scheme: &scheme,
             rewrite,
@@ -214,13 +591,33 @@ pub async fn add_location(
             read_timeout_ms,
             write_timeout_ms,
             max_request_body_bytes,
+            lb_method: lb_method.map(|m| m.as_str()),
+            lb_hash_key: lb_hash_key.as_deref(),
+            health_check: health_check.as_deref(),
+            sticky_session: sticky_session.as_deref(),
+            retry_policy: retry_policy.as_deref(),
+            cache: cache.as_deref(),
+            compression: compression.as_deref(),
+            rate_limit: rate_limit.as_deref(),
+            access_list_id: payload.access_list_id,
+            redirect_to: payload.redirect_to.as_deref(),
+            redirect_status: payload.redirect_status,
+            rewrite_rules: rewrite_rules.as_deref(),
+            static_files: static_files.as_deref(),
+            mirror: mirror.as_deref(),
+            target_groups: target_groups.as_deref(),
+            upstream_tls: upstream_tls.as_deref(),
+            upstream_protocol: upstream_protocol.as_str(),
+            websocket_idle_timeout_ms,
+            websocket_max_lifetime_ms,
+            upstream_connection: upstream_connection.as_deref(),
         },
     )
     .await?;
 
     let details = format!(
-        "host={}, path={}, target={}, scheme={}, rewrite={}, verify_ssl={}, upstream_sni={:?}, connection_timeout_ms={:?}, read_timeout_ms={:?}, write_timeout_ms={:?}, max_request_body_bytes={:?}",
-        domain, payload.path, payload.target, scheme, rewrite, verify_ssl, payload.upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes
+        "host={}, path={}, match_type={}, target={}, scheme={}, rewrite={}, verify_ssl={}, upstream_sni={:?}, connection_timeout_ms={:?}, read_timeout_ms={:?}, write_timeout_ms={:?}, max_request_body_bytes={:?}, lb_method={:?}, lb_hash_key={:?}, health_check={:?}, sticky_session={:?}, retry_policy={:?}, cache={:?}, compression={:?}, rate_limit={:?}, access_list_id={:?}, redirect_to={:?}, redirect_status={:?}, rewrite_rules={:?}, static_files={:?}, mirror={:?}, target_groups={:?}, upstream_tls={:?}, upstream_protocol={}, websocket_idle_timeout_ms={:?}, websocket_max_lifetime_ms={:?}, upstream_connection={:?}",
+        domain, payload.path, match_type.as_str(), payload.target, scheme, rewrite, verify_ssl, payload.upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes, lb_method.map(|m| m.as_str()), lb_hash_key, health_check, sticky_session, retry_policy, cache, compression, rate_limit, payload.access_list_id, payload.redirect_to, payload.redirect_status, rewrite_rules, static_files, mirror, target_groups, upstream_tls, upstream_protocol.as_str(), websocket_idle_timeout_ms, websocket_max_lifetime_ms, upstream_connection
     );
     let _ = db::insert_audit_log(
         &state.db_pool,
@@ -252,7 +649,15 @@ pub async fn delete_location_handler(
         .await?
         .ok_or_else(|| AppError::NotFound(format!("Host {} not found", domain)))?;
 
-    db::delete_location(&state.db_pool, host_id, &q.path).await?;
+    let match_type = q.match_type.as_deref().map(parse_match_type).transpose()?;
+
+    db::delete_location(
+        &state.db_pool,
+        host_id,
+        &q.path,
+        match_type.map(|m| m.as_str()),
+    )
+    .await?;
 
     let _ = db::insert_audit_log(
         &state.db_pool,
@@ -270,6 +675,41 @@ pub async fn delete_location_handler(
     Ok(StatusCode::OK)
 }
 
+/// Turns maintenance mode on or off without rewriting the host's targets.
+pub async fn set_host_maintenance_handler(
+    claims: Claims,
+    State(state): State<ApiState>,
+    AxumPath(domain): AxumPath<String>,
+    Json(payload): Json<MaintenanceConfig>,
+) -> Result<StatusCode, AppError> {
+    if !claims.can_manage_hosts() {
+        return Err(AppError::Forbidden("Insufficient permissions".to_string()));
+    }
+
+    let maintenance = encode_maintenance(Some(&payload))?;
+    if !db::set_host_maintenance(&state.db_pool, &domain, maintenance.as_deref()).await? {
+        return Err(AppError::NotFound(format!("Host {} not found", domain)));
+    }
+
+    let _ = db::insert_audit_log(
+        &state.db_pool,
+        &claims.sub,
+        Some(claims.user_id),
+        "update",
+        "host",
+        Some(&domain),
+        Some(&format!(
+            "Set maintenance for host {}: {:?}",
+            domain, maintenance
+        )),
+        None,
+    )
+    .await;
+
+    sync_state(&state).await;
+    Ok(StatusCode::OK)
+}
+
 pub async fn list_host_headers(
     _: Claims,
     State(state): State<ApiState>,
@@ -290,6 +730,12 @@ pub async fn list_host_headers(
                 name: h.name.clone(),
                 value: h.value.clone(),
                 target: h.target.clone(),
+                operation: h.operation.as_str().to_string(),
+                location: h.location.as_ref().map(|l| l.path.clone()),
+                match_type: h
+                    .location
+                    .as_ref()
+                    .map(|l| l.match_type.as_str().to_string()),
             })
             .collect(),
     ))
@@ -309,12 +755,51 @@ pub async fn add_header_to_host(
         .await?
         .ok_or_else(|| AppError::NotFound(format!("Host {} not found", domain)))?;
 
+    let operation = payload
+        .operation
+        .as_deref()
+        .map(|raw| {
+            HeaderOperation::parse(raw).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Invalid header operation '{}': expected set, append, remove or set_if_missing",
+                    raw
+                ))
+            })
+        })
+        .transpose()?
+        .unwrap_or_default();
+    if HeaderName::from_bytes(payload.name.as_bytes()).is_err() {
+        return Err(AppError::BadRequest(format!(
+            "Invalid header name '{}'",
+            payload.name
+        )));
+    }
+    if operation != HeaderOperation::Remove {
+        header_vars::validate(&payload.value).map_err(AppError::BadRequest)?;
+    }
+    let location_match_type = match &payload.location {
+        Some(_) => Some(
+            payload
+                .match_type
+                .as_deref()
+                .map(parse_match_type)
+                .transpose()?
+                .unwrap_or_default(),
+        ),
+        None => None,
+    };
+
     db::add_header(
         &state.db_pool,
-        host_id,
-        &payload.name,
-        &payload.value,
-        &payload.target,
+        db::AddHeaderParams {
+            host_id,
+            name: &payload.name,
+            value: &payload.value,
+            target: &payload.target,
+            operation: operation.as_str(),
+            location_path: payload.location.as_deref(),
+            location_match_type: location_match_type.map(|m| m.as_str()),
+        },
     )
     .await?;
 
@@ -326,8 +811,13 @@ pub async fn add_header_to_host(
         "header",
         Some(&format!("{}:{}", domain, payload.name)),
         Some(&format!(
-            "Added header '{}: {}' to host {}",
-            payload.name, payload.value, domain
+            "Added {} header rule '{}: {}' to host {} (location={:?}, match_type={:?})",
+            operation.as_str(),
+            payload.name,
+            payload.value,
+            domain,
+            payload.location,
+            location_match_type.map(|m| m.as_str())
         )),
         None,
     )
@@ -366,3 +856,103 @@ pub async fn delete_host_header(
     sync_state(&state).await;
     Ok(StatusCode::OK)
 }
+
+/// Lists the probe status of every target in pools with an active health check.
+pub async fn list_upstream_health(
+    _: Claims,
+    State(state): State<ApiState>,
+) -> Result<Json<Vec<UpstreamHealthRes>>, AppError> {
+    let config = state.app_state.config.load();
+    let health = &state.app_state.health;
+
+    let pool_status = |domain: &str, host: &HostConfig, loc: Option<&LocationConfig>| {
+        target_groups::pools(host, loc)
+            .into_iter()
+            .flat_map(|(group, pool_key, targets)| {
+                targets
+                    .iter()
+                    .map(move |target| (group, pool_key.clone(), target))
+            })
+            .map(|(group, pool_key, target)| {
+                let status = health.status(&pool_key, &target.addr).unwrap_or_default();
+                UpstreamHealthRes {
+                    domain: domain.to_string(),
+                    location: loc.map(|loc| loc.path.clone()),
+                    match_type: loc.map(|loc| loc.match_type.as_str().to_string()),
+                    target_group: group.map(str::to_string),
+                    target: target.addr.clone(),
+                    healthy: status.healthy,
+                    consecutive_successes: status.consecutive_successes,
+                    consecutive_failures: status.consecutive_failures,
+                    last_checked: status.last_checked,
+                    last_error: status.last_error,
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut res = Vec::new();
+    for (domain, host) in config.hosts.iter() {
+        if host.health_check.is_some() {
+            res.extend(pool_status(domain, host, None));
+        }
+        for loc in host
+            .locations
+            .iter()
+            .filter(|loc| loc.health_check.is_some())
+        {
+            res.extend(pool_status(domain, host, Some(loc)));
+        }
+    }
+    res.sort_by(|a, b| {
+        (&a.domain, &a.location, &a.target).cmp(&(&b.domain, &b.location, &b.target))
+    });
+
+    Ok(Json(res))
+}
+
+/// Removes cached responses for a host, a path prefix of a host, or one key.
+pub async fn purge_cache(
+    claims: Claims,
+    State(state): State<ApiState>,
+    Json(payload): Json<CachePurgeReq>,
+) -> Result<Json<CachePurgeRes>, AppError> {
+    if !claims.can_manage_hosts() {
+        return Err(AppError::Forbidden("Insufficient permissions".to_string()));
+    }
+
+    let target = match (
+        payload.host.as_deref(),
+        payload.path_prefix.as_deref(),
+        payload.key.as_deref(),
+    ) {
+        (Some(host), None, None) => cache::PurgeTarget::Host(host),
+        (Some(host), Some(prefix), None) if prefix.starts_with('/') => {
+            cache::PurgeTarget::PathPrefix { host, prefix }
+        }
+        (None, None, Some(key)) => cache::PurgeTarget::Key(key),
+        _ => {
+            return Err(AppError::BadRequest(
+                "Specify host, host with a path_prefix starting with '/', or key".to_string(),
+            ))
+        }
+    };
+    let purged = cache::purge(target).await;
+
+    let _ = db::insert_audit_log(
+        &state.db_pool,
+        &claims.sub,
+        Some(claims.user_id),
+        "purge",
+        "cache",
+        payload.host.as_deref().or(payload.key.as_deref()),
+        Some(&format!(
+            "Purged {} cached responses ({:?})",
+            purged, target
+        )),
+        None,
+    )
+    .await;
+
+    Ok(Json(CachePurgeRes { purged }))
+}
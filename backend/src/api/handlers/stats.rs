@@ -1,6 +1,8 @@
 use crate::api::{
+    sync_state,
     types::{
-        AuditLogQuery, AuditLogRes, ErrorPageReq, HistoryStatsQuery, LogsQuery, RealtimeStatsRes,
+        AuditLogQuery, AuditLogRes, ErrorPageOverrideReq, ErrorPageOverrideRes, ErrorPageReq,
+        HistoryStatsQuery, LogsQuery, RealtimeStatsRes,
     },
     ApiState,
 };
@@ -8,7 +10,7 @@ use crate::auth::Claims;
 use crate::db::{self, TrafficStatRow};
 use crate::error::AppError;
 use axum::{
-    extract::{Json, Query, State},
+    extract::{Json, Path as AxumPath, Query, State},
     http::StatusCode,
 };
 use std::fs;
@@ -101,6 +103,118 @@ pub async fn update_error_page(
     Ok(StatusCode::OK)
 }
 
+pub async fn list_error_page_overrides(
+    _: Claims,
+    State(state): State<ApiState>,
+) -> Result<Json<Vec<ErrorPageOverrideRes>>, AppError> {
+    let rows = db::get_all_error_pages(&state.db_pool).await?;
+    Ok(Json(
+        rows.into_iter()
+            .map(|r| ErrorPageOverrideRes {
+                id: r.id,
+                domain: r.domain,
+                status_code: r.status_code,
+                html: r.html,
+            })
+            .collect(),
+    ))
+}
+
+pub async fn upsert_error_page_override(
+    claims: Claims,
+    State(state): State<ApiState>,
+    Json(payload): Json<ErrorPageOverrideReq>,
+) -> Result<StatusCode, AppError> {
+    if !claims.is_admin() {
+        return Err(AppError::Forbidden(
+            "Only admins can update error page".to_string(),
+        ));
+    }
+
+    if payload.domain.is_none() && payload.status_code.is_none() {
+        return Err(AppError::BadRequest(
+            "Either domain or status_code is required; use /settings/error-page for the global template".to_string(),
+        ));
+    }
+    if let Some(code) = payload.status_code {
+        if !(400..=599).contains(&code) {
+            return Err(AppError::BadRequest(format!(
+                "status_code must be between 400 and 599, got {}",
+                code
+            )));
+        }
+    }
+
+    let host_id = match &payload.domain {
+        Some(domain) => Some(
+            db::get_host_id(&state.db_pool, domain)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("Host {} not found", domain)))?,
+        ),
+        None => None,
+    };
+
+    let id = db::upsert_error_page(
+        &state.db_pool,
+        host_id,
+        payload.status_code.map(i64::from),
+        &payload.html,
+    )
+    .await?;
+
+    let _ = db::insert_audit_log(
+        &state.db_pool,
+        &claims.sub,
+        Some(claims.user_id),
+        "update",
+        "settings",
+        Some(&format!("error_page:{}", id)),
+        Some(&format!(
+            "Updated error page override (domain={:?}, status_code={:?})",
+            payload.domain, payload.status_code
+        )),
+        None,
+    )
+    .await;
+
+    sync_state(&state).await;
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn delete_error_page_override(
+    claims: Claims,
+    State(state): State<ApiState>,
+    AxumPath(id): AxumPath<i64>,
+) -> Result<StatusCode, AppError> {
+    if !claims.is_admin() {
+        return Err(AppError::Forbidden(
+            "Only admins can update error page".to_string(),
+        ));
+    }
+
+    if !db::delete_error_page(&state.db_pool, id).await? {
+        return Err(AppError::NotFound(format!(
+            "Error page override {} not found",
+            id
+        )));
+    }
+
+    let _ = db::insert_audit_log(
+        &state.db_pool,
+        &claims.sub,
+        Some(claims.user_id),
+        "delete",
+        "settings",
+        Some(&format!("error_page:{}", id)),
+        Some(&format!("Deleted error page override ID {}", id)),
+        None,
+    )
+    .await;
+
+    sync_state(&state).await;
+    Ok(StatusCode::OK)
+}
+
 // --- Audit Log Handlers ---
 
 pub async fn get_audit_logs_handler(
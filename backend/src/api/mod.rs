@@ -56,6 +56,10 @@ pub fn router(
             "/hosts/{domain}/locations",
             post(add_location).delete(delete_location_handler),
         )
+        .route(
+            "/hosts/{domain}/maintenance",
+            put(set_host_maintenance_handler),
+        )
         .route(
             "/hosts/{domain}/headers",
             get(list_host_headers).post(add_header_to_host),
@@ -64,9 +68,12 @@ pub fn router(
             "/hosts/{domain}/headers/{header_id}",
             delete(delete_host_header),
         )
+        .route("/upstreams/health", get(list_upstream_health))
+        .route("/cache/purge", post(purge_cache))
         // Certs
         .route("/certs", get(list_certs).post(request_cert))
         .route("/certs/upload", post(upload_cert))
+        .route("/certs/upstream", post(upload_upstream_cert))
         // Stats & Logs
         .route("/stats/realtime", get(get_realtime_stats))
         .route("/stats/history", get(get_history_stats))
@@ -79,6 +86,14 @@ pub fn router(
             "/settings/error-page",
             get(get_error_page).post(update_error_page),
         )
+        .route(
+            "/settings/error-pages",
+            get(list_error_page_overrides).post(upsert_error_page_override),
+        )
+        .route(
+            "/settings/error-pages/{id}",
+            delete(delete_error_page_override),
+        )
         // Access Lists
         .route(
             "/access-lists",
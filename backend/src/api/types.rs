@@ -1,3 +1,8 @@
+use crate::state::{
+    CacheConfig, CompressionConfig, ForwardedHeadersConfig, HealthCheckConfig, MaintenanceConfig,
+    MirrorConfig, RateLimitConfig, RetryPolicy, RewriteRule, StaticFilesConfig,
+    StickySessionConfig, TargetGroupsConfig, UpstreamConnectionConfig, UpstreamTlsConfig,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -26,11 +31,33 @@ pub struct CreateHostReq {
     pub redirect_to: Option<String>,
     pub redirect_status: Option<i64>,
     pub access_list_id: Option<i64>,
+    pub is_default: Option<bool>,
+    pub lb_method: Option<String>,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub sticky_session: Option<StickySessionConfig>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub cache: Option<CacheConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub forwarded_headers: Option<ForwardedHeadersConfig>,
+    pub rewrite_rules: Option<Vec<RewriteRule>>,
+    pub maintenance: Option<MaintenanceConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    pub upstream_protocol: Option<String>,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
 }
 
 #[derive(Deserialize)]
 pub struct CreateLocationReq {
     pub path: String,
+    pub match_type: Option<String>,
+    /// May be empty when `static_files` is set.
+    #[serde(default)]
     pub target: String,
     pub scheme: Option<String>,
     pub rewrite: Option<bool>,
@@ -40,16 +67,40 @@ pub struct CreateLocationReq {
     pub read_timeout_ms: Option<i64>,
     pub write_timeout_ms: Option<i64>,
     pub max_request_body_bytes: Option<i64>,
+    pub lb_method: Option<String>,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub sticky_session: Option<StickySessionConfig>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub cache: Option<CacheConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Replaces the host's access list for this location.
+    pub access_list_id: Option<i64>,
+    /// Replaces the host's redirect for this location.
+    pub redirect_to: Option<String>,
+    pub redirect_status: Option<i64>,
+    pub rewrite_rules: Option<Vec<RewriteRule>>,
+    pub static_files: Option<StaticFilesConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    pub upstream_protocol: Option<String>,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
 }
 
 #[derive(Deserialize)]
 pub struct DeleteLocationQuery {
     pub path: String,
+    pub match_type: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct LocationRes {
     pub path: String,
+    pub match_type: String,
     pub target: String,
     pub scheme: String,
     pub rewrite: bool,
@@ -59,6 +110,26 @@ pub struct LocationRes {
     pub read_timeout_ms: Option<i64>,
     pub write_timeout_ms: Option<i64>,
     pub max_request_body_bytes: Option<i64>,
+    pub lb_method: Option<String>,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub sticky_session: Option<StickySessionConfig>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub cache: Option<CacheConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub access_list_id: Option<i64>,
+    pub redirect_to: Option<String>,
+    pub redirect_status: Option<u16>,
+    pub rewrite_rules: Vec<RewriteRule>,
+    pub static_files: Option<StaticFilesConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    pub upstream_protocol: String,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
 }
 
 #[derive(Serialize)]
@@ -78,6 +149,55 @@ pub struct HostRes {
     pub locations: Vec<LocationRes>,
     pub access_list_id: Option<i64>,
     pub headers: Vec<HeaderRes>,
+    pub is_default: bool,
+    pub lb_method: String,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<HealthCheckConfig>,
+    pub sticky_session: Option<StickySessionConfig>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub cache: Option<CacheConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub forwarded_headers: Option<ForwardedHeadersConfig>,
+    pub rewrite_rules: Vec<RewriteRule>,
+    pub maintenance: Option<MaintenanceConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    pub upstream_protocol: String,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
+}
+
+#[derive(Serialize)]
+pub struct UpstreamHealthRes {
+    pub domain: String,
+    /// `None` for the host's own targets.
+    pub location: Option<String>,
+    pub match_type: Option<String>,
+    /// `None` for the regular targets, otherwise the target group's name.
+    pub target_group: Option<String>,
+    pub target: String,
+    pub healthy: bool,
+    pub consecutive_successes: u32,
+    pub consecutive_failures: u32,
+    pub last_checked: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+/// Exactly one form: `host`, `host` + `path_prefix`, or `key`
+/// (`host/path?query` as shown in the cache key).
+#[derive(Deserialize)]
+pub struct CachePurgeReq {
+    pub host: Option<String>,
+    pub path_prefix: Option<String>,
+    pub key: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CachePurgeRes {
+    pub purged: usize,
 }
 
 #[derive(Deserialize)]
@@ -135,6 +255,21 @@ pub struct ErrorPageReq {
     pub html: String,
 }
 
+#[derive(Deserialize)]
+pub struct ErrorPageOverrideReq {
+    pub domain: Option<String>,
+    pub status_code: Option<u16>,
+    pub html: String,
+}
+
+#[derive(Serialize)]
+pub struct ErrorPageOverrideRes {
+    pub id: i64,
+    pub domain: Option<String>,
+    pub status_code: Option<i64>,
+    pub html: String,
+}
+
 // --- Access List Structs ---
 
 #[derive(Deserialize)]
@@ -251,8 +386,16 @@ pub struct DnsProviderRes {
 #[derive(Deserialize)]
 pub struct CreateHeaderReq {
     pub name: String,
+    /// Not needed for `remove`.
+    #[serde(default)]
     pub value: String,
     pub target: String,
+    /// `set` (default), `append`, `remove` or `set_if_missing`.
+    pub operation: Option<String>,
+    /// Path of the location to scope the rule to; the whole host when unset.
+    pub location: Option<String>,
+    /// Match type of that location, `prefix` by default.
+    pub match_type: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -261,4 +404,7 @@ pub struct HeaderRes {
     pub name: String,
     pub value: String,
     pub target: String,
+    pub operation: String,
+    pub location: Option<String>,
+    pub match_type: Option<String>,
 }
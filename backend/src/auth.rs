@@ -14,11 +14,14 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use std::env;
 
+// 환경 변수에 설정된 JWT Secret (없으면 None)
+pub fn configured_jwt_secret() -> Option<Vec<u8>> {
+    env::var("JWT_SECRET").ok().map(String::into_bytes)
+}
+
 // 환경 변수에서 JWT Secret 가져오기
 fn get_jwt_secret() -> Vec<u8> {
-    env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "super_secret_key_change_me_in_production".to_string())
-        .into_bytes()
+    configured_jwt_secret().unwrap_or_else(|| b"super_secret_key_change_me_in_production".to_vec())
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -1,14 +1,122 @@
+use crate::constants;
 use crate::db::{self, DbPool};
 use crate::state::{
-    AccessListClientConfig, AccessListConfig, AccessListIpConfig, HeaderConfig, HostConfig,
-    LocationConfig, ProxyConfig,
+    AccessListClientConfig, AccessListConfig, AccessListIpConfig, HeaderConfig, HeaderLocation,
+    HeaderOperation, HostConfig, HostIndex, LbHashKey, LbMethod, LocationConfig, LocationMatchType,
+    ProxyConfig, RewriteRule, UpstreamProtocol, UpstreamTarget, UpstreamTlsConfig,
 };
+use regex::Regex;
 use std::collections::HashMap;
+use std::path::Path;
 
 fn to_u64_opt(value: Option<i64>) -> Option<u64> {
     value.and_then(|v| u64::try_from(v).ok())
 }
 
+/// Decodes an optional JSON settings column, ignoring malformed values.
+fn parse_json_setting<T: serde::de::DeserializeOwned>(
+    raw: Option<&str>,
+    field: &str,
+    owner: &str,
+) -> Option<T> {
+    let raw = raw?;
+    match serde_json::from_str::<T>(raw) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid {} for {}: {}", field, owner, e);
+            None
+        }
+    }
+}
+
+/// Parses a comma-separated target list, skipping invalid entries. The API
+/// rejects them, so they only come from older versions or manual edits.
+fn parse_targets(raw: &str, owner: &str) -> Vec<UpstreamTarget> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .filter_map(|entry| match UpstreamTarget::parse(entry) {
+            Ok(target) => Some(target),
+            Err(e) => {
+                tracing::error!("Skipping invalid target for {}: {}", owner, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parses rewrite rules and compiles their patterns, skipping invalid ones.
+fn parse_rewrite_rules(raw: Option<&str>, owner: &str) -> Vec<RewriteRule> {
+    let rules: Vec<RewriteRule> =
+        parse_json_setting(raw, "rewrite_rules", owner).unwrap_or_default();
+    rules
+        .into_iter()
+        .filter_map(|mut rule| match Regex::new(&rule.pattern) {
+            Ok(regex) => {
+                rule.regex = Some(regex);
+                Some(rule)
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Skipping rewrite rule with invalid pattern '{}' for {}: {}",
+                    rule.pattern,
+                    owner,
+                    e
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parses upstream TLS settings and loads the certificates they name. A
+/// setting whose files cannot be loaded is dropped, so the upstream is
+/// contacted without a client certificate rather than with a stale one.
+fn parse_upstream_tls(raw: Option<&str>, owner: &str) -> Option<UpstreamTlsConfig> {
+    let mut config: UpstreamTlsConfig = parse_json_setting(raw, "upstream_tls", owner)?;
+    match config.load_material(Path::new(constants::cert::UPSTREAM_DIR)) {
+        Ok(material) => {
+            config.material = material;
+            Some(config)
+        }
+        Err(e) => {
+            tracing::error!("Ignoring upstream_tls for {}: {}", owner, e);
+            None
+        }
+    }
+}
+
+fn parse_lb_method(raw: &str, owner: &str) -> LbMethod {
+    LbMethod::parse(raw).unwrap_or_else(|| {
+        tracing::warn!(
+            "Unknown lb_method '{}' for {}, falling back to random",
+            raw,
+            owner
+        );
+        LbMethod::Random
+    })
+}
+
+fn parse_upstream_protocol(raw: &str, owner: &str) -> UpstreamProtocol {
+    UpstreamProtocol::parse(raw).unwrap_or_else(|| {
+        tracing::warn!(
+            "Unknown upstream_protocol '{}' for {}, falling back to http1",
+            raw,
+            owner
+        );
+        UpstreamProtocol::Http1
+    })
+}
+
+fn parse_lb_hash_key(raw: Option<&str>, owner: &str) -> Option<LbHashKey> {
+    let raw = raw?;
+    let key = LbHashKey::parse(raw);
+    if key.is_none() {
+        tracing::warn!("Ignoring invalid lb_hash_key '{}' for {}", raw, owner);
+    }
+    key
+}
+
 pub struct ConfigLoader;
 
 impl ConfigLoader {
@@ -19,6 +127,7 @@ impl ConfigLoader {
         let clients_result = db::get_access_list_clients(pool).await;
         let ips_result = db::get_access_list_ips(pool).await;
         let headers_result = db::get_all_headers(pool).await;
+        let error_pages_result = db::get_all_error_pages(pool).await;
 
         if let (
             Ok(rows),
@@ -27,6 +136,7 @@ impl ConfigLoader {
             Ok(client_rows),
             Ok(ip_rows),
             Ok(header_rows),
+            Ok(error_page_rows),
         ) = (
             hosts_result,
             locations_result,
@@ -34,24 +144,75 @@ impl ConfigLoader {
             clients_result,
             ips_result,
             headers_result,
+            error_pages_result,
         ) {
             // 1. Locations
             let mut locations_map: HashMap<i64, Vec<LocationConfig>> = HashMap::new();
             for loc in loc_rows {
                 // Split comma-separated targets
-                let targets: Vec<String> = loc
-                    .target
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+                let targets = parse_targets(&loc.target, &loc.path);
+
+                let match_type = LocationMatchType::parse(&loc.match_type).unwrap_or_else(|| {
+                    tracing::warn!(
+                        "Unknown match_type '{}' for location {}, falling back to prefix",
+                        loc.match_type,
+                        loc.path
+                    );
+                    LocationMatchType::Prefix
+                });
+                // Regexes are compiled once here so the data plane never has to
+                let regex = match match_type.compile(&loc.path) {
+                    Ok(regex) => regex,
+                    Err(e) => {
+                        tracing::error!(
+                            "Skipping location with invalid regex '{}': {}",
+                            loc.path,
+                            e
+                        );
+                        continue;
+                    }
+                };
+
+                let lb_method = loc
+                    .lb_method
+                    .as_deref()
+                    .map(|m| parse_lb_method(m, &loc.path));
+                let lb_hash_key = parse_lb_hash_key(loc.lb_hash_key.as_deref(), &loc.path);
+                let health_check =
+                    parse_json_setting(loc.health_check.as_deref(), "health_check", &loc.path);
+                let sticky_session =
+                    parse_json_setting(loc.sticky_session.as_deref(), "sticky_session", &loc.path);
+                let retry_policy =
+                    parse_json_setting(loc.retry_policy.as_deref(), "retry_policy", &loc.path);
+                let cache = parse_json_setting(loc.cache.as_deref(), "cache", &loc.path);
+                let compression =
+                    parse_json_setting(loc.compression.as_deref(), "compression", &loc.path);
+                let rate_limit =
+                    parse_json_setting(loc.rate_limit.as_deref(), "rate_limit", &loc.path);
+                let rewrite_rules = parse_rewrite_rules(loc.rewrite_rules.as_deref(), &loc.path);
+                let static_files =
+                    parse_json_setting(loc.static_files.as_deref(), "static_files", &loc.path);
+                let mirror = parse_json_setting(loc.mirror.as_deref(), "mirror", &loc.path);
+                let target_groups =
+                    parse_json_setting(loc.target_groups.as_deref(), "target_groups", &loc.path);
+                let upstream_tls = parse_upstream_tls(loc.upstream_tls.as_deref(), &loc.path);
+                let upstream_protocol = parse_upstream_protocol(&loc.upstream_protocol, &loc.path);
+                let upstream_connection = parse_json_setting(
+                    loc.upstream_connection.as_deref(),
+                    "upstream_connection",
+                    &loc.path,
+                );
 
                 locations_map
                     .entry(loc.host_id)
                     .or_default()
                     .push(LocationConfig {
                         path: loc.path,
+                        match_type,
+                        regex,
                         targets, // Use Vector
+                        lb_method,
+                        lb_hash_key,
                         scheme: loc.scheme,
                         rewrite: loc.rewrite,
                         verify_ssl: loc.verify_ssl,
@@ -60,6 +221,26 @@ impl ConfigLoader {
                         read_timeout_ms: to_u64_opt(loc.read_timeout_ms),
                         write_timeout_ms: to_u64_opt(loc.write_timeout_ms),
                         max_request_body_bytes: to_u64_opt(loc.max_request_body_bytes),
+                        health_check,
+                        sticky_session,
+                        retry_policy,
+                        cache,
+                        compression,
+                        rate_limit,
+                        access_list_id: loc.access_list_id,
+                        redirect_to: loc.redirect_to,
+                        redirect_status: loc
+                            .redirect_status
+                            .and_then(|status| u16::try_from(status).ok()),
+                        rewrite_rules,
+                        static_files,
+                        mirror,
+                        target_groups,
+                        upstream_tls,
+                        upstream_protocol,
+                        websocket_idle_timeout_ms: to_u64_opt(loc.websocket_idle_timeout_ms),
+                        websocket_max_lifetime_ms: to_u64_opt(loc.websocket_max_lifetime_ms),
+                        upstream_connection,
                     });
             }
 
@@ -104,6 +285,22 @@ impl ConfigLoader {
             // 3. Headers (grouped by host_id for ProxyConfig)
             let mut headers_map: HashMap<i64, Vec<HeaderConfig>> = HashMap::new();
             for h in header_rows {
+                let operation = HeaderOperation::parse(&h.operation).unwrap_or_else(|| {
+                    tracing::warn!(
+                        "Unknown operation '{}' for header {}, falling back to set",
+                        h.operation,
+                        h.name
+                    );
+                    HeaderOperation::Set
+                });
+                let location = h.location_path.map(|path| {
+                    let match_type = h
+                        .location_match_type
+                        .as_deref()
+                        .and_then(LocationMatchType::parse)
+                        .unwrap_or_default();
+                    HeaderLocation { path, match_type }
+                });
                 headers_map
                     .entry(h.host_id)
                     .or_default()
@@ -112,27 +309,71 @@ impl ConfigLoader {
                         name: h.name,
                         value: h.value,
                         target: h.target,
+                        operation,
+                        location,
                     });
             }
 
+            // 4. Error page overrides
+            let error_pages = error_page_rows
+                .into_iter()
+                .map(|e| {
+                    let status = e.status_code.and_then(|c| u16::try_from(c).ok());
+                    ((e.host_id, status), e.html)
+                })
+                .collect();
+
             let mut hosts = HashMap::new();
             for row in rows {
                 let locs = locations_map.remove(&row.id).unwrap_or_default();
                 let host_headers = headers_map.get(&row.id).cloned().unwrap_or_default();
 
                 // Split comma-separated targets
-                let targets: Vec<String> = row
-                    .target
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+                let targets = parse_targets(&row.target, &row.domain);
+
+                let lb_method = parse_lb_method(&row.lb_method, &row.domain);
+                let lb_hash_key = parse_lb_hash_key(row.lb_hash_key.as_deref(), &row.domain);
+                let health_check =
+                    parse_json_setting(row.health_check.as_deref(), "health_check", &row.domain);
+                let sticky_session = parse_json_setting(
+                    row.sticky_session.as_deref(),
+                    "sticky_session",
+                    &row.domain,
+                );
+                let retry_policy =
+                    parse_json_setting(row.retry_policy.as_deref(), "retry_policy", &row.domain);
+                let cache = parse_json_setting(row.cache.as_deref(), "cache", &row.domain);
+                let compression =
+                    parse_json_setting(row.compression.as_deref(), "compression", &row.domain);
+                let rate_limit =
+                    parse_json_setting(row.rate_limit.as_deref(), "rate_limit", &row.domain);
+                let forwarded_headers = parse_json_setting(
+                    row.forwarded_headers.as_deref(),
+                    "forwarded_headers",
+                    &row.domain,
+                );
+                let rewrite_rules = parse_rewrite_rules(row.rewrite_rules.as_deref(), &row.domain);
+                let maintenance =
+                    parse_json_setting(row.maintenance.as_deref(), "maintenance", &row.domain);
+                let mirror = parse_json_setting(row.mirror.as_deref(), "mirror", &row.domain);
+                let target_groups =
+                    parse_json_setting(row.target_groups.as_deref(), "target_groups", &row.domain);
+                let upstream_tls = parse_upstream_tls(row.upstream_tls.as_deref(), &row.domain);
+                let upstream_protocol =
+                    parse_upstream_protocol(&row.upstream_protocol, &row.domain);
+                let upstream_connection = parse_json_setting(
+                    row.upstream_connection.as_deref(),
+                    "upstream_connection",
+                    &row.domain,
+                );
 
                 hosts.insert(
                     row.domain,
                     HostConfig {
                         id: row.id,
                         targets, // Use Vector
+                        lb_method,
+                        lb_hash_key,
                         scheme: row.scheme,
                         locations: locs,
                         ssl_forced: row.ssl_forced,
@@ -146,13 +387,33 @@ impl ConfigLoader {
                         redirect_status: row.redirect_status as u16,
                         access_list_id: row.access_list_id,
                         headers: host_headers,
+                        is_default: row.is_default,
+                        health_check,
+                        sticky_session,
+                        retry_policy,
+                        cache,
+                        compression,
+                        rate_limit,
+                        forwarded_headers,
+                        rewrite_rules,
+                        maintenance,
+                        mirror,
+                        target_groups,
+                        upstream_tls,
+                        upstream_protocol,
+                        websocket_idle_timeout_ms: to_u64_opt(row.websocket_idle_timeout_ms),
+                        websocket_max_lifetime_ms: to_u64_opt(row.websocket_max_lifetime_ms),
+                        upstream_connection,
                     },
                 );
             }
+            let host_index = HostIndex::build(&hosts);
             Ok(ProxyConfig {
                 hosts,
+                host_index,
                 access_lists,
                 headers: headers_map,
+                error_pages,
             })
         } else {
             Err("Failed to load initial configuration from DB".into())
@@ -170,6 +431,12 @@ mod tests {
     use std::str::FromStr;
     use tempfile::tempdir;
 
+    #[test]
+    fn invalid_targets_are_skipped_without_dropping_the_rest() {
+        let targets = parse_targets("10.0.0.1:80, 10.0.0.2:80 weight=0, unix:relative.sock", "h");
+        assert_eq!(targets, vec![UpstreamTarget::new("10.0.0.1:80")]);
+    }
+
     #[tokio::test]
     async fn init_db_migrates_legacy_schema_and_preserves_nullable_advanced_config() {
         let temp_dir = tempdir().expect("create temp dir");
@@ -252,7 +519,8 @@ mod tests {
             .hosts
             .get("legacy.local")
             .expect("legacy host should exist");
-        assert_eq!(host.targets, vec!["127.0.0.1:8080"]);
+        assert_eq!(host.targets, vec![UpstreamTarget::new("127.0.0.1:8080")]);
+        assert_eq!(host.lb_method, LbMethod::Random);
         assert_eq!(host.connection_timeout_ms, None);
         assert_eq!(host.read_timeout_ms, None);
         assert_eq!(host.write_timeout_ms, None);
@@ -267,7 +535,12 @@ mod tests {
             .iter()
             .find(|location| location.path == "/api")
             .expect("legacy location should exist");
-        assert_eq!(location.targets, vec!["127.0.0.1:8081"]);
+        assert_eq!(
+            location.targets,
+            vec![UpstreamTarget::new("127.0.0.1:8081")]
+        );
+        assert_eq!(location.lb_method, None);
+        assert_eq!(location.match_type, LocationMatchType::Prefix);
         assert_eq!(location.connection_timeout_ms, None);
         assert_eq!(location.read_timeout_ms, None);
         assert_eq!(location.write_timeout_ms, None);
@@ -291,6 +564,8 @@ mod tests {
             "read_timeout_ms",
             "write_timeout_ms",
             "max_request_body_bytes",
+            "websocket_idle_timeout_ms",
+            "websocket_max_lifetime_ms",
         ] {
             assert!(
                 host_columns
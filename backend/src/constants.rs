@@ -9,6 +9,15 @@ pub mod http {
     pub const UNAUTHORIZED: u16 = StatusCode::UNAUTHORIZED.as_u16();
     pub const CREATED: u16 = StatusCode::CREATED.as_u16();
     pub const PAYLOAD_TOO_LARGE: u16 = StatusCode::PAYLOAD_TOO_LARGE.as_u16();
+    pub const TOO_MANY_REQUESTS: u16 = StatusCode::TOO_MANY_REQUESTS.as_u16();
+    pub const BAD_GATEWAY: u16 = StatusCode::BAD_GATEWAY.as_u16();
+    pub const SERVICE_UNAVAILABLE: u16 = StatusCode::SERVICE_UNAVAILABLE.as_u16();
+    pub const MOVED_PERMANENTLY: u16 = StatusCode::MOVED_PERMANENTLY.as_u16();
+    pub const FOUND: u16 = StatusCode::FOUND.as_u16();
+    pub const PARTIAL_CONTENT: u16 = StatusCode::PARTIAL_CONTENT.as_u16();
+    pub const NOT_MODIFIED: u16 = StatusCode::NOT_MODIFIED.as_u16();
+    pub const METHOD_NOT_ALLOWED: u16 = StatusCode::METHOD_NOT_ALLOWED.as_u16();
+    pub const RANGE_NOT_SATISFIABLE: u16 = StatusCode::RANGE_NOT_SATISFIABLE.as_u16();
 }
 
 /// Network Configuration
@@ -24,6 +33,8 @@ pub mod cert {
     pub const RSA_BITS: u32 = 2048;
     pub const VALIDITY_DAYS: u32 = 3650; // 10 years
     pub const RENEWAL_THRESHOLD_DAYS: u64 = 30;
+    /// Uploaded client certificates and CA bundles for upstream TLS
+    pub const UPSTREAM_DIR: &str = "data/certs/upstream";
 }
 
 /// Timeouts (milliseconds/seconds)
@@ -34,3 +45,38 @@ pub mod timeout {
     pub const TCP_TIMEOUT_SECS: u64 = 300;
     pub const UDP_SESSION_TIMEOUT_SECS: u64 = 60;
 }
+
+/// Passive outlier detection (circuit breaking per upstream target)
+pub mod outlier {
+    pub const CONSECUTIVE_FAILURES: u32 = 5;
+    pub const BASE_EJECTION_SECS: u64 = 10;
+    pub const MAX_EJECTION_SECS: u64 = 300;
+}
+
+/// Response cache storage (sizes are overridable via environment variables)
+pub mod cache {
+    pub const MEMORY_BYTES: usize = 128 * 1024 * 1024;
+    pub const DIR: &str = "data/cache";
+    pub const DEFAULT_MAX_OBJECT_BYTES: u64 = 10 * 1024 * 1024;
+}
+
+pub mod compression {
+    pub const MIN_SIZE_BYTES: u64 = 1024;
+    pub const LEVEL: u32 = 6;
+    pub const MIME_TYPES: &[&str] = &[
+        "text/*",
+        "application/json",
+        "application/javascript",
+        "application/xml",
+        "application/xhtml+xml",
+        "application/rss+xml",
+        "application/wasm",
+        "image/svg+xml",
+    ];
+}
+
+/// Per-client rate limiting
+pub mod rate_limit {
+    /// Buckets kept before idle (full) ones are pruned.
+    pub const MAX_TRACKED_CLIENTS: usize = 100_000;
+}
@@ -0,0 +1,78 @@
+use super::DbPool;
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct ErrorPageRow {
+    pub id: i64,
+    pub host_id: Option<i64>,
+    pub domain: Option<String>,
+    pub status_code: Option<i64>,
+    pub html: String,
+}
+
+/// Retrieves all error page overrides together with the domain of their host.
+///
+/// # Arguments
+/// * `pool` - Database connection pool
+///
+/// # Returns
+/// * `Result<Vec<ErrorPageRow>, sqlx::Error>` - A list of all error page overrides or a database error
+pub async fn get_all_error_pages(pool: &DbPool) -> Result<Vec<ErrorPageRow>, sqlx::Error> {
+    sqlx::query_as::<_, ErrorPageRow>(
+        r#"
+        SELECT e.id, e.host_id, h.domain, e.status_code, e.html
+        FROM error_pages e
+        LEFT JOIN hosts h ON h.id = e.host_id
+        ORDER BY e.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Inserts or replaces an error page override.
+///
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `host_id` - Optional host the page applies to; `None` applies to every host
+/// * `status_code` - Optional status code the page applies to; `None` applies to every status
+/// * `html` - Template HTML
+///
+/// # Returns
+/// * `Result<i64, sqlx::Error>` - The ID of the stored override or a database error
+pub async fn upsert_error_page(
+    pool: &DbPool,
+    host_id: Option<i64>,
+    status_code: Option<i64>,
+    html: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query("DELETE FROM error_pages WHERE host_id IS ? AND status_code IS ?")
+        .bind(host_id)
+        .bind(status_code)
+        .execute(pool)
+        .await?;
+
+    let id = sqlx::query("INSERT INTO error_pages (host_id, status_code, html) VALUES (?, ?, ?)")
+        .bind(host_id)
+        .bind(status_code)
+        .bind(html)
+        .execute(pool)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+/// Deletes an error page override.
+///
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `id` - ID of the override to delete
+///
+/// # Returns
+/// * `Result<bool, sqlx::Error>` - Whether the override existed, or a database error
+pub async fn delete_error_page(pool: &DbPool, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM error_pages WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
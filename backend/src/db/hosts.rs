@@ -16,6 +16,25 @@ pub struct HostRow {
     pub redirect_status: i64,
     pub access_list_id: Option<i64>,
     pub upstream_sni: Option<String>,
+    pub is_default: bool,
+    pub lb_method: String,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<String>,
+    pub sticky_session: Option<String>,
+    pub retry_policy: Option<String>,
+    pub cache: Option<String>,
+    pub compression: Option<String>,
+    pub rate_limit: Option<String>,
+    pub forwarded_headers: Option<String>,
+    pub rewrite_rules: Option<String>,
+    pub maintenance: Option<String>,
+    pub mirror: Option<String>,
+    pub target_groups: Option<String>,
+    pub upstream_tls: Option<String>,
+    pub upstream_protocol: String,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<String>,
 }
 
 #[derive(sqlx::FromRow, Debug, Clone)]
@@ -23,6 +42,7 @@ pub struct LocationRow {
     pub id: i64,
     pub host_id: i64,
     pub path: String,
+    pub match_type: String,
     pub target: String,
     pub scheme: String,
     pub rewrite: bool,
@@ -32,6 +52,26 @@ pub struct LocationRow {
     pub read_timeout_ms: Option<i64>,
     pub write_timeout_ms: Option<i64>,
     pub max_request_body_bytes: Option<i64>,
+    pub lb_method: Option<String>,
+    pub lb_hash_key: Option<String>,
+    pub health_check: Option<String>,
+    pub sticky_session: Option<String>,
+    pub retry_policy: Option<String>,
+    pub cache: Option<String>,
+    pub compression: Option<String>,
+    pub rate_limit: Option<String>,
+    pub access_list_id: Option<i64>,
+    pub redirect_to: Option<String>,
+    pub redirect_status: Option<i64>,
+    pub rewrite_rules: Option<String>,
+    pub static_files: Option<String>,
+    pub mirror: Option<String>,
+    pub target_groups: Option<String>,
+    pub upstream_tls: Option<String>,
+    pub upstream_protocol: String,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    pub upstream_connection: Option<String>,
 }
 
 #[derive(sqlx::FromRow, Debug, Clone)]
@@ -41,6 +81,21 @@ pub struct HeaderRow {
     pub name: String,
     pub value: String,
     pub target: String, // 'request' or 'response'
+    pub operation: String,
+    /// Path and match type of the location the rule is scoped to; `None`
+    /// applies it to the whole host.
+    pub location_path: Option<String>,
+    pub location_match_type: Option<String>,
+}
+
+pub struct AddHeaderParams<'a> {
+    pub host_id: i64,
+    pub name: &'a str,
+    pub value: &'a str,
+    pub target: &'a str,
+    pub operation: &'a str,
+    pub location_path: Option<&'a str>,
+    pub location_match_type: Option<&'a str>,
 }
 
 pub struct UpsertHostParams<'a> {
@@ -57,11 +112,45 @@ pub struct UpsertHostParams<'a> {
     pub redirect_to: Option<&'a str>,
     pub redirect_status: i64,
     pub access_list_id: Option<i64>,
+    pub is_default: bool,
+    pub lb_method: &'a str,
+    pub lb_hash_key: Option<&'a str>,
+    /// JSON-encoded `HealthCheckConfig`.
+    pub health_check: Option<&'a str>,
+    /// JSON-encoded `StickySessionConfig`.
+    pub sticky_session: Option<&'a str>,
+    /// JSON-encoded `RetryPolicy`.
+    pub retry_policy: Option<&'a str>,
+    /// JSON-encoded `CacheConfig`.
+    pub cache: Option<&'a str>,
+    /// JSON-encoded `CompressionConfig`.
+    pub compression: Option<&'a str>,
+    /// JSON-encoded `RateLimitConfig`.
+    pub rate_limit: Option<&'a str>,
+    /// JSON-encoded `ForwardedHeadersConfig`.
+    pub forwarded_headers: Option<&'a str>,
+    /// JSON-encoded list of `RewriteRule`s.
+    pub rewrite_rules: Option<&'a str>,
+    /// JSON-encoded `MaintenanceConfig`.
+    pub maintenance: Option<&'a str>,
+    /// JSON-encoded `MirrorConfig`.
+    pub mirror: Option<&'a str>,
+    /// JSON-encoded `TargetGroupsConfig`.
+    pub target_groups: Option<&'a str>,
+    /// JSON-encoded `UpstreamTlsConfig`.
+    pub upstream_tls: Option<&'a str>,
+    /// `http1`, `h2` or `h2c`.
+    pub upstream_protocol: &'a str,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    /// JSON-encoded `UpstreamConnectionConfig`.
+    pub upstream_connection: Option<&'a str>,
 }
 
 pub struct UpsertLocationParams<'a> {
     pub host_id: i64,
     pub path: &'a str,
+    pub match_type: &'a str,
     pub target: &'a str,
     pub scheme: &'a str,
     pub rewrite: bool,
@@ -71,6 +160,39 @@ pub struct UpsertLocationParams<'a> {
     pub read_timeout_ms: Option<i64>,
     pub write_timeout_ms: Option<i64>,
     pub max_request_body_bytes: Option<i64>,
+    pub lb_method: Option<&'a str>,
+    pub lb_hash_key: Option<&'a str>,
+    /// JSON-encoded `HealthCheckConfig`.
+    pub health_check: Option<&'a str>,
+    /// JSON-encoded `StickySessionConfig`.
+    pub sticky_session: Option<&'a str>,
+    /// JSON-encoded `RetryPolicy`.
+    pub retry_policy: Option<&'a str>,
+    /// JSON-encoded `CacheConfig`.
+    pub cache: Option<&'a str>,
+    /// JSON-encoded `CompressionConfig`.
+    pub compression: Option<&'a str>,
+    /// JSON-encoded `RateLimitConfig`.
+    pub rate_limit: Option<&'a str>,
+    pub access_list_id: Option<i64>,
+    pub redirect_to: Option<&'a str>,
+    pub redirect_status: Option<i64>,
+    /// JSON-encoded list of `RewriteRule`s.
+    pub rewrite_rules: Option<&'a str>,
+    /// JSON-encoded `StaticFilesConfig`.
+    pub static_files: Option<&'a str>,
+    /// JSON-encoded `MirrorConfig`.
+    pub mirror: Option<&'a str>,
+    /// JSON-encoded `TargetGroupsConfig`.
+    pub target_groups: Option<&'a str>,
+    /// JSON-encoded `UpstreamTlsConfig`.
+    pub upstream_tls: Option<&'a str>,
+    /// `http1`, `h2` or `h2c`.
+    pub upstream_protocol: &'a str,
+    pub websocket_idle_timeout_ms: Option<i64>,
+    pub websocket_max_lifetime_ms: Option<i64>,
+    /// JSON-encoded `UpstreamConnectionConfig`.
+    pub upstream_connection: Option<&'a str>,
 }
 
 /// Retrieves all configured hosts from the database.
@@ -104,13 +226,16 @@ pub async fn get_host_id(pool: &DbPool, domain: &str) -> Result<Option<i64>, sql
 
 /// Retrieves all configured locations from the database.
 ///
+/// Rows are returned in insertion order so regex locations are evaluated
+/// deterministically in the order they were declared.
+///
 /// # Arguments
 /// * `pool` - Database connection pool
 ///
 /// # Returns
 /// * `Result<Vec<LocationRow>, sqlx::Error>` - A list of all locations or a database error
 pub async fn get_all_locations(pool: &DbPool) -> Result<Vec<LocationRow>, sqlx::Error> {
-    sqlx::query_as::<_, LocationRow>("SELECT * FROM locations")
+    sqlx::query_as::<_, LocationRow>("SELECT * FROM locations ORDER BY id")
         .fetch_all(pool)
         .await
 }
@@ -150,28 +275,30 @@ pub async fn get_all_headers(pool: &DbPool) -> Result<Vec<HeaderRow>, sqlx::Erro
 ///
 /// # Arguments
 /// * `pool` - Database connection pool
-/// * `host_id` - ID of the host to attach the header to
-/// * `name` - Header name
-/// * `value` - Header value
-/// * `target` - Target of the header ('request' or 'response')
+/// * `params` - Header rule parameters
+///   * `host_id` - ID of the host to attach the header to
+///   * `name` - Header name
+///   * `value` - Header value
+///   * `target` - Target of the header ('request' or 'response')
+///   * `operation` - 'set', 'append', 'remove' or 'set_if_missing'
+///   * `location_path` / `location_match_type` - Location the rule is scoped to
 ///
 /// # Returns
 /// * `Result<i64, sqlx::Error>` - The ID of the newly created header or a database error
-pub async fn add_header(
-    pool: &DbPool,
-    host_id: i64,
-    name: &str,
-    value: &str,
-    target: &str,
-) -> Result<i64, sqlx::Error> {
-    let id = sqlx::query("INSERT INTO headers (host_id, name, value, target) VALUES (?, ?, ?, ?)")
-        .bind(host_id)
-        .bind(name)
-        .bind(value)
-        .bind(target)
-        .execute(pool)
-        .await?
-        .last_insert_rowid();
+pub async fn add_header(pool: &DbPool, params: AddHeaderParams<'_>) -> Result<i64, sqlx::Error> {
+    let id = sqlx::query(
+        "INSERT INTO headers (host_id, name, value, target, operation, location_path, location_match_type) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(params.host_id)
+    .bind(params.name)
+    .bind(params.value)
+    .bind(params.target)
+    .bind(params.operation)
+    .bind(params.location_path)
+    .bind(params.location_match_type)
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
     Ok(id)
 }
 
@@ -203,14 +330,42 @@ pub async fn delete_header(pool: &DbPool, id: i64) -> Result<(), sqlx::Error> {
 /// * `redirect_to` - Optional redirect URL
 /// * `redirect_status` - HTTP status code for redirect
 /// * `access_list_id` - Optional ID of the access list to apply
+/// * `is_default` - Whether this host catches requests for unknown domains; clears the flag on all other hosts
+/// * `lb_method` - Load-balancing strategy for the host's targets
+/// * `lb_hash_key` - `header:<name>` or `cookie:<name>` hashed by `consistent_hash`
+/// * `health_check` - Optional active health check settings as JSON
+/// * `sticky_session` - Optional affinity cookie settings as JSON
+/// * `retry_policy` - Optional retry policy as JSON
+/// * `cache` - Optional response cache settings as JSON
+/// * `compression` - Optional response compression settings as JSON
+/// * `rate_limit` - Optional per-client rate limit as JSON
+/// * `forwarded_headers` - Optional forwarding headers for upstream requests as JSON
+/// * `rewrite_rules` - Optional ordered URL rewrite rules as JSON
+/// * `maintenance` - Optional maintenance mode settings as JSON
+/// * `mirror` - Optional traffic mirroring settings as JSON
+/// * `target_groups` - Optional weighted target groups as JSON
+/// * `upstream_tls` - Optional upstream TLS settings (client certificate, CA bundle) as JSON
+/// * `upstream_protocol` - HTTP version spoken to the upstream (`http1`, `h2` or `h2c`)
+/// * `websocket_idle_timeout_ms` - Optional idle timeout for upgraded (WebSocket) connections
+/// * `websocket_max_lifetime_ms` - Optional maximum lifetime of upgraded (WebSocket) connections
+/// * `upstream_connection` - Optional upstream connection reuse and TCP socket settings as JSON
 ///
 /// # Returns
 /// * `Result<(), sqlx::Error>` - Success or a database error
 pub async fn upsert_host(pool: &DbPool, params: UpsertHostParams<'_>) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    if params.is_default {
+        sqlx::query("UPDATE hosts SET is_default = 0 WHERE domain != ?")
+            .bind(params.domain)
+            .execute(&mut *tx)
+            .await?;
+    }
+
     sqlx::query(
         r#"
-        INSERT INTO hosts (domain, target, scheme, ssl_forced, verify_ssl, upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes, redirect_to, redirect_status, access_list_id)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO hosts (domain, target, scheme, ssl_forced, verify_ssl, upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes, redirect_to, redirect_status, access_list_id, is_default, lb_method, lb_hash_key, health_check, sticky_session, retry_policy, cache, compression, rate_limit, forwarded_headers, rewrite_rules, maintenance, mirror, target_groups, upstream_tls, upstream_protocol, websocket_idle_timeout_ms, websocket_max_lifetime_ms, upstream_connection)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         ON CONFLICT(domain) DO UPDATE SET 
             target = excluded.target, 
             scheme = excluded.scheme,
@@ -223,7 +378,26 @@ pub async fn upsert_host(pool: &DbPool, params: UpsertHostParams<'_>) -> Result<
             max_request_body_bytes = excluded.max_request_body_bytes,
             redirect_to = excluded.redirect_to,
             redirect_status = excluded.redirect_status,
-            access_list_id = excluded.access_list_id
+            access_list_id = excluded.access_list_id,
+            is_default = excluded.is_default,
+            lb_method = excluded.lb_method,
+            lb_hash_key = excluded.lb_hash_key,
+            health_check = excluded.health_check,
+            sticky_session = excluded.sticky_session,
+            retry_policy = excluded.retry_policy,
+            cache = excluded.cache,
+            compression = excluded.compression,
+            rate_limit = excluded.rate_limit,
+            forwarded_headers = excluded.forwarded_headers,
+            rewrite_rules = excluded.rewrite_rules,
+            maintenance = excluded.maintenance,
+            mirror = excluded.mirror,
+            target_groups = excluded.target_groups,
+            upstream_tls = excluded.upstream_tls,
+            upstream_protocol = excluded.upstream_protocol,
+            websocket_idle_timeout_ms = excluded.websocket_idle_timeout_ms,
+            websocket_max_lifetime_ms = excluded.websocket_max_lifetime_ms,
+            upstream_connection = excluded.upstream_connection
         "#,
     )
     .bind(params.domain)
@@ -239,8 +413,29 @@ pub async fn upsert_host(pool: &DbPool, params: UpsertHostParams<'_>) -> Result<
     .bind(params.redirect_to)
     .bind(params.redirect_status)
     .bind(params.access_list_id)
-    .execute(pool)
+    .bind(params.is_default)
+    .bind(params.lb_method)
+    .bind(params.lb_hash_key)
+    .bind(params.health_check)
+    .bind(params.sticky_session)
+    .bind(params.retry_policy)
+    .bind(params.cache)
+    .bind(params.compression)
+    .bind(params.rate_limit)
+    .bind(params.forwarded_headers)
+    .bind(params.rewrite_rules)
+    .bind(params.maintenance)
+    .bind(params.mirror)
+    .bind(params.target_groups)
+    .bind(params.upstream_tls)
+    .bind(params.upstream_protocol)
+    .bind(params.websocket_idle_timeout_ms)
+    .bind(params.websocket_max_lifetime_ms)
+    .bind(params.upstream_connection)
+    .execute(&mut *tx)
     .await?;
+
+    tx.commit().await?;
     Ok(())
 }
 
@@ -260,16 +455,60 @@ pub async fn delete_host(pool: &DbPool, domain: &str) -> Result<(), sqlx::Error>
     Ok(())
 }
 
-/// Inserts or updates a location configuration for a host.
+/// Replaces only the maintenance settings of a host, leaving the rest of its
+/// configuration untouched.
+///
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `domain` - Domain name of the host
+/// * `maintenance` - Maintenance mode settings as JSON, or `None` to clear them
+///
+/// # Returns
+/// * `Result<bool, sqlx::Error>` - Whether the host exists, or a database error
+pub async fn set_host_maintenance(
+    pool: &DbPool,
+    domain: &str,
+    maintenance: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE hosts SET maintenance = ? WHERE domain = ?")
+        .bind(maintenance)
+        .bind(domain)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Inserts or updates a location configuration for a host. An existing
+/// location keeps its row id, and with it its place among regex locations.
 ///
 /// # Arguments
 /// * `pool` - Database connection pool
 /// * `host_id` - ID of the host
 /// * `path` - Location path
+/// * `match_type` - How `path` is matched ('prefix', 'exact', 'regex' or 'regex_insensitive')
 /// * `target` - Upstream target URL
 /// * `scheme` - Upstream scheme
 /// * `rewrite` - Whether to enable path rewriting
 /// * `verify_ssl` - Whether to verify upstream SSL certificates
+/// * `lb_method` - Optional load-balancing strategy; `None` inherits the host's
+/// * `health_check` - Optional active health check settings as JSON
+/// * `sticky_session` - Optional affinity cookie settings as JSON
+/// * `retry_policy` - Optional retry policy as JSON
+/// * `cache` - Optional response cache settings as JSON
+/// * `compression` - Optional response compression settings as JSON
+/// * `rate_limit` - Optional per-client rate limit as JSON
+/// * `access_list_id` - Optional access list overriding the host's
+/// * `redirect_to` - Optional redirect target overriding the host's
+/// * `redirect_status` - HTTP status code for the location redirect
+/// * `rewrite_rules` - Optional ordered URL rewrite rules as JSON
+/// * `static_files` - Optional static file serving settings as JSON
+/// * `mirror` - Optional traffic mirroring settings as JSON
+/// * `target_groups` - Optional weighted target groups as JSON
+/// * `upstream_tls` - Optional upstream TLS settings (client certificate, CA bundle) as JSON
+/// * `upstream_protocol` - HTTP version spoken to the upstream (`http1`, `h2` or `h2c`)
+/// * `websocket_idle_timeout_ms` - Optional idle timeout for upgraded (WebSocket) connections
+/// * `websocket_max_lifetime_ms` - Optional maximum lifetime of upgraded (WebSocket) connections
+/// * `upstream_connection` - Optional upstream connection reuse and TCP socket settings as JSON
 ///
 /// # Returns
 /// * `Result<(), sqlx::Error>` - Success or a database error
@@ -277,17 +516,12 @@ pub async fn upsert_location(
     pool: &DbPool,
     params: UpsertLocationParams<'_>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM locations WHERE host_id = ? AND path = ?")
-        .bind(params.host_id)
-        .bind(params.path)
-        .execute(pool)
-        .await?;
+    let mut tx = pool.begin().await?;
 
-    sqlx::query(
-        "INSERT INTO locations (host_id, path, target, scheme, rewrite, verify_ssl, upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    // Update in place so the row id, which orders regex locations, is kept
+    let updated = sqlx::query(
+        "UPDATE locations SET target = ?, scheme = ?, rewrite = ?, verify_ssl = ?, upstream_sni = ?, connection_timeout_ms = ?, read_timeout_ms = ?, write_timeout_ms = ?, max_request_body_bytes = ?, lb_method = ?, lb_hash_key = ?, health_check = ?, sticky_session = ?, retry_policy = ?, cache = ?, compression = ?, rate_limit = ?, access_list_id = ?, redirect_to = ?, redirect_status = ?, rewrite_rules = ?, static_files = ?, mirror = ?, target_groups = ?, upstream_tls = ?, upstream_protocol = ?, websocket_idle_timeout_ms = ?, websocket_max_lifetime_ms = ?, upstream_connection = ? WHERE host_id = ? AND path = ? AND match_type = ?",
     )
-    .bind(params.host_id)
-    .bind(params.path)
     .bind(params.target)
     .bind(params.scheme)
     .bind(params.rewrite)
@@ -297,8 +531,73 @@ pub async fn upsert_location(
     .bind(params.read_timeout_ms)
     .bind(params.write_timeout_ms)
     .bind(params.max_request_body_bytes)
-    .execute(pool)
+    .bind(params.lb_method)
+    .bind(params.lb_hash_key)
+    .bind(params.health_check)
+    .bind(params.sticky_session)
+    .bind(params.retry_policy)
+    .bind(params.cache)
+    .bind(params.compression)
+    .bind(params.rate_limit)
+    .bind(params.access_list_id)
+    .bind(params.redirect_to)
+    .bind(params.redirect_status)
+    .bind(params.rewrite_rules)
+    .bind(params.static_files)
+    .bind(params.mirror)
+    .bind(params.target_groups)
+    .bind(params.upstream_tls)
+    .bind(params.upstream_protocol)
+    .bind(params.websocket_idle_timeout_ms)
+    .bind(params.websocket_max_lifetime_ms)
+    .bind(params.upstream_connection)
+    .bind(params.host_id)
+    .bind(params.path)
+    .bind(params.match_type)
+    .execute(&mut *tx)
     .await?;
+
+    if updated.rows_affected() == 0 {
+        sqlx::query(
+            "INSERT INTO locations (host_id, path, match_type, target, scheme, rewrite, verify_ssl, upstream_sni, connection_timeout_ms, read_timeout_ms, write_timeout_ms, max_request_body_bytes, lb_method, lb_hash_key, health_check, sticky_session, retry_policy, cache, compression, rate_limit, access_list_id, redirect_to, redirect_status, rewrite_rules, static_files, mirror, target_groups, upstream_tls, upstream_protocol, websocket_idle_timeout_ms, websocket_max_lifetime_ms, upstream_connection) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(params.host_id)
+        .bind(params.path)
+        .bind(params.match_type)
+        .bind(params.target)
+        .bind(params.scheme)
+        .bind(params.rewrite)
+        .bind(params.verify_ssl)
+        .bind(params.upstream_sni)
+        .bind(params.connection_timeout_ms)
+        .bind(params.read_timeout_ms)
+        .bind(params.write_timeout_ms)
+        .bind(params.max_request_body_bytes)
+        .bind(params.lb_method)
+        .bind(params.lb_hash_key)
+        .bind(params.health_check)
+        .bind(params.sticky_session)
+        .bind(params.retry_policy)
+        .bind(params.cache)
+        .bind(params.compression)
+        .bind(params.rate_limit)
+        .bind(params.access_list_id)
+        .bind(params.redirect_to)
+        .bind(params.redirect_status)
+        .bind(params.rewrite_rules)
+        .bind(params.static_files)
+        .bind(params.mirror)
+        .bind(params.target_groups)
+        .bind(params.upstream_tls)
+        .bind(params.upstream_protocol)
+        .bind(params.websocket_idle_timeout_ms)
+        .bind(params.websocket_max_lifetime_ms)
+        .bind(params.upstream_connection)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
     Ok(())
 }
 
@@ -308,14 +607,35 @@ pub async fn upsert_location(
 /// * `pool` - Database connection pool
 /// * `host_id` - ID of the host
 /// * `path` - Path of the location to delete
+/// * `match_type` - Optional match type; when `None`, every location with `path` is removed
 ///
 /// # Returns
 /// * `Result<(), sqlx::Error>` - Success or a database error
-pub async fn delete_location(pool: &DbPool, host_id: i64, path: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("DELETE FROM locations WHERE host_id = ? AND path = ?")
-        .bind(host_id)
-        .bind(path)
-        .execute(pool)
-        .await?;
+pub async fn delete_location(
+    pool: &DbPool,
+    host_id: i64,
+    path: &str,
+    match_type: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM locations WHERE host_id = ? AND path = ? AND (? IS NULL OR match_type = ?)",
+    )
+    .bind(host_id)
+    .bind(path)
+    .bind(match_type)
+    .bind(match_type)
+    .execute(pool)
+    .await?;
+
+    // Header rules scoped to the location go with it
+    sqlx::query(
+        "DELETE FROM headers WHERE host_id = ? AND location_path = ? AND (? IS NULL OR location_match_type = ?)",
+    )
+    .bind(host_id)
+    .bind(path)
+    .bind(match_type)
+    .bind(match_type)
+    .execute(pool)
+    .await?;
     Ok(())
 }
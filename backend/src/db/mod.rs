@@ -5,6 +5,7 @@ pub type DbPool = Pool<Sqlite>;
 
 pub mod access_lists;
 pub mod certs;
+pub mod error_pages;
 pub mod hosts;
 pub mod stats;
 pub mod streams;
@@ -12,6 +13,7 @@ pub mod users;
 
 pub use access_lists::*;
 pub use certs::*;
+pub use error_pages::*;
 pub use hosts::*;
 pub use stats::*;
 pub use streams::*;
@@ -43,6 +45,25 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
             redirect_status INTEGER NOT NULL DEFAULT 301,
             access_list_id INTEGER,
             upstream_sni TEXT,
+            is_default BOOLEAN NOT NULL DEFAULT 0,
+            lb_method TEXT NOT NULL DEFAULT 'random',
+            lb_hash_key TEXT,
+            health_check TEXT,
+            sticky_session TEXT,
+            retry_policy TEXT,
+            cache TEXT,
+            compression TEXT,
+            rate_limit TEXT,
+            forwarded_headers TEXT,
+            rewrite_rules TEXT,
+            maintenance TEXT,
+            mirror TEXT,
+            target_groups TEXT,
+            upstream_tls TEXT,
+            upstream_protocol TEXT NOT NULL DEFAULT 'http1',
+            websocket_idle_timeout_ms INTEGER,
+            websocket_max_lifetime_ms INTEGER,
+            upstream_connection TEXT,
             FOREIGN KEY(access_list_id) REFERENCES access_lists(id)
         );
         "#,
@@ -77,6 +98,100 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
         .execute(&pool)
         .await;
 
+    // 마이그레이션: is_default 컬럼 추가 (매칭되지 않는 Host 요청을 처리할 기본 호스트)
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN is_default BOOLEAN NOT NULL DEFAULT 0")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 로드밸런싱 알고리즘 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN lb_method TEXT NOT NULL DEFAULT 'random'")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN lb_hash_key TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 액티브 헬스체크 설정 컬럼 추가 (JSON)
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN health_check TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 스티키 세션 설정 컬럼 추가 (JSON)
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN sticky_session TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 재시도 정책 컬럼 추가 (JSON)
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN retry_policy TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 응답 캐시 설정 컬럼 추가 (JSON)
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN cache TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 응답 압축 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN compression TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 요청 속도 제한 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN rate_limit TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 업스트림 전달 헤더(X-Forwarded-*, Forwarded) 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN forwarded_headers TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 정규식 URL 재작성 규칙 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN rewrite_rules TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 점검 모드 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN maintenance TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 트래픽 미러링 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN mirror TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 타깃 그룹(카나리) 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN target_groups TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 업스트림 TLS(mTLS) 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN upstream_tls TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 웹소켓 유휴 타임아웃 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN websocket_idle_timeout_ms INTEGER")
+        .execute(&pool)
+        .await;
+
+    // 호스트 웹소켓 최대 연결 수명 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN websocket_max_lifetime_ms INTEGER")
+        .execute(&pool)
+        .await;
+
+    // 호스트 업스트림 연결 재사용/TCP 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE hosts ADD COLUMN upstream_connection TEXT")
+        .execute(&pool)
+        .await;
+
+    // 호스트 업스트림 프로토콜(http1/h2/h2c) 컬럼 추가
+    let _ =
+        sqlx::query("ALTER TABLE hosts ADD COLUMN upstream_protocol TEXT NOT NULL DEFAULT 'http1'")
+            .execute(&pool)
+            .await;
+
     // Locations (경로별 라우팅) 테이블 생성
     sqlx::query(
         r#"
@@ -84,6 +199,7 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             host_id INTEGER NOT NULL,
             path TEXT NOT NULL,
+            match_type TEXT NOT NULL DEFAULT 'prefix',
             target TEXT NOT NULL,
             scheme TEXT NOT NULL DEFAULT 'http',
             rewrite BOOLEAN NOT NULL DEFAULT 0,
@@ -93,6 +209,26 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
             read_timeout_ms INTEGER,
             write_timeout_ms INTEGER,
             max_request_body_bytes INTEGER,
+            lb_method TEXT,
+            lb_hash_key TEXT,
+            health_check TEXT,
+            sticky_session TEXT,
+            retry_policy TEXT,
+            cache TEXT,
+            compression TEXT,
+            rate_limit TEXT,
+            access_list_id INTEGER,
+            redirect_to TEXT,
+            redirect_status INTEGER,
+            rewrite_rules TEXT,
+            static_files TEXT,
+            mirror TEXT,
+            target_groups TEXT,
+            upstream_tls TEXT,
+            upstream_protocol TEXT NOT NULL DEFAULT 'http1',
+            websocket_idle_timeout_ms INTEGER,
+            websocket_max_lifetime_ms INTEGER,
+            upstream_connection TEXT,
             FOREIGN KEY(host_id) REFERENCES hosts(id) ON DELETE CASCADE
         );
         "#,
@@ -122,6 +258,108 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
         .execute(&pool)
         .await;
 
+    // 마이그레이션: match_type 컬럼 추가 (prefix/exact/regex/regex_insensitive)
+    let _ =
+        sqlx::query("ALTER TABLE locations ADD COLUMN match_type TEXT NOT NULL DEFAULT 'prefix'")
+            .execute(&pool)
+            .await;
+
+    // 마이그레이션: 로드밸런싱 알고리즘 컬럼 추가 for locations (NULL이면 호스트 설정 상속)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN lb_method TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN lb_hash_key TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 액티브 헬스체크 설정 컬럼 추가 for locations (JSON)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN health_check TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 스티키 세션 설정 컬럼 추가 for locations (JSON, NULL이면 호스트 설정 상속)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN sticky_session TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 재시도 정책 컬럼 추가 for locations (JSON, NULL이면 호스트 설정 상속)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN retry_policy TEXT")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 응답 캐시 설정 컬럼 추가 for locations (JSON, NULL이면 호스트 설정 상속)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN cache TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 응답 압축 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN compression TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 요청 속도 제한 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN rate_limit TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션별 접근 제어 목록 및 리다이렉트 컬럼 추가 (NULL이면 호스트 설정 상속)
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN access_list_id INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN redirect_to TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN redirect_status INTEGER")
+        .execute(&pool)
+        .await;
+
+    // 로케이션별 정규식 URL 재작성 규칙 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN rewrite_rules TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 정적 파일 서빙 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN static_files TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 트래픽 미러링 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN mirror TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 타깃 그룹(카나리) 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN target_groups TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 업스트림 TLS(mTLS) 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN upstream_tls TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 웹소켓 유휴 타임아웃 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN websocket_idle_timeout_ms INTEGER")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 웹소켓 최대 연결 수명 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN websocket_max_lifetime_ms INTEGER")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 업스트림 연결 재사용/TCP 설정 컬럼 추가
+    let _ = sqlx::query("ALTER TABLE locations ADD COLUMN upstream_connection TEXT")
+        .execute(&pool)
+        .await;
+
+    // 로케이션 업스트림 프로토콜(http1/h2/h2c) 컬럼 추가
+    let _ = sqlx::query(
+        "ALTER TABLE locations ADD COLUMN upstream_protocol TEXT NOT NULL DEFAULT 'http1'",
+    )
+    .execute(&pool)
+    .await;
+
     // Stream (TCP/UDP) 테이블 생성
     sqlx::query(
         r#"
@@ -188,6 +426,37 @@ pub async fn init_db(db_url: &str) -> Result<DbPool, Box<dyn Error>> {
             name TEXT NOT NULL,
             value TEXT NOT NULL,
             target TEXT NOT NULL CHECK(target IN ('request', 'response')),
+            operation TEXT NOT NULL DEFAULT 'set',
+            location_path TEXT,
+            location_match_type TEXT,
+            FOREIGN KEY(host_id) REFERENCES hosts(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 마이그레이션: 헤더 동작 컬럼 추가 (set/append/remove/set_if_missing)
+    let _ = sqlx::query("ALTER TABLE headers ADD COLUMN operation TEXT NOT NULL DEFAULT 'set'")
+        .execute(&pool)
+        .await;
+
+    // 마이그레이션: 로케이션 범위 헤더 (NULL이면 호스트 전체에 적용)
+    let _ = sqlx::query("ALTER TABLE headers ADD COLUMN location_path TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE headers ADD COLUMN location_match_type TEXT")
+        .execute(&pool)
+        .await;
+
+    // Error Pages (호스트/상태 코드별 에러 페이지 오버라이드)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS error_pages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            host_id INTEGER,
+            status_code INTEGER,
+            html TEXT NOT NULL,
             FOREIGN KEY(host_id) REFERENCES hosts(id) ON DELETE CASCADE
         );
         "#,
@@ -16,6 +16,7 @@ use crate::proxy::DynamicProxy;
 use crate::state::AppState;
 use crate::stream_manager::StreamManager;
 use crate::tls_manager::SharedCertManager;
+use pingora::apps::HttpServerOptions;
 use pingora::listeners::tls::TlsSettings;
 use pingora::prelude::*;
 use std::sync::Arc;
@@ -157,6 +158,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         });
 
+        // 업스트림 액티브 헬스체크 (설정된 호스트/로케이션만)
+        tokio::spawn(proxy::health::run_health_checks(state_for_init.clone()));
+
         // 6. 메트릭 수집 스케줄러 (매 1분마다 DB 저장)
         let pool_for_stats = pool.clone();
         let state_for_stats = state_for_init.clone();
@@ -196,6 +200,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 7. Pingora 서버 실행 (메인 스레드 점유)
     let mut my_server = Server::new(None)?;
+    if let Some(pool_size) = proxy::upstream_pool_size() {
+        match Arc::get_mut(&mut my_server.configuration) {
+            Some(conf) => conf.upstream_keepalive_pool_size = pool_size,
+            None => tracing::warn!("⚠️ Failed to apply PPM_UPSTREAM_POOL_SIZE"),
+        }
+    }
     my_server.bootstrap();
 
     let mut my_proxy = http_proxy_service(
@@ -207,6 +217,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     my_proxy.set_connection_filter(Arc::new(IpBlockConnectionFilter::from_env()));
 
+    // Plain-text clients may also speak HTTP/2 with prior knowledge (h2c), as gRPC clients do
+    if let Some(proxy) = my_proxy.app_logic_mut() {
+        proxy.server_options = Some(HttpServerOptions {
+            h2c: true,
+            ..Default::default()
+        });
+    }
     my_proxy.add_tcp(constants::network::PROXY_PORT_STR);
 
     // SNI 기반 동적 인증서 선택 설정
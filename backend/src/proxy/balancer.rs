@@ -0,0 +1,461 @@
+use super::target_groups;
+use crate::state::{HostConfig, LbHashKey, LbMethod, LocationConfig, ProxyConfig, UpstreamTarget};
+use parking_lot::RwLock;
+use pingora::http::RequestHeader;
+use pingora::lb::discovery::Static;
+use pingora::lb::selection::{
+    BackendIter, BackendSelection, Consistent, FNVHash, Random, RoundRobin,
+};
+use pingora::lb::{Backend, Backends, LoadBalancer};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Backends Pingora's selectors may try before giving up. After the first
+/// pick the weighted selectors continue at random, so this leaves a
+/// negligible chance of missing the only eligible target.
+const MAX_SELECT_ITERATIONS: usize = 256;
+
+/// Position of a target in its pool, carried in the backend's extensions.
+#[derive(Debug, Clone, Copy)]
+struct TargetIndex(usize);
+
+/// Pingora selector behind each `lb_method`. Least requests needs the
+/// in-flight counters, which Pingora's selectors do not see.
+enum Selector {
+    Random(LoadBalancer<Random>),
+    RoundRobin(LoadBalancer<RoundRobin>),
+    FnvHash(LoadBalancer<FNVHash>),
+    Consistent(LoadBalancer<Consistent>),
+    LeastRequests(AtomicUsize),
+}
+
+/// Selection state of one pool, rebuilt when its targets or method change.
+pub struct Pool {
+    targets: Vec<UpstreamTarget>,
+    method: LbMethod,
+    selector: Selector,
+}
+
+impl Pool {
+    async fn new(targets: &[UpstreamTarget], method: LbMethod) -> Self {
+        let selector = match method {
+            LbMethod::Random => Selector::Random(load_balancer(targets, true).await),
+            LbMethod::RoundRobin => Selector::RoundRobin(load_balancer(targets, false).await),
+            LbMethod::WeightedRoundRobin => {
+                Selector::RoundRobin(load_balancer(targets, true).await)
+            }
+            LbMethod::IpHash => Selector::FnvHash(load_balancer(targets, true).await),
+            LbMethod::ConsistentHash => Selector::Consistent(load_balancer(targets, true).await),
+            LbMethod::LeastRequests => Selector::LeastRequests(AtomicUsize::new(0)),
+        };
+        Self {
+            targets: targets.to_vec(),
+            method,
+            selector,
+        }
+    }
+}
+
+/// Pingora identifies backends by socket address, but targets can also be
+/// host names or unix sockets. Each target stands in as an address derived
+/// from its `addr`, so a hash ring only depends on the targets themselves.
+fn backend_addr(addr: &str) -> SocketAddr {
+    let digest = Sha256::digest(addr.as_bytes());
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&digest[..16]);
+    SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(octets), 0, 0, 0))
+}
+
+async fn load_balancer<S>(targets: &[UpstreamTarget], weighted: bool) -> LoadBalancer<S>
+where
+    S: BackendSelection + 'static,
+    S::Iter: BackendIter,
+{
+    let backends: BTreeSet<Backend> = targets
+        .iter()
+        .enumerate()
+        .filter_map(|(index, target)| {
+            let weight = if weighted { target.weight as usize } else { 1 };
+            let mut backend =
+                Backend::new_with_weight(&backend_addr(&target.addr).to_string(), weight).ok()?;
+            backend.ext.insert(TargetIndex(index));
+            Some(backend)
+        })
+        .collect();
+
+    let lb = LoadBalancer::from_backends(Backends::new(Static::new(backends)));
+    // Static discovery completes immediately and cannot fail
+    if let Err(e) = lb.update().await {
+        tracing::error!("Failed to build load balancer: {}", e);
+    }
+    lb
+}
+
+fn pick<S>(
+    lb: &LoadBalancer<S>,
+    key: &[u8],
+    targets: &[UpstreamTarget],
+    eligible: &dyn Fn(&UpstreamTarget) -> bool,
+) -> Option<usize>
+where
+    S: BackendSelection + 'static,
+    S::Iter: BackendIter,
+{
+    let index = |backend: &Backend| backend.ext.get::<TargetIndex>().map(|i| i.0);
+    lb.select_with(key, MAX_SELECT_ITERATIONS, |backend, _| {
+        index(backend).is_some_and(|i| eligible(&targets[i]))
+    })
+    .and_then(|backend| index(&backend))
+}
+
+/// Identifies the target pool of a host or one of its locations.
+pub fn pool_key(host: &HostConfig, location: Option<&LocationConfig>) -> String {
+    match location {
+        Some(loc) => format!("{}:{}:{}", host.id, loc.match_type.as_str(), loc.path),
+        None => host.id.to_string(),
+    }
+}
+
+/// FNV-1a followed by a splitmix64 finalizer for a better spread.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+/// Reads a cookie value from the request's `Cookie` headers.
+pub fn cookie_value<'a>(req: &'a RequestHeader, name: &str) -> Option<&'a str> {
+    cookie_values(req, name).next()
+}
+
+/// Every value of the cookie `name`. Cookies set for different paths can
+/// share a name; browsers send the most specific path first.
+pub fn cookie_values<'a>(
+    req: &'a RequestHeader,
+    name: &'a str,
+) -> impl Iterator<Item = &'a str> + 'a {
+    req.headers
+        .get_all(http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter(move |(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+/// Extracts the value hashed for `key`, if the request carries it.
+pub fn hash_key_value<'a>(req: &'a RequestHeader, key: &LbHashKey) -> Option<&'a [u8]> {
+    match key {
+        LbHashKey::Header(name) => req.headers.get(name.as_str()).map(|v| v.as_bytes()),
+        LbHashKey::Cookie(name) => cookie_value(req, name).map(str::as_bytes),
+    }
+}
+
+/// Decrements the in-flight counter of a target when dropped.
+#[derive(Debug)]
+pub struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Runtime load-balancing state shared by all requests.
+///
+/// Pools are keyed by host/location so round robin positions survive config
+/// reloads; in-flight counters are keyed by address so a backend shared by
+/// several pools is seen with its total load.
+#[derive(Default)]
+pub struct Balancer {
+    pools: RwLock<HashMap<String, Arc<Pool>>>,
+    in_flight: RwLock<HashMap<String, Arc<AtomicUsize>>>,
+}
+
+impl Balancer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selection state for the pool `key`, rebuilt when its targets or
+    /// method changed.
+    pub async fn pool(&self, key: &str, targets: &[UpstreamTarget], method: LbMethod) -> Arc<Pool> {
+        if let Some(pool) = self.pools.read().get(key) {
+            if pool.method == method && pool.targets == targets {
+                return pool.clone();
+            }
+        }
+
+        let pool = Arc::new(Pool::new(targets, method).await);
+        self.pools.write().insert(key.to_string(), pool.clone());
+        pool
+    }
+
+    /// Drops the pools and idle in-flight counters the config no longer uses.
+    pub fn retain(&self, config: &ProxyConfig) {
+        let mut keys = HashSet::new();
+        let mut addrs = HashSet::new();
+        for host in config.hosts.values() {
+            let locations = std::iter::once(None).chain(host.locations.iter().map(Some));
+            for location in locations {
+                for (_, key, targets) in target_groups::pools(host, location) {
+                    keys.insert(key);
+                    addrs.extend(targets.iter().map(|t| t.addr.clone()));
+                }
+            }
+        }
+
+        self.pools.write().retain(|key, _| keys.contains(key));
+        // Counters still held by a request go on the next reload
+        self.in_flight
+            .write()
+            .retain(|addr, counter| addrs.contains(addr) || Arc::strong_count(counter) > 1);
+    }
+
+    fn in_flight_counter(&self, addr: &str) -> Arc<AtomicUsize> {
+        if let Some(counter) = self.in_flight.read().get(addr) {
+            return counter.clone();
+        }
+        self.in_flight
+            .write()
+            .entry(addr.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Current number of requests proxied to `addr`.
+    pub fn in_flight(&self, addr: &str) -> usize {
+        self.in_flight
+            .read()
+            .get(addr)
+            .map(|c| c.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Marks a request as in flight to `addr` until the guard is dropped.
+    pub fn track(&self, addr: &str) -> InFlightGuard {
+        let counter = self.in_flight_counter(addr);
+        counter.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(counter)
+    }
+
+    /// Picks a target index from the pool, skipping targets rejected by
+    /// `eligible`. Hash based methods pick at random when the request has no
+    /// hash input.
+    pub fn select(
+        &self,
+        pool: &Pool,
+        hash_input: Option<&[u8]>,
+        eligible: impl Fn(&UpstreamTarget) -> bool,
+    ) -> Option<usize> {
+        let targets = &pool.targets;
+        if targets.is_empty() {
+            return None;
+        }
+
+        let random_key = rand::random::<u64>().to_le_bytes();
+        let key = hash_input.unwrap_or(&random_key);
+
+        match &pool.selector {
+            Selector::Random(lb) => pick(lb, key, targets, &eligible),
+            Selector::RoundRobin(lb) => pick(lb, key, targets, &eligible),
+            Selector::FnvHash(lb) => pick(lb, key, targets, &eligible),
+            Selector::Consistent(lb) => pick(lb, key, targets, &eligible),
+            Selector::LeastRequests(cursor) => {
+                // Rotate the scan start so ties do not always land on the first target
+                let len = targets.len();
+                let start = cursor.fetch_add(1, Ordering::Relaxed);
+                (0..len)
+                    .map(|i| (start + i) % len)
+                    .filter(|&i| eligible(&targets[i]))
+                    .min_by(|&a, &b| {
+                        let load_a = (self.in_flight(&targets[a].addr) as u64 + 1)
+                            * u64::from(targets[b].weight);
+                        let load_b = (self.in_flight(&targets[b].addr) as u64 + 1)
+                            * u64::from(targets[a].weight);
+                        load_a.cmp(&load_b)
+                    })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(spec: &[(&str, u32)]) -> Vec<UpstreamTarget> {
+        spec.iter()
+            .map(|(addr, weight)| UpstreamTarget {
+                addr: addr.to_string(),
+                weight: *weight,
+            })
+            .collect()
+    }
+
+    async fn pick(
+        balancer: &Balancer,
+        targets: &[UpstreamTarget],
+        method: LbMethod,
+        hash_input: Option<&[u8]>,
+    ) -> String {
+        let pool = balancer.pool("pool", targets, method).await;
+        let idx = balancer.select(&pool, hash_input, |_| true).unwrap();
+        targets[idx].addr.clone()
+    }
+
+    fn count(picks: &[String], addr: &str) -> usize {
+        picks.iter().filter(|p| *p == addr).count()
+    }
+
+    #[tokio::test]
+    async fn round_robin_cycles_through_targets() {
+        let balancer = Balancer::new();
+        let pool = targets(&[("a", 1), ("b", 5), ("c", 1)]);
+
+        let mut picks = Vec::new();
+        for _ in 0..6 {
+            picks.push(pick(&balancer, &pool, LbMethod::RoundRobin, None).await);
+        }
+
+        // Weights are ignored, and each cycle visits every target once
+        assert_eq!(&picks[..3], &picks[3..]);
+        for addr in ["a", "b", "c"] {
+            assert_eq!(count(&picks, addr), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn weighted_round_robin_is_proportional() {
+        let balancer = Balancer::new();
+        let pool = targets(&[("a", 5), ("b", 1), ("c", 1)]);
+
+        let mut picks = Vec::new();
+        for _ in 0..14 {
+            picks.push(pick(&balancer, &pool, LbMethod::WeightedRoundRobin, None).await);
+        }
+
+        assert_eq!(count(&picks, "a"), 10);
+        assert_eq!(count(&picks, "b"), 2);
+        assert_eq!(count(&picks, "c"), 2);
+    }
+
+    #[tokio::test]
+    async fn least_requests_prefers_idle_targets() {
+        let balancer = Balancer::new();
+        let pool = targets(&[("a", 1), ("b", 1)]);
+
+        let busy = balancer.track("a");
+        for _ in 0..4 {
+            assert_eq!(
+                pick(&balancer, &pool, LbMethod::LeastRequests, None).await,
+                "b"
+            );
+        }
+
+        drop(busy);
+        assert_eq!(balancer.in_flight("a"), 0);
+    }
+
+    #[tokio::test]
+    async fn consistent_hash_is_stable_and_skips_ineligible_targets() {
+        let balancer = Balancer::new();
+        let pool = targets(&[("a", 1), ("b", 1), ("c", 1)]);
+
+        let first = pick(&balancer, &pool, LbMethod::ConsistentHash, Some(b"user-42")).await;
+        for _ in 0..5 {
+            assert_eq!(
+                pick(&balancer, &pool, LbMethod::ConsistentHash, Some(b"user-42")).await,
+                first
+            );
+        }
+
+        let state = balancer.pool("pool", &pool, LbMethod::ConsistentHash).await;
+        let idx = balancer
+            .select(&state, Some(b"user-42"), |t| t.addr != first)
+            .unwrap();
+        assert_ne!(pool[idx].addr, first);
+    }
+
+    #[tokio::test]
+    async fn consistent_hash_moves_few_keys_when_a_target_is_added() {
+        let balancer = Balancer::new();
+        let before = targets(&[("a", 1), ("b", 1), ("c", 1)]);
+        let after = targets(&[("a", 1), ("b", 1), ("c", 1), ("d", 1)]);
+        let old_pool = balancer
+            .pool("old", &before, LbMethod::ConsistentHash)
+            .await;
+        let new_pool = balancer.pool("new", &after, LbMethod::ConsistentHash).await;
+
+        let moved = (0..400)
+            .filter(|i| {
+                let key = format!("key-{}", i);
+                let old = balancer.select(&old_pool, Some(key.as_bytes()), |_| true);
+                let new = balancer.select(&new_pool, Some(key.as_bytes()), |_| true);
+                before[old.unwrap()].addr != after[new.unwrap()].addr
+            })
+            .count();
+
+        // Ideal is 1/4 of the keys; a modulo hash would move about 3/4
+        assert!(moved < 160, "moved {} of 400 keys", moved);
+    }
+
+    #[tokio::test]
+    async fn ip_hash_keeps_a_client_on_one_target() {
+        let balancer = Balancer::new();
+        let pool = targets(&[("a", 1), ("b", 1), ("c", 1)]);
+
+        let first = pick(&balancer, &pool, LbMethod::IpHash, Some(b"10.0.0.7")).await;
+        for _ in 0..5 {
+            assert_eq!(
+                pick(&balancer, &pool, LbMethod::IpHash, Some(b"10.0.0.7")).await,
+                first
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_drops_pools_and_counters_of_removed_targets() {
+        let balancer = Balancer::new();
+        let host: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "targets": [{ "addr": "a:80" }],
+            "scheme": "http"
+        }))
+        .unwrap();
+        let live_key = pool_key(&host, None);
+        balancer
+            .pool(&live_key, &host.targets, LbMethod::Random)
+            .await;
+        balancer
+            .pool("2", &targets(&[("b:80", 1)]), LbMethod::Random)
+            .await;
+        drop(balancer.track("a:80"));
+        drop(balancer.track("b:80"));
+        let busy = balancer.track("c:80");
+
+        let mut config = ProxyConfig::default();
+        config.hosts.insert("example.com".to_string(), host);
+        balancer.retain(&config);
+
+        let pools = balancer.pools.read();
+        assert!(pools.contains_key(&live_key));
+        assert!(!pools.contains_key("2"));
+        let in_flight = balancer.in_flight.read();
+        assert!(in_flight.contains_key("a:80"));
+        assert!(!in_flight.contains_key("b:80"));
+        assert!(in_flight.contains_key("c:80"), "busy counters are kept");
+        drop(busy);
+    }
+}
@@ -0,0 +1,1001 @@
+use super::balancer;
+use crate::constants;
+use crate::state::{CacheBypass, CacheConfig, HostConfig, LocationConfig, ProxyConfig};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use parking_lot::{Mutex, RwLock};
+use pingora::cache::cache_control::CacheControl;
+use pingora::cache::filters::resp_cacheable;
+use pingora::cache::key::{CacheHashKey, CompactCacheKey};
+use pingora::cache::storage::{HandleHit, HandleMiss, MissFinishType};
+use pingora::cache::trace::SpanHandle;
+use pingora::cache::{
+    CacheKey, CacheMeta, CacheMetaDefaults, CachePhase, HitHandler, MissHandler, NoCacheReason,
+    PurgeType, RespCacheable, Storage,
+};
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::prelude::*;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// Statuses cached with `default_ttl_secs` when the origin sends no freshness
+/// information (same set nginx caches by default).
+const DEFAULT_TTL_STATUSES: [u16; 6] = [200, 203, 301, 308, 404, 410];
+
+fn no_default_freshness(_status: http::StatusCode) -> Option<u32> {
+    None
+}
+
+/// Only the origin's directives decide freshness; `default_ttl_secs` is
+/// applied separately so it never overrides them.
+const NO_DEFAULTS: CacheMetaDefaults = CacheMetaDefaults::new(no_default_freshness, 0, 0);
+
+/// Cache settings for a request: the location's, then the host's. Disabled
+/// settings count as unset.
+pub fn effective_config<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a CacheConfig> {
+    location
+        .and_then(|loc| loc.cache.as_ref())
+        .or(host.cache.as_ref())
+        .filter(|config| config.enabled)
+}
+
+/// Whether any bypass rule matches the request.
+pub fn bypassed(config: &CacheConfig, req: &RequestHeader) -> bool {
+    config.bypass.iter().any(|rule| match rule {
+        CacheBypass::Header(name) => req.headers.contains_key(name.as_str()),
+        CacheBypass::Cookie(name) => balancer::cookie_value(req, name).is_some(),
+        CacheBypass::PathPrefix(prefix) => req.uri.path().starts_with(prefix.as_str()),
+    })
+}
+
+/// Coarse encoding class of the client, always part of the key so origin
+/// responses with `Vary: Accept-Encoding` are never served to the wrong client.
+fn encoding_class(req: &RequestHeader) -> &'static str {
+    let accepted = req
+        .headers
+        .get(http::header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if accepted.contains("br") {
+        "br"
+    } else if accepted.contains("gzip") {
+        "gzip"
+    } else {
+        "identity"
+    }
+}
+
+/// Readable cache key: `scheme://host/path[?query]`, then one line per
+/// configured header and cookie. Purges match against the first line.
+pub fn primary_key(config: &CacheConfig, scheme: &str, host: &str, req: &RequestHeader) -> String {
+    let mut key = format!(
+        "{}://{}{}",
+        scheme,
+        host.to_ascii_lowercase(),
+        req.uri.path()
+    );
+    if config.key.include_query {
+        if let Some(query) = req.uri.query() {
+            key.push('?');
+            key.push_str(query);
+        }
+    }
+    key.push_str("\nencoding=");
+    key.push_str(encoding_class(req));
+    for name in &config.key.headers {
+        let value = req
+            .headers
+            .get(name.as_str())
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        key.push_str(&format!("\nheader:{}={}", name.to_ascii_lowercase(), value));
+    }
+    for name in &config.key.cookies {
+        let value = balancer::cookie_value(req, name).unwrap_or_default();
+        key.push_str(&format!("\ncookie:{}={}", name, value));
+    }
+    key
+}
+
+pub fn cache_key(primary: String) -> CacheKey {
+    // The readable key doubles as the user tag so the storage can purge by it
+    CacheKey::new("", primary.clone(), primary)
+}
+
+/// Whether a `Vary` header only names request parts the key already covers.
+fn vary_covered(config: &CacheConfig, resp: &ResponseHeader) -> bool {
+    resp.headers
+        .get_all(http::header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .all(|name| {
+            name.eq_ignore_ascii_case("accept-encoding")
+                || config
+                    .key
+                    .headers
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(name))
+        })
+}
+
+/// Fills in the configured stale windows the origin did not set itself.
+fn with_stale_defaults(config: &CacheConfig, meta: CacheMeta) -> CacheMeta {
+    let or_default = |origin: u32, configured: u32| if origin > 0 { origin } else { configured };
+    CacheMeta::new(
+        meta.fresh_until(),
+        meta.created(),
+        or_default(
+            meta.stale_while_revalidate_sec(),
+            config.stale_while_revalidate_secs,
+        ),
+        or_default(meta.stale_if_error_sec(), config.stale_if_error_secs),
+        meta.response_header().clone(),
+    )
+}
+
+/// Decides whether an upstream response is stored. Origin `Cache-Control` /
+/// `Expires` win; `default_ttl_secs` only applies when the origin sends neither.
+pub fn response_cacheable(
+    config: &CacheConfig,
+    req: &RequestHeader,
+    resp: &ResponseHeader,
+) -> RespCacheable {
+    if resp.headers.contains_key(http::header::SET_COOKIE) {
+        return RespCacheable::Uncacheable(NoCacheReason::Custom("set-cookie"));
+    }
+    if !vary_covered(config, resp) {
+        return RespCacheable::Uncacheable(NoCacheReason::Custom("vary"));
+    }
+
+    let cc = CacheControl::from_resp_headers(resp);
+    let authorization = req.headers.contains_key(http::header::AUTHORIZATION);
+    match resp_cacheable(cc.as_ref(), resp.clone(), authorization, &NO_DEFAULTS) {
+        RespCacheable::Cacheable(meta) => {
+            RespCacheable::Cacheable(with_stale_defaults(config, meta))
+        }
+        uncacheable => {
+            let no_directives = cc.is_none() && !resp.headers.contains_key(http::header::EXPIRES);
+            if config.default_ttl_secs > 0
+                && no_directives
+                && !authorization
+                && DEFAULT_TTL_STATUSES.contains(&resp.status.as_u16())
+            {
+                let now = SystemTime::now();
+                RespCacheable::Cacheable(CacheMeta::new(
+                    now + Duration::from_secs(config.default_ttl_secs.into()),
+                    now,
+                    config.stale_while_revalidate_secs,
+                    config.stale_if_error_secs,
+                    resp.clone(),
+                ))
+            } else {
+                uncacheable
+            }
+        }
+    }
+}
+
+/// Value of the `X-Cache-Status` response header.
+pub fn status_label(phase: CachePhase) -> &'static str {
+    match phase {
+        CachePhase::Hit => "HIT",
+        CachePhase::Miss => "MISS",
+        CachePhase::Stale | CachePhase::StaleUpdating => "STALE",
+        CachePhase::Expired => "EXPIRED",
+        CachePhase::Revalidated | CachePhase::RevalidatedNoCache(_) => "REVALIDATED",
+        _ => "BYPASS",
+    }
+}
+
+/// What an admin purge removes, matched against the first line of the
+/// readable key. Targets without a scheme match both `http` and `https`.
+#[derive(Debug, Clone, Copy)]
+pub enum PurgeTarget<'a> {
+    Host(&'a str),
+    PathPrefix { host: &'a str, prefix: &'a str },
+    Key(&'a str),
+}
+
+impl PurgeTarget<'_> {
+    fn matches(&self, tag: &str) -> bool {
+        let url = tag.split('\n').next().unwrap_or_default();
+        let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+        match *self {
+            PurgeTarget::Host(host) => without_scheme
+                .strip_prefix(host.to_ascii_lowercase().as_str())
+                .is_some_and(|rest| rest.starts_with('/')),
+            PurgeTarget::PathPrefix { host, prefix } => without_scheme
+                .strip_prefix(host.to_ascii_lowercase().as_str())
+                .is_some_and(|rest| rest.starts_with(prefix)),
+            PurgeTarget::Key(key) => url == key || without_scheme == key,
+        }
+    }
+}
+
+/// One stored response.
+struct CachedObject {
+    /// `CacheMeta::serialize` output.
+    meta: (Vec<u8>, Vec<u8>),
+    body: Bytes,
+    /// Readable key (see `primary_key`).
+    tag: String,
+}
+
+impl CachedObject {
+    fn size(&self) -> usize {
+        self.meta.0.len() + self.meta.1.len() + self.body.len() + self.tag.len()
+    }
+
+    /// On-disk layout: length-prefixed tag and meta parts, then the body.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size() + 12);
+        for part in [self.tag.as_bytes(), &self.meta.0, &self.meta.1] {
+            buf.extend_from_slice(&(part.len() as u32).to_le_bytes());
+            buf.extend_from_slice(part);
+        }
+        buf.extend_from_slice(&self.body);
+        buf
+    }
+
+    fn decode(data: Bytes) -> Option<Self> {
+        let mut offset = 0;
+        let mut parts = Vec::with_capacity(3);
+        for _ in 0..3 {
+            let len_bytes = data.get(offset..offset + 4)?;
+            let len = u32::from_le_bytes(len_bytes.try_into().ok()?) as usize;
+            offset += 4;
+            parts.push(data.get(offset..offset + len)?.to_vec());
+            offset += len;
+        }
+        let meta1 = parts.pop()?;
+        let meta0 = parts.pop()?;
+        let tag = String::from_utf8(parts.pop()?).ok()?;
+        Some(Self {
+            meta: (meta0, meta1),
+            body: data.slice(offset..),
+            tag,
+        })
+    }
+}
+
+/// Size-bounded map evicting the least recently used entries.
+struct Lru<V> {
+    entries: HashMap<String, (V, u64, usize)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    size: usize,
+    capacity: usize,
+}
+
+impl<V> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&V> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        let old = std::mem::replace(&mut entry.1, self.tick);
+        if let Some(key) = self.order.remove(&old) {
+            self.order.insert(self.tick, key);
+        }
+        Some(&entry.0)
+    }
+
+    /// Inserts `value`, returning the evicted entries. Values larger than the
+    /// whole budget are not stored.
+    fn insert(&mut self, key: String, value: V, size: usize) -> Vec<(String, V)> {
+        let mut evicted = Vec::new();
+        if let Some(old) = self.remove(&key) {
+            evicted.push((key.clone(), old));
+        }
+        if size > self.capacity {
+            return evicted;
+        }
+        evicted.extend(self.evict_until(self.capacity - size));
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick, size));
+        self.size += size;
+        evicted
+    }
+
+    /// Changes the budget, returning the entries evicted to fit it.
+    fn set_capacity(&mut self, capacity: usize) -> Vec<(String, V)> {
+        self.capacity = capacity;
+        self.evict_until(capacity)
+    }
+
+    fn evict_until(&mut self, limit: usize) -> Vec<(String, V)> {
+        let mut evicted = Vec::new();
+        while self.size > limit {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((value, _, old_size)) = self.entries.remove(&oldest) {
+                self.size -= old_size;
+                evicted.push((oldest, value));
+            }
+        }
+        evicted
+    }
+
+    fn remove(&mut self, key: &str) -> Option<V> {
+        let (value, tick, size) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        self.size -= size;
+        Some(value)
+    }
+
+    fn keys_where(&self, mut pred: impl FnMut(&V) -> bool) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(_, (value, _, _))| pred(value))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+/// Disk tier of a zone; the index maps key hashes to readable keys. The
+/// index is built on first use from the tags at the start of each file, and
+/// bodies are only read on a hit.
+struct DiskTier {
+    dir: PathBuf,
+    capacity: AtomicUsize,
+    index: tokio::sync::OnceCell<Mutex<Lru<String>>>,
+}
+
+impl DiskTier {
+    fn new(dir: PathBuf, capacity: usize) -> Self {
+        Self {
+            dir,
+            capacity: AtomicUsize::new(capacity),
+            index: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    async fn index(&self) -> &Mutex<Lru<String>> {
+        self.index
+            .get_or_init(|| async {
+                let dir = self.dir.clone();
+                let capacity = self.capacity.load(Ordering::Relaxed);
+                let index = tokio::task::spawn_blocking(move || scan(&dir, capacity))
+                    .await
+                    .unwrap_or_else(|_| Lru::new(capacity));
+                Mutex::new(index)
+            })
+            .await
+    }
+
+    /// Changes the budget, deleting the files that no longer fit.
+    fn resize(&self, capacity: usize) {
+        if self.capacity.swap(capacity, Ordering::Relaxed) == capacity {
+            return;
+        }
+        if let Some(index) = self.index.get() {
+            for (hash, _) in index.lock().set_capacity(capacity) {
+                let _ = std::fs::remove_file(self.dir.join(hash));
+            }
+        }
+    }
+
+    async fn read(&self, hash: &str) -> Option<CachedObject> {
+        self.index().await.lock().get(hash)?;
+        let data = tokio::fs::read(self.dir.join(hash)).await.ok()?;
+        CachedObject::decode(Bytes::from(data))
+    }
+
+    async fn write(&self, hash: &str, object: &CachedObject) {
+        let data = object.encode();
+        let size = data.len();
+        if size > self.capacity.load(Ordering::Relaxed) {
+            return;
+        }
+        let index = self.index().await;
+        if let Err(e) = tokio::fs::write(self.dir.join(hash), data).await {
+            tracing::warn!("Failed to write cache entry to disk: {}", e);
+            return;
+        }
+        let evicted = index
+            .lock()
+            .insert(hash.to_string(), object.tag.clone(), size);
+        for (key, _) in evicted.into_iter().filter(|(key, _)| key != hash) {
+            self.delete_file(&key).await;
+        }
+    }
+
+    async fn remove(&self, hash: &str) -> bool {
+        let removed = self.index().await.lock().remove(hash).is_some();
+        if removed {
+            self.delete_file(hash).await;
+        }
+        removed
+    }
+
+    async fn delete_file(&self, hash: &str) {
+        let _ = tokio::fs::remove_file(self.dir.join(hash)).await;
+    }
+}
+
+/// Indexes the files left in `dir` by a previous run, oldest first so
+/// recency roughly survives restarts. Unreadable files and files over the
+/// budget are deleted.
+fn scan(dir: &Path, capacity: usize) -> Lru<String> {
+    let mut index = Lru::new(capacity);
+    if capacity > 0 {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            tracing::error!("Failed to open cache directory {}: {}", dir.display(), e);
+        }
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return index;
+    };
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else { continue };
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        files.push((modified, meta.len() as usize, entry.path()));
+    }
+    files.sort();
+
+    for (_, size, path) in files {
+        let hash = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_string);
+        match (hash, read_tag(&path, size)) {
+            (Some(hash), Some(tag)) => {
+                for (evicted, _) in index.insert(hash.clone(), tag, size) {
+                    let _ = std::fs::remove_file(dir.join(evicted));
+                }
+                if index.entries.get(&hash).is_none() {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+            _ => {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+    index
+}
+
+/// Reads only the tag at the start of a cache file (see `CachedObject::encode`).
+fn read_tag(path: &Path, file_size: usize) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut len = [0u8; 4];
+    file.read_exact(&mut len).ok()?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > file_size.saturating_sub(4) {
+        return None;
+    }
+    let mut tag = vec![0u8; len];
+    file.read_exact(&mut tag).ok()?;
+    String::from_utf8(tag).ok()
+}
+
+/// In-memory LRU with a disk tier behind it, for one cache zone.
+pub struct TieredStorage {
+    memory: Mutex<Lru<Arc<CachedObject>>>,
+    disk: DiskTier,
+}
+
+impl TieredStorage {
+    fn new(dir: PathBuf, memory_bytes: usize, disk_bytes: usize) -> Self {
+        Self {
+            memory: Mutex::new(Lru::new(memory_bytes)),
+            disk: DiskTier::new(dir, disk_bytes),
+        }
+    }
+
+    /// Applies changed budgets, evicting what no longer fits.
+    fn resize(&self, memory_bytes: usize, disk_bytes: usize) {
+        let mut memory = self.memory.lock();
+        if memory.capacity != memory_bytes {
+            memory.set_capacity(memory_bytes);
+        }
+        drop(memory);
+        self.disk.resize(disk_bytes);
+    }
+
+    async fn get(&self, hash: &str) -> Option<Arc<CachedObject>> {
+        if let Some(object) = self.memory.lock().get(hash) {
+            return Some(object.clone());
+        }
+        let object = Arc::new(self.disk.read(hash).await?);
+        // Promote so hot disk entries are served from memory next time
+        let size = object.size();
+        self.memory
+            .lock()
+            .insert(hash.to_string(), object.clone(), size);
+        Some(object)
+    }
+
+    async fn put(&self, hash: String, object: CachedObject) {
+        let object = Arc::new(object);
+        let size = object.size();
+        self.memory
+            .lock()
+            .insert(hash.clone(), object.clone(), size);
+        self.disk.write(&hash, &object).await;
+    }
+
+    async fn remove(&self, hash: &str) -> bool {
+        let in_memory = self.memory.lock().remove(hash).is_some();
+        let on_disk = self.disk.remove(hash).await;
+        in_memory || on_disk
+    }
+
+    /// Removes every entry matching `target`, returning how many were purged.
+    async fn purge_matching(&self, target: PurgeTarget<'_>) -> usize {
+        let mut hashes: HashSet<String> = self
+            .memory
+            .lock()
+            .keys_where(|object| target.matches(&object.tag))
+            .into_iter()
+            .collect();
+        hashes.extend(
+            self.disk
+                .index()
+                .await
+                .lock()
+                .keys_where(|tag| target.matches(tag)),
+        );
+
+        let mut purged = 0;
+        for hash in hashes {
+            if self.remove(&hash).await {
+                purged += 1;
+            }
+        }
+        purged
+    }
+}
+
+/// Memory and disk budgets used when the cache settings leave them unset.
+fn default_budgets() -> (usize, usize) {
+    static BUDGETS: OnceLock<(usize, usize)> = OnceLock::new();
+    *BUDGETS.get_or_init(|| {
+        let megabytes = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .map(|mb| mb * 1024 * 1024)
+        };
+        (
+            megabytes("PPM_CACHE_MEMORY_MB").unwrap_or(constants::cache::MEMORY_BYTES),
+            megabytes("PPM_CACHE_DISK_MB").unwrap_or(0),
+        )
+    })
+}
+
+fn budgets(config: &CacheConfig) -> (usize, usize) {
+    let (memory, disk) = default_budgets();
+    let bytes = |configured: Option<u64>, default: usize| {
+        configured.map_or(default, |b| usize::try_from(b).unwrap_or(usize::MAX))
+    };
+    (
+        bytes(config.max_memory_bytes, memory),
+        bytes(config.max_disk_bytes, disk),
+    )
+}
+
+/// Storage of each host or location with its own cache settings, keyed like
+/// balancer pools. Pingora needs `'static` storage, so zones live for the
+/// rest of the process and are emptied rather than dropped.
+fn zones() -> &'static RwLock<HashMap<String, &'static TieredStorage>> {
+    static ZONES: OnceLock<RwLock<HashMap<String, &'static TieredStorage>>> = OnceLock::new();
+    ZONES.get_or_init(Default::default)
+}
+
+/// Storage for a request: the zone of the location or host whose cache
+/// settings apply, resized to those settings.
+pub fn zone(
+    host: &HostConfig,
+    location: Option<&LocationConfig>,
+    config: &CacheConfig,
+) -> &'static TieredStorage {
+    let owner = location.filter(|loc| loc.cache.is_some());
+    let key = balancer::pool_key(host, owner);
+    let (memory, disk) = budgets(config);
+
+    let existing = zones().read().get(&key).copied();
+    let storage = existing.unwrap_or_else(|| {
+        *zones().write().entry(key).or_insert_with_key(|key| {
+            let dir = std::env::var("PPM_CACHE_DIR")
+                .unwrap_or_else(|_| constants::cache::DIR.to_string());
+            let dir =
+                PathBuf::from(dir).join(format!("{:016x}", balancer::hash_bytes(key.as_bytes())));
+            &*Box::leak(Box::new(TieredStorage::new(dir, memory, disk)))
+        })
+    });
+    storage.resize(memory, disk);
+    storage
+}
+
+/// Empties the zones of hosts and locations that no longer cache.
+pub fn retain(config: &ProxyConfig) {
+    let mut live = HashSet::new();
+    for host in config.hosts.values() {
+        if host.cache.is_some() {
+            live.insert(balancer::pool_key(host, None));
+        }
+        for location in host.locations.iter().filter(|loc| loc.cache.is_some()) {
+            live.insert(balancer::pool_key(host, Some(location)));
+        }
+    }
+    for (key, storage) in zones().read().iter() {
+        if !live.contains(key) {
+            storage.resize(0, 0);
+        }
+    }
+}
+
+/// Removes every entry matching `target` from all zones, returning how many
+/// were purged.
+pub async fn purge(target: PurgeTarget<'_>) -> usize {
+    let storages: Vec<&'static TieredStorage> = zones().read().values().copied().collect();
+    let mut purged = 0;
+    for storage in storages {
+        purged += storage.purge_matching(target).await;
+    }
+    purged
+}
+
+#[async_trait]
+impl Storage for TieredStorage {
+    async fn lookup(
+        &'static self,
+        key: &CacheKey,
+        _trace: &SpanHandle,
+    ) -> Result<Option<(CacheMeta, HitHandler)>> {
+        let Some(object) = self.get(&key.combined()).await else {
+            return Ok(None);
+        };
+        let meta = CacheMeta::deserialize(&object.meta.0, &object.meta.1)?;
+        let hit = BodyHit {
+            range: 0..object.body.len(),
+            body: object.body.clone(),
+            done: false,
+        };
+        Ok(Some((meta, Box::new(hit))))
+    }
+
+    async fn get_miss_handler(
+        &'static self,
+        key: &CacheKey,
+        meta: &CacheMeta,
+        _trace: &SpanHandle,
+    ) -> Result<MissHandler> {
+        Ok(Box::new(BodyMiss {
+            storage: self,
+            hash: key.combined(),
+            tag: key.user_tag().to_string(),
+            meta: meta.serialize()?,
+            body: BytesMut::new(),
+        }))
+    }
+
+    async fn purge(
+        &'static self,
+        key: &CompactCacheKey,
+        _purge_type: PurgeType,
+        _trace: &SpanHandle,
+    ) -> Result<bool> {
+        Ok(self.remove(&key.combined()).await)
+    }
+
+    async fn update_meta(
+        &'static self,
+        key: &CacheKey,
+        meta: &CacheMeta,
+        _trace: &SpanHandle,
+    ) -> Result<bool> {
+        let hash = key.combined();
+        let Some(object) = self.get(&hash).await else {
+            return Ok(false);
+        };
+        let updated = CachedObject {
+            meta: meta.serialize()?,
+            body: object.body.clone(),
+            tag: object.tag.clone(),
+        };
+        self.put(hash, updated).await;
+        Ok(true)
+    }
+
+    fn support_streaming_partial_write(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync + 'static) {
+        self
+    }
+}
+
+/// Serves a stored body in one chunk.
+struct BodyHit {
+    body: Bytes,
+    range: std::ops::Range<usize>,
+    done: bool,
+}
+
+#[async_trait]
+impl HandleHit for BodyHit {
+    async fn read_body(&mut self) -> Result<Option<Bytes>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(self.body.slice(self.range.clone())))
+    }
+
+    async fn finish(
+        self: Box<Self>,
+        _storage: &'static (dyn Storage + Sync),
+        _key: &CacheKey,
+        _trace: &SpanHandle,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    fn seek(&mut self, start: usize, end: Option<usize>) -> Result<()> {
+        let end = end.unwrap_or(self.body.len());
+        if start > end || end > self.body.len() {
+            return Err(Error::explain(
+                ErrorType::InternalError,
+                "Cache seek range out of bounds",
+            ));
+        }
+        self.range = start..end;
+        self.done = false;
+        Ok(())
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+}
+
+/// Buffers an upstream body and stores it once complete.
+struct BodyMiss {
+    storage: &'static TieredStorage,
+    hash: String,
+    tag: String,
+    meta: (Vec<u8>, Vec<u8>),
+    body: BytesMut,
+}
+
+#[async_trait]
+impl HandleMiss for BodyMiss {
+    async fn write_body(&mut self, data: Bytes, _eof: bool) -> Result<()> {
+        self.body.extend_from_slice(&data);
+        Ok(())
+    }
+
+    async fn finish(self: Box<Self>) -> Result<MissFinishType> {
+        let object = CachedObject {
+            meta: self.meta,
+            body: self.body.freeze(),
+            tag: self.tag,
+        };
+        let size = object.size();
+        self.storage.put(self.hash, object).await;
+        Ok(MissFinishType::Created(size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::CacheKeyConfig;
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", path.as_bytes(), None).unwrap();
+        for (name, value) in headers {
+            req.insert_header(name.to_string(), *value).unwrap();
+        }
+        req
+    }
+
+    fn config() -> CacheConfig {
+        CacheConfig {
+            enabled: true,
+            default_ttl_secs: 60,
+            max_object_bytes: 1024,
+            key: CacheKeyConfig {
+                include_query: true,
+                headers: vec!["X-Tenant".to_string()],
+                cookies: vec!["lang".to_string()],
+            },
+            bypass: vec![
+                CacheBypass::Header("Authorization".to_string()),
+                CacheBypass::Cookie("session".to_string()),
+                CacheBypass::PathPrefix("/api/".to_string()),
+            ],
+            stale_while_revalidate_secs: 0,
+            stale_if_error_secs: 0,
+            max_memory_bytes: None,
+            max_disk_bytes: None,
+        }
+    }
+
+    #[test]
+    fn primary_key_includes_configured_request_parts() {
+        let req = request(
+            "/app.js?v=2",
+            &[
+                ("X-Tenant", "acme"),
+                ("Cookie", "lang=ko; other=1"),
+                ("Accept-Encoding", "gzip, deflate"),
+            ],
+        );
+        assert_eq!(
+            primary_key(&config(), "https", "Example.com", &req),
+            "https://example.com/app.js?v=2\nencoding=gzip\nheader:x-tenant=acme\ncookie:lang=ko"
+        );
+
+        let mut without_query = config();
+        without_query.key = CacheKeyConfig::default();
+        without_query.key.include_query = false;
+        assert_eq!(
+            primary_key(
+                &without_query,
+                "http",
+                "example.com",
+                &request("/a?b=c", &[])
+            ),
+            "http://example.com/a\nencoding=identity"
+        );
+    }
+
+    #[test]
+    fn bypass_rules_match_header_cookie_and_path() {
+        let config = config();
+        assert!(!bypassed(&config, &request("/index.html", &[])));
+        assert!(bypassed(
+            &config,
+            &request("/", &[("Authorization", "Bearer x")])
+        ));
+        assert!(bypassed(
+            &config,
+            &request("/", &[("Cookie", "a=1; session=abc")])
+        ));
+        assert!(bypassed(&config, &request("/api/users", &[])));
+    }
+
+    #[test]
+    fn responses_with_cookies_or_uncovered_vary_are_not_cached() {
+        let config = config();
+        let req = request("/", &[]);
+
+        let mut plain = ResponseHeader::build(200, None).unwrap();
+        assert!(matches!(
+            response_cacheable(&config, &req, &plain),
+            RespCacheable::Cacheable(_)
+        ));
+
+        plain
+            .insert_header("Vary", "Accept-Encoding, X-Tenant")
+            .unwrap();
+        assert!(matches!(
+            response_cacheable(&config, &req, &plain),
+            RespCacheable::Cacheable(_)
+        ));
+
+        let mut vary = ResponseHeader::build(200, None).unwrap();
+        vary.insert_header("Vary", "User-Agent").unwrap();
+        assert!(matches!(
+            response_cacheable(&config, &req, &vary),
+            RespCacheable::Uncacheable(_)
+        ));
+
+        let mut cookie = ResponseHeader::build(200, None).unwrap();
+        cookie.insert_header("Set-Cookie", "a=1").unwrap();
+        assert!(matches!(
+            response_cacheable(&config, &req, &cookie),
+            RespCacheable::Uncacheable(_)
+        ));
+
+        let mut no_store = ResponseHeader::build(200, None).unwrap();
+        no_store.insert_header("Cache-Control", "no-store").unwrap();
+        assert!(matches!(
+            response_cacheable(&config, &req, &no_store),
+            RespCacheable::Uncacheable(_)
+        ));
+    }
+
+    #[test]
+    fn purge_targets_match_the_url_line_of_the_key() {
+        let tag = "https://example.com/static/app.js?v=2\nencoding=br";
+        assert!(PurgeTarget::Host("EXAMPLE.com").matches(tag));
+        assert!(!PurgeTarget::Host("example.co").matches(tag));
+        assert!(PurgeTarget::PathPrefix {
+            host: "example.com",
+            prefix: "/static/"
+        }
+        .matches(tag));
+        assert!(!PurgeTarget::PathPrefix {
+            host: "example.com",
+            prefix: "/img/"
+        }
+        .matches(tag));
+        assert!(PurgeTarget::Key("example.com/static/app.js?v=2").matches(tag));
+        assert!(PurgeTarget::Key("https://example.com/static/app.js?v=2").matches(tag));
+        assert!(!PurgeTarget::Key("http://example.com/static/app.js?v=2").matches(tag));
+        assert!(!PurgeTarget::Key("example.com/static/app.js").matches(tag));
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used_within_budget() {
+        let mut lru = Lru::new(10);
+        assert!(lru.insert("a".to_string(), 1, 4).is_empty());
+        assert!(lru.insert("b".to_string(), 2, 4).is_empty());
+        assert_eq!(lru.get("a"), Some(&1));
+
+        let evicted = lru.insert("c".to_string(), 3, 4);
+        assert_eq!(evicted, vec![("b".to_string(), 2)]);
+        assert!(lru.insert("huge".to_string(), 4, 11).is_empty());
+        assert_eq!(lru.get("huge"), None);
+        assert_eq!(lru.size, 8);
+
+        assert_eq!(lru.set_capacity(4), vec![("a".to_string(), 1)]);
+        assert_eq!(lru.size, 4);
+    }
+
+    #[test]
+    fn disk_encoding_round_trips() {
+        let object = CachedObject {
+            meta: (vec![1, 2, 3], vec![4]),
+            body: Bytes::from_static(b"hello"),
+            tag: "example.com/\nencoding=identity".to_string(),
+        };
+        let decoded = CachedObject::decode(Bytes::from(object.encode())).unwrap();
+        assert_eq!(decoded.meta, object.meta);
+        assert_eq!(decoded.body, object.body);
+        assert_eq!(decoded.tag, object.tag);
+        assert!(CachedObject::decode(Bytes::from_static(b"\xff\xff")).is_none());
+    }
+
+    #[test]
+    fn disk_index_is_rebuilt_from_file_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let object = CachedObject {
+            meta: (vec![1], vec![2]),
+            body: Bytes::from(vec![0u8; 64]),
+            tag: "http://example.com/\nencoding=identity".to_string(),
+        };
+        std::fs::write(dir.path().join("kept"), object.encode()).unwrap();
+        std::fs::write(dir.path().join("corrupt"), b"\xff\xff\xff\xff").unwrap();
+
+        let mut index = scan(dir.path(), 1024);
+        assert_eq!(index.get("kept"), Some(&object.tag));
+        assert!(!dir.path().join("corrupt").exists());
+
+        // Files that no longer fit the budget are deleted
+        let index = scan(dir.path(), 16);
+        assert!(index.entries.is_empty());
+        assert!(!dir.path().join("kept").exists());
+    }
+}
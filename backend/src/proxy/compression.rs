@@ -0,0 +1,305 @@
+use crate::state::{CompressionAlgorithm, CompressionConfig, HostConfig, LocationConfig};
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+    TRANSFER_ENCODING, VARY,
+};
+use pingora::http::{RequestHeader, ResponseHeader};
+use std::io::Write;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+/// Compression settings for a request: the location's, then the host's.
+/// Disabled settings count as unset.
+pub fn effective_config<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a CompressionConfig> {
+    location
+        .and_then(|loc| loc.compression.as_ref())
+        .or(host.compression.as_ref())
+        .filter(|config| config.enabled)
+}
+
+/// Picks the first configured algorithm the client accepts with a non-zero
+/// q-value (`*` covers codings not listed explicitly).
+pub fn negotiate(config: &CompressionConfig, req: &RequestHeader) -> Option<CompressionAlgorithm> {
+    let accepted: Vec<(String, f32)> = req
+        .headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            let q = match parts.find_map(|p| p.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse::<f32>().ok()?,
+                None => 1.0,
+            };
+            (!coding.is_empty()).then_some((coding, q))
+        })
+        .collect();
+
+    let quality = |token: &str| {
+        accepted
+            .iter()
+            .find(|(coding, _)| coding == token)
+            .or_else(|| accepted.iter().find(|(coding, _)| coding == "*"))
+            .map(|(_, q)| *q)
+    };
+    config
+        .algorithms
+        .iter()
+        .copied()
+        .find(|algorithm| quality(algorithm.as_str()).is_some_and(|q| q > 0.0))
+}
+
+fn mime_allowed(config: &CompressionConfig, content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    config.mime_types.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        match allowed.strip_suffix("/*") {
+            Some(family) => mime.split_once('/').is_some_and(|(ty, _)| ty == family),
+            None => mime == allowed,
+        }
+    })
+}
+
+/// Whether a response may be compressed: a full, not yet encoded body of an
+/// allowed type that is not known to be below the size threshold.
+pub fn should_compress(config: &CompressionConfig, resp: &ResponseHeader) -> bool {
+    let status = resp.status.as_u16();
+    if status < 200 || matches!(status, 204 | 206 | 304) {
+        return false;
+    }
+
+    let header = |name| resp.headers.get(name).and_then(|v| v.to_str().ok());
+    if header(CONTENT_ENCODING).is_some_and(|enc| !enc.trim().eq_ignore_ascii_case("identity")) {
+        return false;
+    }
+    if header(CACHE_CONTROL).is_some_and(|cc| cc.to_ascii_lowercase().contains("no-transform")) {
+        return false;
+    }
+    if header(CONTENT_LENGTH)
+        .and_then(|len| len.trim().parse::<u64>().ok())
+        .is_some_and(|len| len < config.min_size_bytes)
+    {
+        return false;
+    }
+    header(CONTENT_TYPE).is_some_and(|ct| mime_allowed(config, ct))
+}
+
+/// Rewrites the headers of a response whose body is about to be encoded.
+/// HTTP/1 clients get a chunked body since the final length is unknown.
+pub fn apply_headers(
+    resp: &mut ResponseHeader,
+    algorithm: CompressionAlgorithm,
+    chunked: bool,
+) -> pingora::Result<()> {
+    let _ = resp.remove_header(&CONTENT_LENGTH);
+    resp.insert_header(CONTENT_ENCODING, algorithm.as_str())?;
+    if chunked {
+        resp.insert_header(TRANSFER_ENCODING, "chunked")?;
+    }
+
+    let varies = resp
+        .headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("accept-encoding"));
+    if !varies {
+        resp.append_header(VARY, "Accept-Encoding")?;
+    }
+
+    // The encoded bytes differ from the origin's, so a strong validator no longer holds
+    if let Some(weak) = resp
+        .headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(|etag| format!("W/{}", etag))
+    {
+        resp.insert_header(ETAG, weak)?;
+    }
+    Ok(())
+}
+
+/// Streaming encoder for one response body.
+pub enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Finished,
+}
+
+impl Encoder {
+    pub fn new(algorithm: CompressionAlgorithm, level: u32) -> Self {
+        match algorithm {
+            CompressionAlgorithm::Gzip => Encoder::Gzip(GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(level.min(9)),
+            )),
+            CompressionAlgorithm::Br => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER_SIZE,
+                level.min(11),
+                BROTLI_WINDOW_BITS,
+            ))),
+        }
+    }
+
+    /// Feeds a body chunk and returns the encoded bytes produced so far;
+    /// `end` also writes the stream trailer.
+    pub fn encode(&mut self, input: &[u8], end: bool) -> std::io::Result<Bytes> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write_all(input)?,
+            Encoder::Brotli(encoder) => encoder.write_all(input)?,
+            Encoder::Finished => return Ok(Bytes::new()),
+        }
+
+        if end {
+            return match std::mem::replace(self, Encoder::Finished) {
+                Encoder::Gzip(encoder) => encoder.finish().map(Bytes::from),
+                Encoder::Brotli(encoder) => Ok(Bytes::from(encoder.into_inner())),
+                Encoder::Finished => Ok(Bytes::new()),
+            };
+        }
+        let output = match self {
+            Encoder::Gzip(encoder) => std::mem::take(encoder.get_mut()),
+            Encoder::Brotli(encoder) => std::mem::take(encoder.get_mut()),
+            Encoder::Finished => Vec::new(),
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn config() -> CompressionConfig {
+        CompressionConfig {
+            enabled: true,
+            algorithms: vec![CompressionAlgorithm::Br, CompressionAlgorithm::Gzip],
+            min_size_bytes: 100,
+            mime_types: vec!["text/*".to_string(), "application/json".to_string()],
+            level: 6,
+        }
+    }
+
+    fn request(accept_encoding: &str) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header("Accept-Encoding", accept_encoding)
+            .unwrap();
+        req
+    }
+
+    fn response(content_type: &str, length: Option<u64>) -> ResponseHeader {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Content-Type", content_type).unwrap();
+        if let Some(length) = length {
+            resp.insert_header("Content-Length", length.to_string())
+                .unwrap();
+        }
+        resp
+    }
+
+    #[test]
+    fn negotiation_follows_config_order_and_q_values() {
+        let config = config();
+        assert_eq!(
+            negotiate(&config, &request("gzip, deflate, br")),
+            Some(CompressionAlgorithm::Br)
+        );
+        assert_eq!(
+            negotiate(&config, &request("gzip, br;q=0")),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(
+            negotiate(&config, &request("*;q=0.5, br;q=0")),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(negotiate(&config, &request("identity")), None);
+        assert_eq!(
+            negotiate(&config, &RequestHeader::build("GET", b"/", None).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn only_eligible_responses_are_compressed() {
+        let config = config();
+        assert!(should_compress(
+            &config,
+            &response("application/json; charset=utf-8", Some(500))
+        ));
+        assert!(should_compress(&config, &response("text/html", None)));
+        assert!(!should_compress(&config, &response("text/html", Some(50))));
+        assert!(!should_compress(&config, &response("image/png", Some(500))));
+
+        let mut encoded = response("text/html", Some(500));
+        encoded.insert_header("Content-Encoding", "gzip").unwrap();
+        assert!(!should_compress(&config, &encoded));
+
+        let mut no_transform = response("text/html", Some(500));
+        no_transform
+            .insert_header("Cache-Control", "public, no-transform")
+            .unwrap();
+        assert!(!should_compress(&config, &no_transform));
+    }
+
+    #[test]
+    fn headers_are_adjusted_for_the_encoded_body() {
+        let mut resp = response("text/html", Some(500));
+        resp.insert_header("ETag", "\"abc\"").unwrap();
+        resp.insert_header("Vary", "Origin").unwrap();
+        apply_headers(&mut resp, CompressionAlgorithm::Gzip, true).unwrap();
+
+        assert!(resp.headers.get("Content-Length").is_none());
+        assert_eq!(resp.headers.get("Content-Encoding").unwrap(), "gzip");
+        assert_eq!(resp.headers.get("Transfer-Encoding").unwrap(), "chunked");
+        assert_eq!(resp.headers.get("ETag").unwrap(), "W/\"abc\"");
+        let vary: Vec<_> = resp.headers.get_all("Vary").iter().collect();
+        assert_eq!(vary, ["Origin", "Accept-Encoding"]);
+    }
+
+    #[test]
+    fn encoders_round_trip_streamed_chunks() {
+        let input = "{\"message\":\"hello\"}".repeat(200);
+        let (first, second) = input.as_bytes().split_at(1000);
+
+        for algorithm in [CompressionAlgorithm::Gzip, CompressionAlgorithm::Br] {
+            let mut encoder = Encoder::new(algorithm, 6);
+            let mut encoded = encoder.encode(first, false).unwrap().to_vec();
+            encoded.extend_from_slice(&encoder.encode(second, true).unwrap());
+            assert!(encoded.len() < input.len());
+            assert!(encoder.encode(b"late", true).unwrap().is_empty());
+
+            let mut decoded = String::new();
+            match algorithm {
+                CompressionAlgorithm::Gzip => {
+                    flate2::read::GzDecoder::new(encoded.as_slice())
+                        .read_to_string(&mut decoded)
+                        .unwrap();
+                }
+                CompressionAlgorithm::Br => {
+                    brotli::Decompressor::new(encoded.as_slice(), 4096)
+                        .read_to_string(&mut decoded)
+                        .unwrap();
+                }
+            }
+            assert_eq!(decoded, input);
+        }
+    }
+}
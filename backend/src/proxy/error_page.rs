@@ -0,0 +1,105 @@
+use super::grpc;
+use bytes::Bytes;
+use pingora::http::ResponseHeader;
+use pingora::prelude::*;
+
+/// Values substituted into `{{name}}` placeholders of an error page template.
+pub struct ErrorPageVars<'a> {
+    pub status_code: u16,
+    pub host: &'a str,
+    pub request_id: &'a str,
+    pub timestamp: String,
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders a template, replacing `{{status_code}}`, `{{status_text}}`,
+/// `{{host}}`, `{{request_id}}` and `{{timestamp}}`. Values are HTML-escaped
+/// since the host comes straight from the client.
+pub fn render(template: &str, vars: &ErrorPageVars<'_>) -> String {
+    let status_text = http::StatusCode::from_u16(vars.status_code)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Error");
+
+    template
+        .replace("{{status_code}}", &vars.status_code.to_string())
+        .replace("{{status_text}}", status_text)
+        .replace("{{host}}", &escape_html(vars.host))
+        .replace("{{request_id}}", &escape_html(vars.request_id))
+        .replace("{{timestamp}}", &escape_html(&vars.timestamp))
+}
+
+/// Writes a complete HTML error response to the downstream.
+pub async fn respond(session: &mut Session, status: u16, html: String) -> Result<()> {
+    respond_with_headers(session, status, html, Vec::new()).await
+}
+
+/// Like [`respond`], adding `extra` response headers such as `Retry-After`.
+/// gRPC calls get a gRPC status instead of the page.
+pub async fn respond_with_headers(
+    session: &mut Session,
+    status: u16,
+    html: String,
+    extra: Vec<(&'static str, String)>,
+) -> Result<()> {
+    if grpc::is_grpc(session.req_header()) {
+        session.set_keepalive(None);
+        return grpc::respond(session, status, extra).await;
+    }
+
+    let mut header = ResponseHeader::build(status, Some(4 + extra.len()))?;
+    header.insert_header("Content-Type", "text/html; charset=utf-8")?;
+    header.insert_header("Content-Length", html.len().to_string())?;
+    header.insert_header("Cache-Control", "no-store")?;
+    for (name, value) in extra {
+        header.insert_header(name, value)?;
+    }
+
+    session.set_keepalive(None);
+    session
+        .write_response_header(Box::new(header), false)
+        .await?;
+    session
+        .write_response_body(Some(Bytes::from(html)), true)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_substitutes_and_escapes_variables() {
+        let vars = ErrorPageVars {
+            status_code: 502,
+            host: "<script>evil</script>",
+            request_id: "abc123",
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+        };
+
+        let html = render(
+            "<h1>{{status_code}} {{status_text}}</h1><p>{{host}} {{request_id}} {{timestamp}}</p>",
+            &vars,
+        );
+
+        assert_eq!(
+            html,
+            "<h1>502 Bad Gateway</h1><p>&lt;script&gt;evil&lt;/script&gt; abc123 2026-01-01T00:00:00Z</p>"
+        );
+    }
+}
@@ -2,6 +2,7 @@ use super::trusted_proxy;
 use super::{FilterResult, ProxyCtx, ProxyFilter};
 use crate::auth;
 use crate::constants;
+use crate::proxy::grpc;
 use crate::state::AppState;
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
@@ -21,7 +22,13 @@ impl ProxyFilter for AclFilter {
         ctx: &mut ProxyCtx,
     ) -> Result<FilterResult> {
         if let Some(host_config) = &ctx.host_config {
-            if let Some(acl_id) = host_config.access_list_id {
+            // A matched location's access list replaces the host's
+            let acl_id = ctx
+                .matched_location
+                .as_ref()
+                .and_then(|loc| loc.access_list_id)
+                .or(host_config.access_list_id);
+            if let Some(acl_id) = acl_id {
                 if let Some(acl) = self.state.get_access_list(acl_id) {
                     // (A) IP 기반 필터링
                     if !acl.ips.is_empty() {
@@ -48,7 +55,9 @@ impl ProxyFilter for AclFilter {
                                         client_ip,
                                         ctx.host
                                     );
-                                    let _ = session.respond_error(constants::http::FORBIDDEN).await;
+                                    let _ =
+                                        grpc::respond_error(session, constants::http::FORBIDDEN)
+                                            .await;
                                     return Ok(FilterResult::Handled);
                                 }
                             }
@@ -60,7 +69,7 @@ impl ProxyFilter for AclFilter {
                                 client_ip,
                                 ctx.host
                             );
-                            let _ = session.respond_error(constants::http::FORBIDDEN).await;
+                            let _ = grpc::respond_error(session, constants::http::FORBIDDEN).await;
                             return Ok(FilterResult::Handled);
                         }
                     }
@@ -100,6 +109,11 @@ impl ProxyFilter for AclFilter {
 
                         if !authenticated {
                             tracing::info!("🔒 Authentication required for {}", ctx.host);
+                            if grpc::is_grpc(session.req_header()) {
+                                grpc::respond(session, constants::http::UNAUTHORIZED, Vec::new())
+                                    .await?;
+                                return Ok(FilterResult::Handled);
+                            }
                             let mut header =
                                 match ResponseHeader::build(constants::http::UNAUTHORIZED, Some(4))
                                 {
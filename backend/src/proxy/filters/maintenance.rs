@@ -0,0 +1,111 @@
+use super::trusted_proxy;
+use super::{FilterResult, ProxyCtx, ProxyFilter};
+use crate::constants;
+use crate::proxy::error_page;
+use crate::state::{AccessListConfig, AppState};
+use async_trait::async_trait;
+use chrono::Utc;
+use pingora::prelude::*;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Whether an `allow` rule of the access list names the client.
+fn is_allowlisted(acl: &AccessListConfig, client_ip: Option<IpAddr>) -> bool {
+    let Some(client_ip) = client_ip else {
+        return false;
+    };
+    acl.ips
+        .iter()
+        .filter(|rule| rule.action == "allow")
+        .any(|rule| rule.ip.parse::<IpAddr>().is_ok_and(|ip| ip == client_ip))
+}
+
+/// Answers with the maintenance page while a host's maintenance window is
+/// active. Allowlisted clients are proxied as usual.
+pub struct MaintenanceFilter {
+    pub state: Arc<AppState>,
+}
+
+#[async_trait]
+impl ProxyFilter for MaintenanceFilter {
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyCtx,
+    ) -> Result<FilterResult> {
+        let Some(host_config) = &ctx.host_config else {
+            return Ok(FilterResult::Continue);
+        };
+        let Some(maintenance) = &host_config.maintenance else {
+            return Ok(FilterResult::Continue);
+        };
+        let now = Utc::now();
+        if !maintenance.is_active(now) {
+            return Ok(FilterResult::Continue);
+        }
+
+        let client_ip = trusted_proxy::effective_client_ip(session);
+        let allowlisted = maintenance
+            .allow_access_list_id
+            .and_then(|id| self.state.get_access_list(id))
+            .is_some_and(|acl| is_allowlisted(&acl, client_ip));
+        if allowlisted {
+            return Ok(FilterResult::Continue);
+        }
+
+        let status = constants::http::SERVICE_UNAVAILABLE;
+        let template = maintenance
+            .page_html
+            .clone()
+            .unwrap_or_else(|| self.state.get_error_template(Some(host_config.id), status));
+        let html = error_page::render(
+            &template,
+            &error_page::ErrorPageVars {
+                status_code: status,
+                host: &ctx.host,
+                request_id: &ctx.request_id,
+                timestamp: now.to_rfc3339(),
+            },
+        );
+
+        tracing::debug!("🚧 Host {} is in maintenance", ctx.host);
+        error_page::respond_with_headers(
+            session,
+            status,
+            html,
+            vec![("Retry-After", maintenance.retry_after(now).to_string())],
+        )
+        .await?;
+        Ok(FilterResult::Handled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AccessListIpConfig;
+
+    #[test]
+    fn only_allow_rules_allowlist_clients() {
+        let acl = AccessListConfig {
+            id: 1,
+            name: "office".to_string(),
+            clients: vec![],
+            ips: vec![
+                AccessListIpConfig {
+                    ip: "203.0.113.7".to_string(),
+                    action: "allow".to_string(),
+                },
+                AccessListIpConfig {
+                    ip: "198.51.100.1".to_string(),
+                    action: "deny".to_string(),
+                },
+            ],
+        };
+
+        assert!(is_allowlisted(&acl, Some("203.0.113.7".parse().unwrap())));
+        assert!(!is_allowlisted(&acl, Some("198.51.100.1".parse().unwrap())));
+        assert!(!is_allowlisted(&acl, Some("192.0.2.1".parse().unwrap())));
+        assert!(!is_allowlisted(&acl, None));
+    }
+}
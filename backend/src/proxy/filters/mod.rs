@@ -4,8 +4,12 @@ use pingora::prelude::*;
 
 pub mod acl;
 pub mod acme;
+pub mod maintenance;
+pub mod rate_limit;
 pub mod redirect;
+pub mod rewrite;
 pub mod ssl;
+pub mod static_files;
 pub mod trusted_proxy;
 
 #[derive(Debug)]
@@ -0,0 +1,301 @@
+use super::trusted_proxy;
+use super::{FilterResult, ProxyCtx, ProxyFilter};
+use crate::constants;
+use crate::proxy::{balancer, grpc};
+use crate::state::{AppState, HostConfig, LocationConfig, RateLimitConfig, RateLimitKey};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Rate limit for a request: the location's, then the host's. Disabled
+/// limits count as unset.
+pub fn effective_config<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a RateLimitConfig> {
+    location
+        .and_then(|loc| loc.rate_limit.as_ref())
+        .or(host.rate_limit.as_ref())
+        .filter(|config| config.enabled)
+}
+
+/// Outcome of a rate-limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the client's bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request is accepted; 0 when allowed.
+    pub retry_after_secs: u64,
+}
+
+impl RateLimitStatus {
+    /// `X-RateLimit-*` headers sent with every response of a limited route.
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            ("X-RateLimit-Limit", self.limit.to_string()),
+            ("X-RateLimit-Remaining", self.remaining.to_string()),
+            ("X-RateLimit-Reset", self.reset_secs.to_string()),
+        ]
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Tokens added per second.
+    rate: f64,
+    capacity: f64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn is_full_at(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.rate >= self.capacity
+    }
+}
+
+/// Token buckets per route and client. A bucket holds `requests + burst`
+/// tokens and refills at `requests` per period.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a token from the bucket of `key`, if one is available.
+    pub fn check(&self, key: &str, config: &RateLimitConfig, now: Instant) -> RateLimitStatus {
+        let rate = f64::from(config.requests) / config.per.as_secs() as f64;
+        let capacity = f64::from(config.requests) + f64::from(config.burst);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= constants::rate_limit::MAX_TRACKED_CLIENTS && !buckets.contains_key(key)
+        {
+            // A full bucket behaves exactly like a missing one, so idle clients can go
+            buckets.retain(|_, bucket| !bucket.is_full_at(now));
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            rate,
+            capacity,
+        });
+        // Limits may have been edited since the bucket was created
+        bucket.rate = rate;
+        bucket.capacity = capacity;
+        bucket.refill(now);
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let secs_until = |tokens: f64| (tokens.max(0.0) / rate).ceil() as u64;
+        RateLimitStatus {
+            allowed,
+            limit: config.requests,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: secs_until(capacity - bucket.tokens),
+            retry_after_secs: if allowed {
+                0
+            } else {
+                secs_until(1.0 - bucket.tokens).max(1)
+            },
+        }
+    }
+}
+
+fn basic_auth_user(req: &RequestHeader) -> Option<String> {
+    let encoded = req
+        .headers
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials
+        .split_once(':')
+        .map(|(username, _)| username.to_string())
+}
+
+/// Identifies the client per `key`, falling back to its IP so omitting the
+/// header or credentials does not escape the limit.
+fn client_key(session: &Session, key: &RateLimitKey) -> String {
+    let req = session.req_header();
+    let identity = match key {
+        RateLimitKey::ClientIp => None,
+        RateLimitKey::Header(name) => req
+            .headers
+            .get(name.as_str())
+            .and_then(|v| v.to_str().ok())
+            .map(|v| format!("header:{}", v)),
+        RateLimitKey::BasicAuthUser => basic_auth_user(req).map(|user| format!("user:{}", user)),
+    };
+    identity.unwrap_or_else(|| {
+        let ip = trusted_proxy::effective_client_ip(session)
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+        format!("ip:{}", ip)
+    })
+}
+
+pub struct RateLimitFilter {
+    pub state: Arc<AppState>,
+}
+
+#[async_trait]
+impl ProxyFilter for RateLimitFilter {
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyCtx,
+    ) -> Result<FilterResult> {
+        let Some(host_config) = &ctx.host_config else {
+            return Ok(FilterResult::Continue);
+        };
+        let location = ctx.matched_location.as_ref();
+        let Some(config) = effective_config(host_config, location) else {
+            return Ok(FilterResult::Continue);
+        };
+
+        // Buckets are per route so a host and its locations limit independently
+        let key = format!(
+            "{}|{}",
+            balancer::pool_key(host_config, location),
+            client_key(session, &config.key)
+        );
+        let status = self.state.rate_limits.check(&key, config, Instant::now());
+        ctx.rate_limit = Some(status);
+        if status.allowed {
+            return Ok(FilterResult::Continue);
+        }
+
+        tracing::warn!("🚦 Rate limit exceeded for {}", ctx.host);
+        if grpc::is_grpc(session.req_header()) {
+            let headers = status
+                .headers()
+                .into_iter()
+                .chain([("Retry-After", status.retry_after_secs.to_string())])
+                .collect();
+            grpc::respond(session, constants::http::TOO_MANY_REQUESTS, headers).await?;
+            return Ok(FilterResult::Handled);
+        }
+        let mut header = match ResponseHeader::build(constants::http::TOO_MANY_REQUESTS, Some(5)) {
+            Ok(h) => h,
+            Err(e) => {
+                tracing::error!("Failed to build 429 response header: {}", e);
+                let _ = session.respond_error(constants::http::INTERNAL_ERROR).await;
+                return Ok(FilterResult::Handled);
+            }
+        };
+        let headers = status.headers().into_iter().chain([
+            ("Retry-After", status.retry_after_secs.to_string()),
+            ("Content-Length", "0".to_string()),
+        ]);
+        for (name, value) in headers {
+            if let Err(e) = header.insert_header(name, value) {
+                tracing::error!("Failed to insert {} header: {}", name, e);
+                let _ = session.respond_error(constants::http::INTERNAL_ERROR).await;
+                return Ok(FilterResult::Handled);
+            }
+        }
+        session
+            .write_response_header(Box::new(header), true)
+            .await?;
+        Ok(FilterResult::Handled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::RateLimitPeriod;
+    use std::time::Duration;
+
+    fn config(requests: u32, per: RateLimitPeriod, burst: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: true,
+            requests,
+            per,
+            burst,
+            key: RateLimitKey::ClientIp,
+        }
+    }
+
+    #[test]
+    fn bucket_allows_rate_plus_burst_then_refills() {
+        let limiter = RateLimiter::new();
+        let config = config(2, RateLimitPeriod::Second, 1);
+        let start = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            let status = limiter.check("client", &config, start);
+            assert!(status.allowed);
+            assert_eq!(status.remaining, remaining);
+        }
+        let limited = limiter.check("client", &config, start);
+        assert!(!limited.allowed);
+        assert_eq!(limited.retry_after_secs, 1);
+        assert_eq!(limited.reset_secs, 2);
+
+        // Other clients have their own bucket
+        assert!(limiter.check("other", &config, start).allowed);
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check("client", &config, later).allowed);
+        assert!(!limiter.check("client", &config, later).allowed);
+    }
+
+    #[test]
+    fn per_minute_limits_refill_slowly() {
+        let limiter = RateLimiter::new();
+        let config = config(60, RateLimitPeriod::Minute, 0);
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert!(limiter.check("client", &config, start).allowed);
+        }
+        assert!(!limiter.check("client", &config, start).allowed);
+        assert!(
+            limiter
+                .check("client", &config, start + Duration::from_secs(1))
+                .allowed
+        );
+        assert_eq!(
+            limiter
+                .check("client", &config, start + Duration::from_secs(1))
+                .reset_secs,
+            60
+        );
+    }
+
+    #[test]
+    fn basic_auth_username_is_extracted() {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        assert_eq!(basic_auth_user(&req), None);
+
+        req.insert_header("Authorization", "Basic YWxpY2U6c2VjcmV0")
+            .unwrap();
+        assert_eq!(basic_auth_user(&req).as_deref(), Some("alice"));
+
+        req.insert_header("Authorization", "Bearer token").unwrap();
+        assert_eq!(basic_auth_user(&req), None);
+    }
+}
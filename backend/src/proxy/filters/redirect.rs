@@ -14,8 +14,24 @@ impl ProxyFilter for RedirectFilter {
         ctx: &mut ProxyCtx,
     ) -> Result<FilterResult> {
         if let Some(host_config) = &ctx.host_config {
-            if let Some(redirect_target) = &host_config.redirect_to {
-                let status = host_config.redirect_status;
+            // A matched location's redirect replaces the host's
+            let location = ctx
+                .matched_location
+                .as_ref()
+                .filter(|loc| loc.redirect_to.is_some());
+            let redirect = match location {
+                Some(loc) => loc.redirect_to.as_ref().map(|to| {
+                    (
+                        to,
+                        loc.redirect_status.unwrap_or(host_config.redirect_status),
+                    )
+                }),
+                None => host_config
+                    .redirect_to
+                    .as_ref()
+                    .map(|to| (to, host_config.redirect_status)),
+            };
+            if let Some((redirect_target, status)) = redirect {
                 let path = session.req_header().uri.path();
                 let query = session
                     .req_header()
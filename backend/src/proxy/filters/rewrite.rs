@@ -0,0 +1,272 @@
+use super::{FilterResult, ProxyCtx, ProxyFilter};
+use crate::constants;
+use crate::state::{RewriteFlag, RewriteRule};
+use async_trait::async_trait;
+use pingora::http::ResponseHeader;
+use pingora::prelude::*;
+use regex::Captures;
+
+/// Result of running the rewrite rules of a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RewriteOutcome {
+    Unchanged,
+    /// New path and query for the upstream request.
+    Rewritten(String),
+    Redirect {
+        location: String,
+        status: u16,
+    },
+}
+
+/// Substitutes `$0`-`$9` with capture groups; other `$` are kept literally.
+fn expand(replacement: &str, caps: &Captures<'_>) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().and_then(|next| next.to_digit(10))) {
+            ('$', Some(group)) => {
+                chars.next();
+                out.push_str(caps.get(group as usize).map_or("", |m| m.as_str()));
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Runs the host rules, then the location rules, against the request path.
+///
+/// A replacement with a query replaces the path and prepends its arguments to
+/// the original query; a trailing `?` drops the original query.
+pub fn rewrite(
+    host_rules: &[RewriteRule],
+    location_rules: &[RewriteRule],
+    uri: &str,
+) -> RewriteOutcome {
+    let (mut path, mut query) = match uri.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (uri.to_string(), None),
+    };
+    let mut changed = false;
+
+    'lists: for rules in [host_rules, location_rules] {
+        for rule in rules {
+            let Some(caps) = rule.regex.as_ref().and_then(|re| re.captures(&path)) else {
+                continue;
+            };
+            let expanded = expand(&rule.replacement, &caps);
+            let (expanded, drop_query) = match expanded.strip_suffix('?') {
+                Some(stripped) => (stripped.to_string(), true),
+                None => (expanded, false),
+            };
+            let original_query = if drop_query { None } else { query.take() };
+            let (new_path, new_query) = match expanded.split_once('?') {
+                Some((new_path, args)) => {
+                    let merged = match original_query {
+                        Some(original) if !original.is_empty() => format!("{}&{}", args, original),
+                        _ => args.to_string(),
+                    };
+                    (new_path.to_string(), Some(merged))
+                }
+                None => (expanded, original_query),
+            };
+            path = new_path;
+            query = new_query.filter(|q| !q.is_empty());
+            changed = true;
+
+            let redirect_status = match rule.flag {
+                Some(RewriteFlag::Permanent) => Some(constants::http::MOVED_PERMANENTLY),
+                Some(RewriteFlag::Redirect) => Some(constants::http::FOUND),
+                _ if rule.is_absolute_url() => Some(constants::http::FOUND),
+                _ => None,
+            };
+            if let Some(status) = redirect_status {
+                return RewriteOutcome::Redirect {
+                    location: join(&path, query.as_deref()),
+                    status,
+                };
+            }
+            match rule.flag {
+                Some(RewriteFlag::Last) => break 'lists,
+                Some(RewriteFlag::Break) => continue 'lists,
+                _ => {}
+            }
+        }
+    }
+
+    if changed {
+        RewriteOutcome::Rewritten(join(&path, query.as_deref()))
+    } else {
+        RewriteOutcome::Unchanged
+    }
+}
+
+fn join(path: &str, query: Option<&str>) -> String {
+    match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    }
+}
+
+pub struct RewriteFilter;
+
+#[async_trait]
+impl ProxyFilter for RewriteFilter {
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyCtx,
+    ) -> Result<FilterResult> {
+        let Some(host_config) = &ctx.host_config else {
+            return Ok(FilterResult::Continue);
+        };
+        let location_rules = ctx
+            .matched_location
+            .as_ref()
+            .map_or(&[][..], |loc| &loc.rewrite_rules);
+        if host_config.rewrite_rules.is_empty() && location_rules.is_empty() {
+            return Ok(FilterResult::Continue);
+        }
+
+        let uri = session
+            .req_header()
+            .uri
+            .path_and_query()
+            .map_or("/", |pq| pq.as_str());
+        match rewrite(&host_config.rewrite_rules, location_rules, uri) {
+            RewriteOutcome::Unchanged => Ok(FilterResult::Continue),
+            RewriteOutcome::Rewritten(new_uri) => {
+                ctx.rewritten_uri = Some(new_uri);
+                Ok(FilterResult::Continue)
+            }
+            RewriteOutcome::Redirect { location, status } => {
+                let mut header = match ResponseHeader::build(status, Some(4)) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to build rewrite redirect header for {}: {}",
+                            ctx.host,
+                            e
+                        );
+                        let _ = session.respond_error(constants::http::INTERNAL_ERROR).await;
+                        return Ok(FilterResult::Handled);
+                    }
+                };
+                for (name, value) in [("Location", location), ("Content-Length", "0".to_string())] {
+                    if let Err(e) = header.insert_header(name, value) {
+                        tracing::error!("Failed to insert {} header for {}: {}", name, ctx.host, e);
+                        let _ = session.respond_error(constants::http::INTERNAL_ERROR).await;
+                        return Ok(FilterResult::Handled);
+                    }
+                }
+                session
+                    .write_response_header(Box::new(header), true)
+                    .await?;
+                Ok(FilterResult::Handled)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn rule(pattern: &str, replacement: &str, flag: Option<RewriteFlag>) -> RewriteRule {
+        RewriteRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            flag,
+            regex: Some(Regex::new(pattern).unwrap()),
+        }
+    }
+
+    #[test]
+    fn captures_and_query_strings_are_rewritten() {
+        let rules = [rule(r"^/user/(\d+)/profile$", "/profiles?id=$1", None)];
+        assert_eq!(
+            rewrite(&rules, &[], "/user/42/profile"),
+            RewriteOutcome::Rewritten("/profiles?id=42".to_string())
+        );
+        assert_eq!(
+            rewrite(&rules, &[], "/user/42/profile?tab=posts"),
+            RewriteOutcome::Rewritten("/profiles?id=42&tab=posts".to_string())
+        );
+        assert_eq!(
+            rewrite(&rules, &[], "/user/x/profile"),
+            RewriteOutcome::Unchanged
+        );
+
+        let drop_query = [rule(r"^/old/(.*)$", "/new/$1?", None)];
+        assert_eq!(
+            rewrite(&drop_query, &[], "/old/a/b?utm=1"),
+            RewriteOutcome::Rewritten("/new/a/b".to_string())
+        );
+
+        let keep_query = [rule(r"^/docs$", "/documentation", None)];
+        assert_eq!(
+            rewrite(&keep_query, &[], "/docs?page=2"),
+            RewriteOutcome::Rewritten("/documentation?page=2".to_string())
+        );
+    }
+
+    #[test]
+    fn flags_control_later_rules() {
+        let chain = [rule("^/a$", "/b", None), rule("^/b$", "/c", None)];
+        assert_eq!(
+            rewrite(&chain, &[], "/a"),
+            RewriteOutcome::Rewritten("/c".to_string())
+        );
+
+        let location = [rule("^/b$", "/location", None)];
+        let last = [
+            rule("^/a$", "/b", Some(RewriteFlag::Last)),
+            rule("^/b$", "/c", None),
+        ];
+        assert_eq!(
+            rewrite(&last, &location, "/a"),
+            RewriteOutcome::Rewritten("/b".to_string())
+        );
+
+        // A host `break` skips the host's remaining rules but not the location's
+        let brk = [
+            rule("^/a$", "/b", Some(RewriteFlag::Break)),
+            rule("^/b$", "/c", None),
+        ];
+        assert_eq!(
+            rewrite(&brk, &location, "/a"),
+            RewriteOutcome::Rewritten("/location".to_string())
+        );
+    }
+
+    #[test]
+    fn redirect_flags_and_absolute_urls_redirect() {
+        let permanent = [rule("^/old/(.*)$", "/new/$1", Some(RewriteFlag::Permanent))];
+        assert_eq!(
+            rewrite(&permanent, &[], "/old/page?x=1"),
+            RewriteOutcome::Redirect {
+                location: "/new/page?x=1".to_string(),
+                status: 301,
+            }
+        );
+
+        let absolute = [rule("^/(.*)$", "https://example.com/$1", None)];
+        assert_eq!(
+            rewrite(&absolute, &[], "/path"),
+            RewriteOutcome::Redirect {
+                location: "https://example.com/path".to_string(),
+                status: 302,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_references_expand_to_literal_dollars() {
+        let rules = [rule("^/price/(\\d+)$", "/cost?amount=$$1&c=$x", None)];
+        assert_eq!(
+            rewrite(&rules, &[], "/price/5"),
+            RewriteOutcome::Rewritten("/cost?amount=$5&c=$x".to_string())
+        );
+    }
+}
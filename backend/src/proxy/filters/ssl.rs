@@ -14,6 +14,24 @@ fn configured_tls_port() -> Option<u16> {
         .and_then(|p| p.parse::<u16>().ok())
 }
 
+/// Whether the client reached the proxy over TLS: on the TLS listener, or
+/// through a trusted proxy reporting `https`.
+pub fn is_tls_request(session: &Session) -> bool {
+    if trusted_proxy::forwarded_proto_is_https(session) {
+        return true;
+    }
+
+    let tls_port = configured_tls_port();
+    session
+        .server_addr()
+        .map(|a| {
+            a.as_inet()
+                .map(|s| tls_port.map(|p| s.port() == p).unwrap_or(false))
+                .unwrap_or(false)
+        })
+        .unwrap_or(false)
+}
+
 #[async_trait]
 impl ProxyFilter for SslFilter {
     async fn request_filter(
@@ -22,23 +40,7 @@ impl ProxyFilter for SslFilter {
         ctx: &mut ProxyCtx,
     ) -> Result<FilterResult> {
         if let Some(host_config) = &ctx.host_config {
-            let forwarded_proto_is_https = trusted_proxy::forwarded_proto_is_https(session);
-
-            let is_tls = if forwarded_proto_is_https {
-                true
-            } else {
-                let tls_port = configured_tls_port();
-                session
-                    .server_addr()
-                    .map(|a| {
-                        a.as_inet()
-                            .map(|s| tls_port.map(|p| s.port() == p).unwrap_or(false))
-                            .unwrap_or(false)
-                    })
-                    .unwrap_or(false)
-            };
-
-            if host_config.ssl_forced && !is_tls {
+            if host_config.ssl_forced && !is_tls_request(session) {
                 let path = session.req_header().uri.path();
                 let query = session
                     .req_header()
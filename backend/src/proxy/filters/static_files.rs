@@ -0,0 +1,548 @@
+use super::{FilterResult, ProxyCtx, ProxyFilter};
+use crate::constants;
+use crate::proxy::strip_location_prefix;
+use crate::state::StaticFilesConfig;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http::header;
+use pingora::http::ResponseHeader;
+use pingora::prelude::*;
+use std::fs::Metadata;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Decodes `%XX` escapes; `None` for malformed escapes or non UTF-8 results.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            out.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Maps a request path onto `root`. `None` when a segment could leave the
+/// root: `..`, backslashes or NUL bytes, also when percent-encoded.
+pub fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(request_path)?;
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains(['\\', '\0']) => return None,
+            s => path.push(s),
+        }
+    }
+    Some(path)
+}
+
+/// What a `Range` header asks for, given the file length.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    Full,
+    /// Inclusive first and last byte.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=` range. Multiple ranges and malformed headers are
+/// ignored and the whole file is sent, as RFC 9110 allows.
+pub fn parse_range(value: Option<&str>, len: u64) -> ByteRange {
+    let Some(spec) = value.and_then(|v| v.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial(len.saturating_sub(n), len - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = if end.is_empty() {
+        None
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => Some(end),
+            _ => return ByteRange::Full,
+        }
+    };
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end.map_or(len - 1, |end| end.min(len - 1)))
+}
+
+/// `Content-Type` for a file, by extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "webmanifest" => "application/manifest+json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        _ => "application/octet-stream",
+    }
+}
+
+enum Lookup {
+    File(PathBuf, Metadata),
+    /// A directory requested without its trailing slash.
+    AddSlash,
+    NotFound,
+    Forbidden,
+}
+
+async fn regular_file(path: PathBuf) -> Option<(PathBuf, Metadata)> {
+    let meta = fs::metadata(&path).await.ok()?;
+    meta.is_file().then_some((path, meta))
+}
+
+async fn index_file(config: &StaticFilesConfig, dir: &Path) -> Option<(PathBuf, Metadata)> {
+    for name in &config.index {
+        if let Some(found) = regular_file(dir.join(name)).await {
+            return Some(found);
+        }
+    }
+    None
+}
+
+async fn lookup(config: &StaticFilesConfig, request_path: &str) -> Lookup {
+    let root = Path::new(&config.root);
+    let Some(candidate) = resolve(root, request_path) else {
+        return Lookup::Forbidden;
+    };
+
+    let found = match fs::metadata(&candidate).await {
+        Ok(meta) if meta.is_file() => Some((candidate, meta)),
+        Ok(meta) if meta.is_dir() => {
+            if !request_path.ends_with('/') {
+                return Lookup::AddSlash;
+            }
+            index_file(config, &candidate).await
+        }
+        _ => None,
+    };
+    let found = match found {
+        Some(found) => Some(found),
+        None if config.spa_fallback => index_file(config, root).await,
+        None => None,
+    };
+    let Some((path, meta)) = found else {
+        return Lookup::NotFound;
+    };
+
+    // Symlinks must not lead out of the root
+    let (Ok(canonical_root), Ok(canonical)) =
+        (fs::canonicalize(root).await, fs::canonicalize(&path).await)
+    else {
+        return Lookup::NotFound;
+    };
+    if !canonical.starts_with(&canonical_root) {
+        return Lookup::Forbidden;
+    }
+    Lookup::File(path, meta)
+}
+
+fn etag(meta: &Metadata) -> String {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    format!("\"{:x}-{:x}\"", meta.len(), mtime)
+}
+
+fn not_modified(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: &str,
+    modified: Option<DateTime<Utc>>,
+) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(tags) = if_none_match {
+        return tags
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == "*" || t == etag);
+    }
+    match (if_modified_since, modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .is_ok_and(|since| modified.timestamp() <= since.timestamp()),
+        _ => false,
+    }
+}
+
+fn response_header(
+    status: u16,
+    headers: Vec<(header::HeaderName, String)>,
+) -> Result<ResponseHeader> {
+    let mut resp = ResponseHeader::build(status, Some(headers.len()))?;
+    for (name, value) in headers {
+        resp.insert_header(name, value)?;
+    }
+    Ok(resp)
+}
+
+async fn send_file(session: &mut Session, path: &Path, start: u64, len: u64) -> Result<()> {
+    let mut file = fs::File::open(path).await.map_err(|e| {
+        Error::explain(
+            ErrorType::InternalError,
+            format!("Failed to open {}: {}", path.display(), e),
+        )
+    })?;
+    if start > 0 {
+        file.seek(SeekFrom::Start(start)).await.map_err(|e| {
+            Error::explain(
+                ErrorType::InternalError,
+                format!("Failed to seek {}: {}", path.display(), e),
+            )
+        })?;
+    }
+
+    let mut remaining = len;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    while remaining > 0 {
+        let want = buf.len().min(remaining as usize);
+        let read = file.read(&mut buf[..want]).await.map_err(|e| {
+            Error::explain(
+                ErrorType::InternalError,
+                format!("Failed to read {}: {}", path.display(), e),
+            )
+        })?;
+        if read == 0 {
+            // File shrank while being sent
+            return Err(Error::explain(
+                ErrorType::InternalError,
+                format!("Unexpected end of {}", path.display()),
+            ));
+        }
+        remaining -= read as u64;
+        session
+            .write_response_body(Some(Bytes::copy_from_slice(&buf[..read])), remaining == 0)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Serves files from disk for locations with `static_files`, instead of
+/// proxying them.
+pub struct StaticFilesFilter;
+
+#[async_trait]
+impl ProxyFilter for StaticFilesFilter {
+    async fn request_filter(
+        &self,
+        session: &mut Session,
+        ctx: &mut ProxyCtx,
+    ) -> Result<FilterResult> {
+        let Some(loc) = ctx.matched_location.as_ref() else {
+            return Ok(FilterResult::Continue);
+        };
+        let Some(config) = loc.static_files.as_ref() else {
+            return Ok(FilterResult::Continue);
+        };
+
+        let req = session.req_header();
+        let is_head = req.method == http::Method::HEAD;
+        if req.method != http::Method::GET && !is_head {
+            let resp = response_header(
+                constants::http::METHOD_NOT_ALLOWED,
+                vec![
+                    (header::ALLOW, "GET, HEAD".to_string()),
+                    (header::CONTENT_LENGTH, "0".to_string()),
+                ],
+            )?;
+            session.write_response_header(Box::new(resp), true).await?;
+            return Ok(FilterResult::Handled);
+        }
+
+        let path = match &ctx.rewritten_uri {
+            Some(uri) => uri.split('?').next().unwrap_or("/").to_string(),
+            None => req.uri.path().to_string(),
+        };
+        let path = strip_location_prefix(loc, &path).unwrap_or(path);
+
+        let (file, meta) = match lookup(config, &path).await {
+            Lookup::File(file, meta) => (file, meta),
+            Lookup::AddSlash => {
+                let location = match req.uri.query() {
+                    Some(query) => format!("{}/?{}", req.uri.path(), query),
+                    None => format!("{}/", req.uri.path()),
+                };
+                let resp = response_header(
+                    constants::http::MOVED_PERMANENTLY,
+                    vec![
+                        (header::LOCATION, location),
+                        (header::CONTENT_LENGTH, "0".to_string()),
+                    ],
+                )?;
+                session.write_response_header(Box::new(resp), true).await?;
+                return Ok(FilterResult::Handled);
+            }
+            Lookup::Forbidden => {
+                tracing::warn!(
+                    "⚠️ Rejected static file path outside root for {}: {}",
+                    ctx.host,
+                    path
+                );
+                session.respond_error(constants::http::FORBIDDEN).await?;
+                return Ok(FilterResult::Handled);
+            }
+            Lookup::NotFound => {
+                session.respond_error(constants::http::NOT_FOUND).await?;
+                return Ok(FilterResult::Handled);
+            }
+        };
+
+        let etag = etag(&meta);
+        let modified = meta.modified().ok().map(DateTime::<Utc>::from);
+        let header_str =
+            |name: header::HeaderName| req.headers.get(name).and_then(|v| v.to_str().ok());
+
+        let mut headers = vec![
+            (header::ETAG, etag.clone()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ];
+        if let Some(modified) = modified {
+            headers.push((
+                header::LAST_MODIFIED,
+                modified.format(HTTP_DATE).to_string(),
+            ));
+        }
+
+        if not_modified(
+            header_str(header::IF_NONE_MATCH),
+            header_str(header::IF_MODIFIED_SINCE),
+            &etag,
+            modified,
+        ) {
+            let resp = response_header(constants::http::NOT_MODIFIED, headers)?;
+            session.write_response_header(Box::new(resp), true).await?;
+            return Ok(FilterResult::Handled);
+        }
+
+        let len = meta.len();
+        // A stale If-Range validator means the client wants the whole file
+        let range = match header_str(header::IF_RANGE) {
+            Some(validator) if validator != etag => ByteRange::Full,
+            _ => parse_range(header_str(header::RANGE), len),
+        };
+        let (status, start, count) = match range {
+            ByteRange::Full => (constants::http::OK, 0, len),
+            ByteRange::Partial(start, end) => {
+                headers.push((
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                ));
+                (constants::http::PARTIAL_CONTENT, start, end - start + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                headers.push((header::CONTENT_RANGE, format!("bytes */{}", len)));
+                headers.push((header::CONTENT_LENGTH, "0".to_string()));
+                let resp = response_header(constants::http::RANGE_NOT_SATISFIABLE, headers)?;
+                session.write_response_header(Box::new(resp), true).await?;
+                return Ok(FilterResult::Handled);
+            }
+        };
+        headers.push((header::CONTENT_TYPE, content_type(&file).to_string()));
+        headers.push((header::CONTENT_LENGTH, count.to_string()));
+
+        let resp = response_header(status, headers)?;
+        let end_of_stream = is_head || count == 0;
+        session
+            .write_response_header(Box::new(resp), end_of_stream)
+            .await?;
+        if !end_of_stream {
+            send_file(session, &file, start, count).await?;
+        }
+        Ok(FilterResult::Handled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_cannot_leave_the_root() {
+        let root = Path::new("/srv/www");
+        assert_eq!(
+            resolve(root, "/assets/app%20v2.js"),
+            Some(PathBuf::from("/srv/www/assets/app v2.js"))
+        );
+        assert_eq!(
+            resolve(root, "/./a//b/"),
+            Some(PathBuf::from("/srv/www/a/b"))
+        );
+        assert_eq!(resolve(root, "/../etc/passwd"), None);
+        assert_eq!(resolve(root, "/a/%2e%2e/%2e%2e/etc/passwd"), None);
+        assert_eq!(resolve(root, "/a/..%5c..%5cetc"), None);
+        assert_eq!(resolve(root, "/a%00.html"), None);
+        assert_eq!(resolve(root, "/bad%zz"), None);
+    }
+
+    #[test]
+    fn single_byte_ranges_are_parsed() {
+        assert_eq!(parse_range(None, 100), ByteRange::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            ByteRange::Partial(0, 9)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-500"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            ByteRange::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-500"), 100),
+            ByteRange::Partial(0, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=-0"), 100), ByteRange::Unsatisfiable);
+        // Ignored: multiple ranges, other units and malformed values
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), ByteRange::Full);
+        assert_eq!(parse_range(Some("bytes=9-1"), 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn conditional_requests_match_validators() {
+        let modified = DateTime::parse_from_rfc2822("Tue, 15 Nov 1994 08:12:31 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let etag = "\"64-2ec3d4f\"";
+        assert!(not_modified(
+            Some("\"x\", W/\"64-2ec3d4f\""),
+            None,
+            etag,
+            None
+        ));
+        assert!(not_modified(Some("*"), None, etag, None));
+        assert!(!not_modified(
+            Some("\"x\""),
+            Some("Tue, 15 Nov 1994 08:12:31 GMT"),
+            etag,
+            Some(modified)
+        ));
+        assert!(not_modified(
+            None,
+            Some("Tue, 15 Nov 1994 08:12:31 GMT"),
+            etag,
+            Some(modified)
+        ));
+        assert!(!not_modified(
+            None,
+            Some("Mon, 14 Nov 1994 08:12:31 GMT"),
+            etag,
+            Some(modified)
+        ));
+        assert_eq!(
+            modified.format(HTTP_DATE).to_string(),
+            "Tue, 15 Nov 1994 08:12:31 GMT"
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_serves_index_files_and_spa_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("index.html"), "root").unwrap();
+        std::fs::write(dir.path().join("docs/index.html"), "docs").unwrap();
+        let mut config = StaticFilesConfig {
+            root: dir.path().to_string_lossy().into_owned(),
+            index: vec!["index.html".to_string()],
+            spa_fallback: false,
+        };
+
+        let served = |lookup: Lookup| match lookup {
+            Lookup::File(path, _) => Some(path),
+            _ => None,
+        };
+
+        assert_eq!(
+            served(lookup(&config, "/docs/").await),
+            Some(dir.path().join("docs/index.html"))
+        );
+        assert!(matches!(lookup(&config, "/docs").await, Lookup::AddSlash));
+        assert!(matches!(
+            lookup(&config, "/app/route").await,
+            Lookup::NotFound
+        ));
+
+        config.spa_fallback = true;
+        assert_eq!(
+            served(lookup(&config, "/app/route").await),
+            Some(dir.path().join("index.html"))
+        );
+        assert_eq!(
+            content_type(Path::new("a/b.JS")),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("a/b")), "application/octet-stream");
+    }
+}
@@ -57,6 +57,18 @@ pub fn forwarded_proto_is_https(session: &Session) -> bool {
         .unwrap_or(false)
 }
 
+/// `https` when the client connected over TLS, directly or through a trusted proxy.
+pub fn effective_scheme(session: &Session) -> &'static str {
+    let direct_tls = session
+        .digest()
+        .is_some_and(|digest| digest.ssl_digest.is_some());
+    if direct_tls || forwarded_proto_is_https(session) {
+        "https"
+    } else {
+        "http"
+    }
+}
+
 pub fn effective_client_ip(session: &Session) -> Option<IpAddr> {
     if is_trusted_proxy_hop(session) {
         if let Some(forwarded_for) = session
@@ -0,0 +1,245 @@
+use super::filters::{ssl, trusted_proxy};
+use crate::state::{ForwardedHeader, ForwardedHeadersConfig, ForwardedMode};
+use http::header::HOST;
+use pingora::http::RequestHeader;
+use pingora::prelude::*;
+use std::net::IpAddr;
+
+/// What the proxy knows about the hop a request arrived on.
+pub struct Hop<'a> {
+    /// Address of the connected peer.
+    pub peer: Option<IpAddr>,
+    /// Original client, resolved through trusted proxies.
+    pub client: Option<IpAddr>,
+    /// Whether the peer is a trusted proxy.
+    pub trusted: bool,
+    pub proto: &'static str,
+    pub host: &'a str,
+}
+
+impl<'a> Hop<'a> {
+    pub fn new(session: &'a Session) -> Self {
+        let req = session.req_header();
+        let host = req
+            .headers
+            .get(HOST)
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| req.uri.authority().map(|a| a.as_str()))
+            .unwrap_or_default();
+
+        Self {
+            peer: trusted_proxy::downstream_client_ip(session),
+            client: trusted_proxy::effective_client_ip(session),
+            trusted: trusted_proxy::is_trusted_proxy_hop(session),
+            proto: if ssl::is_tls_request(session) {
+                "https"
+            } else {
+                "http"
+            },
+            host,
+        }
+    }
+}
+
+/// RFC 7239 node: IPv6 addresses are bracketed and quoted.
+fn forwarded_node(ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(IpAddr::V4(ip)) => ip.to_string(),
+        Some(IpAddr::V6(ip)) => format!("\"[{}]\"", ip),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Quotes a `Forwarded` parameter value unless it is a plain token.
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// All incoming values of a header, comma-joined.
+fn incoming(req: &RequestHeader, name: &str) -> Option<String> {
+    let values: Vec<&str> = req
+        .headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Adds the configured forwarding headers to an upstream request. Incoming
+/// values are extended with this hop when `mode` keeps them, and replaced
+/// with the original client's otherwise.
+pub fn apply(
+    config: &ForwardedHeadersConfig,
+    hop: &Hop<'_>,
+    req: &mut RequestHeader,
+) -> Result<()> {
+    let keep_incoming = match config.mode {
+        ForwardedMode::Append => true,
+        ForwardedMode::ReplaceUntrusted => hop.trusted,
+        ForwardedMode::Replace => false,
+    };
+
+    for header in &config.headers {
+        let name = header.name();
+        let prior = if keep_incoming {
+            incoming(req, name)
+        } else {
+            None
+        };
+
+        let value = match header {
+            ForwardedHeader::XForwardedFor => match (prior, hop.peer) {
+                (Some(prior), Some(peer)) => Some(format!("{}, {}", prior, peer)),
+                (Some(prior), None) => Some(prior),
+                (None, _) if keep_incoming => hop.peer.map(|ip| ip.to_string()),
+                (None, _) => hop.client.map(|ip| ip.to_string()),
+            },
+            ForwardedHeader::XRealIp => hop.client.map(|ip| ip.to_string()),
+            ForwardedHeader::XForwardedProto => prior.or_else(|| Some(hop.proto.to_string())),
+            ForwardedHeader::XForwardedHost => {
+                prior.or_else(|| (!hop.host.is_empty()).then(|| hop.host.to_string()))
+            }
+            ForwardedHeader::Forwarded => {
+                let node = if keep_incoming { hop.peer } else { hop.client };
+                let mut element = format!("for={}", forwarded_node(node));
+                if !hop.host.is_empty() {
+                    element.push_str(&format!(";host={}", forwarded_value(hop.host)));
+                }
+                element.push_str(&format!(";proto={}", hop.proto));
+                Some(match prior {
+                    Some(prior) => format!("{}, {}", prior, element),
+                    None => element,
+                })
+            }
+        };
+
+        match value {
+            Some(value) => req.insert_header(name, value)?,
+            None => {
+                let _ = req.remove_header(name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: ForwardedMode, headers: Vec<ForwardedHeader>) -> ForwardedHeadersConfig {
+        ForwardedHeadersConfig {
+            enabled: true,
+            mode,
+            headers,
+        }
+    }
+
+    fn hop(trusted: bool) -> Hop<'static> {
+        Hop {
+            peer: Some("10.0.0.5".parse().unwrap()),
+            client: Some(
+                if trusted { "203.0.113.7" } else { "10.0.0.5" }
+                    .parse()
+                    .unwrap(),
+            ),
+            trusted,
+            proto: "https",
+            host: "example.com",
+        }
+    }
+
+    fn request() -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        req.insert_header("X-Forwarded-For", "203.0.113.7").unwrap();
+        req.insert_header("X-Forwarded-Proto", "http").unwrap();
+        req.insert_header("Forwarded", "for=203.0.113.7").unwrap();
+        req
+    }
+
+    fn header<'a>(req: &'a RequestHeader, name: &str) -> Option<&'a str> {
+        req.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    #[test]
+    fn trusted_hops_are_appended_and_untrusted_values_replaced() {
+        let all = vec![
+            ForwardedHeader::XForwardedFor,
+            ForwardedHeader::XForwardedProto,
+            ForwardedHeader::XForwardedHost,
+            ForwardedHeader::XRealIp,
+            ForwardedHeader::Forwarded,
+        ];
+        let config = config(ForwardedMode::ReplaceUntrusted, all);
+
+        let mut req = request();
+        apply(&config, &hop(true), &mut req).unwrap();
+        assert_eq!(
+            header(&req, "X-Forwarded-For"),
+            Some("203.0.113.7, 10.0.0.5")
+        );
+        assert_eq!(header(&req, "X-Forwarded-Proto"), Some("http"));
+        assert_eq!(header(&req, "X-Forwarded-Host"), Some("example.com"));
+        assert_eq!(header(&req, "X-Real-IP"), Some("203.0.113.7"));
+        assert_eq!(
+            header(&req, "Forwarded"),
+            Some("for=203.0.113.7, for=10.0.0.5;host=example.com;proto=https")
+        );
+
+        let mut req = request();
+        apply(&config, &hop(false), &mut req).unwrap();
+        assert_eq!(header(&req, "X-Forwarded-For"), Some("10.0.0.5"));
+        assert_eq!(header(&req, "X-Forwarded-Proto"), Some("https"));
+        assert_eq!(header(&req, "X-Real-IP"), Some("10.0.0.5"));
+        assert_eq!(
+            header(&req, "Forwarded"),
+            Some("for=10.0.0.5;host=example.com;proto=https")
+        );
+    }
+
+    #[test]
+    fn modes_control_whether_incoming_values_are_kept() {
+        let xff = vec![ForwardedHeader::XForwardedFor];
+
+        let mut req = request();
+        apply(
+            &config(ForwardedMode::Append, xff.clone()),
+            &hop(false),
+            &mut req,
+        )
+        .unwrap();
+        assert_eq!(
+            header(&req, "X-Forwarded-For"),
+            Some("203.0.113.7, 10.0.0.5")
+        );
+
+        // Replace collapses the chain to the client resolved through trusted proxies
+        let mut req = request();
+        apply(&config(ForwardedMode::Replace, xff), &hop(true), &mut req).unwrap();
+        assert_eq!(header(&req, "X-Forwarded-For"), Some("203.0.113.7"));
+        // Headers that are not configured are left alone
+        assert_eq!(header(&req, "X-Forwarded-Proto"), Some("http"));
+    }
+
+    #[test]
+    fn forwarded_values_are_quoted_when_needed() {
+        assert_eq!(
+            forwarded_node(Some("2001:db8::1".parse().unwrap())),
+            "\"[2001:db8::1]\""
+        );
+        assert_eq!(forwarded_node(None), "unknown");
+        assert_eq!(forwarded_value("example.com"), "example.com");
+        assert_eq!(forwarded_value("example.com:8443"), "\"example.com:8443\"");
+    }
+}
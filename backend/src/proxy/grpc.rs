@@ -0,0 +1,123 @@
+use pingora::http::{RequestHeader, ResponseHeader};
+use pingora::prelude::*;
+
+pub const CONTENT_TYPE: &str = "application/grpc";
+
+/// gRPC status codes used for proxy-generated errors.
+pub mod status {
+    pub const UNKNOWN: u8 = 2;
+    pub const PERMISSION_DENIED: u8 = 7;
+    pub const RESOURCE_EXHAUSTED: u8 = 8;
+    pub const UNIMPLEMENTED: u8 = 12;
+    pub const INTERNAL: u8 = 13;
+    pub const UNAVAILABLE: u8 = 14;
+    pub const UNAUTHENTICATED: u8 = 16;
+}
+
+/// Whether the request is a gRPC call (`application/grpc`, `application/grpc+proto`, ...).
+pub fn is_grpc(req: &RequestHeader) -> bool {
+    req.headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix(CONTENT_TYPE))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['+', ';']))
+}
+
+/// gRPC status for an HTTP status, following the gRPC HTTP-to-status mapping.
+pub fn status_for_http(code: u16) -> u8 {
+    match code {
+        400 => status::INTERNAL,
+        401 => status::UNAUTHENTICATED,
+        403 => status::PERMISSION_DENIED,
+        404 => status::UNIMPLEMENTED,
+        413 => status::RESOURCE_EXHAUSTED,
+        429 | 502..=504 => status::UNAVAILABLE,
+        _ => status::UNKNOWN,
+    }
+}
+
+/// Percent-encodes a `grpc-message` value as the gRPC spec requires.
+fn encode_message(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Builds a trailers-only gRPC response: HTTP 200 with the status in the
+/// headers, so clients report the error instead of a protocol failure.
+pub fn error_header(code: u16, extra: Vec<(&'static str, String)>) -> Result<ResponseHeader> {
+    let message = http::StatusCode::from_u16(code)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Error");
+
+    let mut header = ResponseHeader::build(200, Some(4 + extra.len()))?;
+    header.insert_header("Content-Type", CONTENT_TYPE)?;
+    header.insert_header("grpc-status", status_for_http(code).to_string())?;
+    header.insert_header("grpc-message", encode_message(message))?;
+    header.insert_header("Cache-Control", "no-store")?;
+    for (name, value) in extra {
+        header.insert_header(name, value)?;
+    }
+    Ok(header)
+}
+
+/// Answers a gRPC call with the gRPC status for the HTTP status `code`.
+pub async fn respond(
+    session: &mut Session,
+    code: u16,
+    extra: Vec<(&'static str, String)>,
+) -> Result<()> {
+    let header = error_header(code, extra)?;
+    session.write_response_header(Box::new(header), true).await
+}
+
+/// Like `Session::respond_error`, but answers gRPC calls with a gRPC status.
+pub async fn respond_error(session: &mut Session, code: u16) -> Result<()> {
+    if is_grpc(session.req_header()) {
+        respond(session, code, Vec::new()).await
+    } else {
+        session.respond_error(code).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content_type: &str) -> RequestHeader {
+        let mut req = RequestHeader::build("POST", b"/pkg.Service/Method", None).unwrap();
+        req.insert_header("Content-Type", content_type).unwrap();
+        req
+    }
+
+    #[test]
+    fn grpc_requests_are_detected_by_content_type() {
+        assert!(is_grpc(&request("application/grpc")));
+        assert!(is_grpc(&request("application/grpc+proto")));
+        assert!(!is_grpc(&request("application/grpc-web")));
+        assert!(!is_grpc(&request("application/json")));
+    }
+
+    #[test]
+    fn proxy_errors_become_trailers_only_responses() {
+        let header = error_header(503, vec![("Retry-After", "30".to_string())]).unwrap();
+        assert_eq!(header.status.as_u16(), 200);
+        assert_eq!(header.headers.get("grpc-status").unwrap(), "14");
+        assert_eq!(
+            header.headers.get("grpc-message").unwrap(),
+            "Service Unavailable"
+        );
+        assert_eq!(header.headers.get("Retry-After").unwrap(), "30");
+
+        assert_eq!(status_for_http(403), status::PERMISSION_DENIED);
+        assert_eq!(status_for_http(500), status::UNKNOWN);
+        assert_eq!(encode_message("100% bad"), "100%25 bad");
+    }
+}
@@ -0,0 +1,216 @@
+use super::filters::trusted_proxy;
+use super::ProxyCtx;
+use pingora::prelude::*;
+
+/// Variables usable in custom header values, as `$name` or `${name}`.
+pub const VARIABLES: &[&str] = &[
+    "remote_addr",
+    "host",
+    "scheme",
+    "request_id",
+    "request_uri",
+    "upstream_addr",
+    "ssl_client_o",
+];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    Literal(&'a str),
+    Var(&'a str),
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Splits a value into literal text and variable references. A `$` not
+/// followed by a name is kept as is. There is no escape for `$`, so values
+/// saved before variables existed keep their meaning.
+fn parse(value: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = value;
+    while let Some(pos) = rest.find('$') {
+        if pos > 0 {
+            parts.push(Part::Literal(&rest[..pos]));
+        }
+        let after = &rest[pos + 1..];
+
+        if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) if braced[..end].chars().all(is_name_char) && end > 0 => {
+                    parts.push(Part::Var(&braced[..end]));
+                    rest = &braced[end + 1..];
+                }
+                _ => {
+                    parts.push(Part::Literal("$"));
+                    rest = after;
+                }
+            }
+        } else if after.starts_with(is_name_start) {
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            parts.push(Part::Var(&after[..end]));
+            rest = &after[end..];
+        } else {
+            parts.push(Part::Literal("$"));
+            rest = after;
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    parts
+}
+
+/// Rejects references to unknown variables.
+pub fn validate(value: &str) -> Result<(), String> {
+    for part in parse(value) {
+        if let Part::Var(name) = part {
+            if !VARIABLES.contains(&name) {
+                return Err(format!(
+                    "Unknown header variable '${}' (supported: {})",
+                    name,
+                    VARIABLES.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Variable values of the request being proxied.
+pub struct HeaderVars<'a> {
+    remote_addr: String,
+    host: &'a str,
+    scheme: &'static str,
+    request_id: &'a str,
+    request_uri: &'a str,
+    upstream_addr: &'a str,
+    ssl_client_o: String,
+}
+
+impl<'a> HeaderVars<'a> {
+    pub fn new(session: &'a Session, ctx: &'a ProxyCtx) -> Self {
+        let req = session.req_header();
+        // Pingora's TLS digest only carries the organization of the client
+        // certificate, not its full subject
+        let ssl_client_o = session
+            .digest()
+            .and_then(|digest| digest.ssl_digest.as_ref())
+            .and_then(|ssl| ssl.organization.clone())
+            .unwrap_or_default();
+
+        Self {
+            remote_addr: trusted_proxy::effective_client_ip(session)
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+            host: &ctx.host,
+            scheme: trusted_proxy::effective_scheme(session),
+            request_id: &ctx.request_id,
+            request_uri: req.uri.path_and_query().map_or("/", |pq| pq.as_str()),
+            upstream_addr: ctx
+                .attempted_targets
+                .last()
+                .map(String::as_str)
+                .unwrap_or_default(),
+            ssl_client_o,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "remote_addr" => Some(&self.remote_addr),
+            "host" => Some(self.host),
+            "scheme" => Some(self.scheme),
+            "request_id" => Some(self.request_id),
+            "request_uri" => Some(self.request_uri),
+            "upstream_addr" => Some(self.upstream_addr),
+            "ssl_client_o" => Some(&self.ssl_client_o),
+            _ => None,
+        }
+    }
+
+    /// Interpolates variables. Unknown names are kept verbatim so values saved
+    /// before variables were supported do not change.
+    pub fn expand(&self, value: &str) -> String {
+        if !value.contains('$') {
+            return value.to_string();
+        }
+        let mut out = String::with_capacity(value.len());
+        for part in parse(value) {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Var(name) => match self.get(name) {
+                    Some(v) => out.push_str(v),
+                    None => {
+                        out.push('$');
+                        out.push_str(name);
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HeaderVars<'static> {
+        HeaderVars {
+            remote_addr: "203.0.113.7".to_string(),
+            host: "example.com",
+            scheme: "https",
+            request_id: "abc123",
+            request_uri: "/search?q=1",
+            upstream_addr: "10.0.0.1:3000",
+            ssl_client_o: String::new(),
+        }
+    }
+
+    #[test]
+    fn parses_plain_and_braced_references() {
+        assert_eq!(
+            parse("a $host-${scheme}x $ $1"),
+            vec![
+                Part::Literal("a "),
+                Part::Var("host"),
+                Part::Literal("-"),
+                Part::Var("scheme"),
+                Part::Literal("x "),
+                Part::Literal("$"),
+                Part::Literal(" "),
+                Part::Literal("$"),
+                Part::Literal("1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn validation_rejects_unknown_variables() {
+        assert!(validate("$remote_addr, ${request_id}").is_ok());
+        assert!(validate("costs $5").is_ok());
+        assert!(validate("$remote_user").is_err());
+        assert!(validate("${nope}").is_err());
+    }
+
+    #[test]
+    fn expansion_substitutes_request_values() {
+        let vars = vars();
+        assert_eq!(
+            vars.expand("$scheme://$host$request_uri"),
+            "https://example.com/search?q=1"
+        );
+        assert_eq!(
+            vars.expand("for=$remote_addr;id=${request_id}"),
+            "for=203.0.113.7;id=abc123"
+        );
+        assert_eq!(vars.expand("$upstream_addr"), "10.0.0.1:3000");
+        // Values saved before variables existed are left unchanged
+        assert_eq!(vars.expand("$legacy a$$b plain"), "$legacy a$$b plain");
+    }
+}
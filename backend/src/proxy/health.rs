@@ -0,0 +1,482 @@
+use super::{configure_upstream_protocol, configure_upstream_tls, resolve_peer, target_groups};
+use crate::state::{
+    AppState, HealthCheckConfig, HealthCheckKind, ProxyConfig, UpstreamProtocol, UpstreamTarget,
+    UpstreamTlsConfig, REGEX_HOST_PREFIX, WILDCARD_HOST_PREFIX,
+};
+use pingora::connectors::http::Connector as HttpConnector;
+use pingora::connectors::TransportConnector;
+use pingora::http::RequestHeader;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Probe state of one target within one pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetHealth {
+    pub healthy: bool,
+    pub consecutive_successes: u32,
+    pub consecutive_failures: u32,
+    /// Unix timestamp (seconds) of the last completed probe.
+    pub last_checked: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl Default for TargetHealth {
+    // Targets start healthy so a new pool takes traffic before its first probe
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            consecutive_successes: 0,
+            consecutive_failures: 0,
+            last_checked: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Health of every probed target, keyed by pool and target address.
+#[derive(Default)]
+pub struct HealthRegistry {
+    pools: RwLock<HashMap<String, HashMap<String, TargetHealth>>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Targets that were never probed count as healthy.
+    pub fn is_healthy(&self, pool_key: &str, addr: &str) -> bool {
+        self.pools
+            .read()
+            .unwrap()
+            .get(pool_key)
+            .and_then(|targets| targets.get(addr))
+            .is_none_or(|health| health.healthy)
+    }
+
+    pub fn status(&self, pool_key: &str, addr: &str) -> Option<TargetHealth> {
+        self.pools
+            .read()
+            .unwrap()
+            .get(pool_key)
+            .and_then(|targets| targets.get(addr))
+            .cloned()
+    }
+
+    /// Applies one probe result using the check's rise/fall thresholds.
+    /// Returns the new health state when it flipped.
+    pub fn record(
+        &self,
+        pool_key: &str,
+        addr: &str,
+        check: &HealthCheckConfig,
+        result: Result<(), String>,
+        now: i64,
+    ) -> Option<bool> {
+        let mut pools = self.pools.write().unwrap();
+        let health = pools
+            .entry(pool_key.to_string())
+            .or_default()
+            .entry(addr.to_string())
+            .or_default();
+        health.last_checked = Some(now);
+
+        match result {
+            Ok(()) => {
+                health.consecutive_successes = health.consecutive_successes.saturating_add(1);
+                health.consecutive_failures = 0;
+                health.last_error = None;
+                if !health.healthy && health.consecutive_successes >= check.rise {
+                    health.healthy = true;
+                    return Some(true);
+                }
+            }
+            Err(e) => {
+                health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+                health.consecutive_successes = 0;
+                health.last_error = Some(e);
+                if health.healthy && health.consecutive_failures >= check.fall {
+                    health.healthy = false;
+                    return Some(false);
+                }
+            }
+        }
+        None
+    }
+
+    /// Drops state for pools and targets that are no longer probed.
+    fn retain(&self, active: &HashMap<String, HashSet<String>>) {
+        let mut pools = self.pools.write().unwrap();
+        pools.retain(|pool_key, targets| match active.get(pool_key) {
+            Some(addrs) => {
+                targets.retain(|addr, _| addrs.contains(addr));
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// A pool with an active health check, flattened from the proxy config.
+struct ProbedPool {
+    pool_key: String,
+    check: HealthCheckConfig,
+    targets: Vec<UpstreamTarget>,
+    tls: bool,
+    verify_ssl: bool,
+    sni: String,
+    upstream_tls: Option<UpstreamTlsConfig>,
+    protocol: UpstreamProtocol,
+    host_header: Option<String>,
+}
+
+fn probed_pools(config: &ProxyConfig) -> Vec<ProbedPool> {
+    let mut pools = Vec::new();
+
+    for (domain, host) in &config.hosts {
+        // Pattern hosts have no concrete name to send, so probe with the target address
+        let plain_domain = (!domain.starts_with(WILDCARD_HOST_PREFIX)
+            && !domain.starts_with(REGEX_HOST_PREFIX))
+        .then(|| domain.clone());
+
+        if let Some(check) = &host.health_check {
+            let sni = host
+                .upstream_sni
+                .clone()
+                .or_else(|| plain_domain.clone())
+                .unwrap_or_default();
+            for (_, pool_key, targets) in target_groups::pools(host, None) {
+                pools.push(ProbedPool {
+                    pool_key,
+                    check: check.clone(),
+                    targets: targets.to_vec(),
+                    tls: host.scheme == "https",
+                    verify_ssl: host.verify_ssl,
+                    sni: sni.clone(),
+                    upstream_tls: host.upstream_tls.clone(),
+                    protocol: host.upstream_protocol,
+                    host_header: plain_domain.clone(),
+                });
+            }
+        }
+
+        for loc in &host.locations {
+            if let Some(check) = &loc.health_check {
+                let sni = loc
+                    .upstream_sni
+                    .clone()
+                    .or_else(|| plain_domain.clone())
+                    .unwrap_or_default();
+                for (_, pool_key, targets) in target_groups::pools(host, Some(loc)) {
+                    pools.push(ProbedPool {
+                        pool_key,
+                        check: check.clone(),
+                        targets: targets.to_vec(),
+                        tls: loc.scheme == "https",
+                        verify_ssl: loc.verify_ssl,
+                        sni: sni.clone(),
+                        upstream_tls: loc.upstream_tls.clone(),
+                        protocol: loc.upstream_protocol,
+                        host_header: plain_domain.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    pools
+}
+
+struct Probers {
+    http: HttpConnector,
+    transport: TransportConnector,
+}
+
+async fn probe(probers: &Probers, pool: &ProbedPool, addr: &str) -> Result<(), String> {
+    let timeout = Duration::from_millis(pool.check.timeout_ms);
+
+    let mut peer = resolve_peer(addr, pool.tls, pool.sni.clone()).await?;
+    peer.options.connection_timeout = Some(timeout);
+    peer.options.read_timeout = Some(timeout);
+    peer.options.write_timeout = Some(timeout);
+    peer.options.verify_cert = pool.verify_ssl;
+    peer.options.verify_hostname = pool.verify_ssl;
+    if let Some(tls) = pool.upstream_tls.as_ref().filter(|_| pool.tls) {
+        configure_upstream_tls(&mut peer, tls);
+    }
+    configure_upstream_protocol(&mut peer, pool.protocol);
+
+    match pool.check.kind {
+        HealthCheckKind::Tcp => probers
+            .transport
+            .new_stream(&peer)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        HealthCheckKind::Http => {
+            let (mut session, _) = probers
+                .http
+                .get_http_session(&peer)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let host_header = pool.host_header.as_deref().unwrap_or(addr);
+            // HTTP/2 takes the authority from the URI, and forbids `Connection`
+            let http1 = pool.protocol == UpstreamProtocol::Http1;
+            let path = if http1 {
+                pool.check.path.clone()
+            } else {
+                let scheme = if pool.tls { "https" } else { "http" };
+                format!("{}://{}{}", scheme, host_header, pool.check.path)
+            };
+            let mut req =
+                RequestHeader::build("GET", path.as_bytes(), None).map_err(|e| e.to_string())?;
+            req.insert_header("Host", host_header)
+                .map_err(|e| e.to_string())?;
+            req.insert_header("User-Agent", "pingora-proxy-manager-health-check")
+                .map_err(|e| e.to_string())?;
+            if http1 {
+                req.insert_header("Connection", "close")
+                    .map_err(|e| e.to_string())?;
+            }
+
+            session
+                .write_request_header(Box::new(req))
+                .await
+                .map_err(|e| e.to_string())?;
+            session
+                .finish_request_body()
+                .await
+                .map_err(|e| e.to_string())?;
+            session.set_read_timeout(Some(timeout));
+            session
+                .read_response_header()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let status = session.response_header().map(|resp| resp.status.as_u16());
+            session.shutdown().await;
+
+            match status {
+                Some(status) if status == pool.check.expected_status => Ok(()),
+                Some(status) => Err(format!(
+                    "unexpected status {} (expected {})",
+                    status, pool.check.expected_status
+                )),
+                None => Err("no response header".to_string()),
+            }
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Background loop probing every pool with a configured health check.
+///
+/// The config is re-read every second, so checks added, changed or removed via
+/// the API take effect without a restart.
+pub async fn run_health_checks(state: Arc<AppState>) {
+    let probers = Arc::new(Probers {
+        http: HttpConnector::new(None),
+        transport: TransportConnector::new(None),
+    });
+    let mut last_run: HashMap<String, Instant> = HashMap::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        ticker.tick().await;
+
+        let config = state.config.load_full();
+        let pools = probed_pools(&config);
+
+        let active: HashMap<String, HashSet<String>> = pools
+            .iter()
+            .map(|pool| {
+                let addrs = pool.targets.iter().map(|t| t.addr.clone()).collect();
+                (pool.pool_key.clone(), addrs)
+            })
+            .collect();
+        state.health.retain(&active);
+        last_run.retain(|pool_key, _| active.contains_key(pool_key));
+
+        let now = Instant::now();
+        for pool in pools {
+            let interval = Duration::from_millis(pool.check.interval_ms);
+            let due = last_run
+                .get(&pool.pool_key)
+                .is_none_or(|last| now.duration_since(*last) >= interval);
+            if !due {
+                continue;
+            }
+            last_run.insert(pool.pool_key.clone(), now);
+
+            let pool = Arc::new(pool);
+            for target in &pool.targets {
+                let addr = target.addr.clone();
+                let pool = pool.clone();
+                let probers = probers.clone();
+                let state = state.clone();
+
+                tokio::spawn(async move {
+                    let hard_limit = Duration::from_millis(pool.check.timeout_ms);
+                    let result = tokio::time::timeout(hard_limit, probe(&probers, &pool, &addr))
+                        .await
+                        .unwrap_or_else(|_| Err("timed out".to_string()));
+
+                    match state.health.record(
+                        &pool.pool_key,
+                        &addr,
+                        &pool.check,
+                        result.clone(),
+                        unix_now(),
+                    ) {
+                        Some(true) => {
+                            tracing::info!("Upstream {} ({}) is healthy again", addr, pool.pool_key)
+                        }
+                        Some(false) => tracing::warn!(
+                            "Upstream {} ({}) marked unhealthy: {}",
+                            addr,
+                            pool.pool_key,
+                            result.err().unwrap_or_default()
+                        ),
+                        None => {}
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::HostConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn check(kind: HealthCheckKind) -> HealthCheckConfig {
+        serde_json::from_value(serde_json::json!({ "type": kind, "rise": 2, "fall": 2 })).unwrap()
+    }
+
+    #[test]
+    fn record_applies_rise_and_fall_thresholds() {
+        let registry = HealthRegistry::new();
+        let check = check(HealthCheckKind::Http);
+        let fail = || Err("boom".to_string());
+
+        assert!(registry.is_healthy("1", "a"));
+        assert_eq!(registry.record("1", "a", &check, fail(), 1), None);
+        assert!(registry.is_healthy("1", "a"));
+        assert_eq!(registry.record("1", "a", &check, fail(), 2), Some(false));
+        assert!(!registry.is_healthy("1", "a"));
+        assert!(registry.is_healthy("2", "a"), "health is tracked per pool");
+
+        assert_eq!(registry.record("1", "a", &check, Ok(()), 3), None);
+        assert!(!registry.is_healthy("1", "a"));
+        assert_eq!(registry.record("1", "a", &check, Ok(()), 4), Some(true));
+
+        let status = registry.status("1", "a").unwrap();
+        assert!(status.healthy);
+        assert_eq!(status.last_checked, Some(4));
+        assert_eq!(status.last_error, None);
+    }
+
+    async fn serve_status_once(status_line: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let response = format!("{}\r\nContent-Length: 0\r\n\r\n", status_line);
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+        addr
+    }
+
+    fn probed_pool(addr: &str, kind: HealthCheckKind) -> ProbedPool {
+        ProbedPool {
+            pool_key: "1".to_string(),
+            check: check(kind),
+            targets: vec![addr.into()],
+            tls: false,
+            verify_ssl: false,
+            sni: String::new(),
+            host_header: Some("health.local".to_string()),
+        }
+    }
+
+    fn probers() -> Probers {
+        Probers {
+            http: HttpConnector::new(None),
+            transport: TransportConnector::new(None),
+        }
+    }
+
+    #[tokio::test]
+    async fn http_probe_compares_expected_status() {
+        let probers = probers();
+
+        let ok_addr = serve_status_once("HTTP/1.1 200 OK").await;
+        let pool = probed_pool(&ok_addr, HealthCheckKind::Http);
+        assert_eq!(probe(&probers, &pool, &ok_addr).await, Ok(()));
+
+        let bad_addr = serve_status_once("HTTP/1.1 503 Service Unavailable").await;
+        let pool = probed_pool(&bad_addr, HealthCheckKind::Http);
+        let err = probe(&probers, &pool, &bad_addr).await.unwrap_err();
+        assert!(err.contains("503"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_succeeds_for_listening_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let pool = probed_pool(&addr, HealthCheckKind::Tcp);
+        assert_eq!(probe(&probers(), &pool, &addr).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn tcp_probe_fails_for_closed_port() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let pool = probed_pool(&addr, HealthCheckKind::Tcp);
+        assert!(probe(&probers(), &pool, &addr).await.is_err());
+    }
+
+    #[test]
+    fn probed_pools_only_include_configured_checks() {
+        let mut config = ProxyConfig::default();
+        let host: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 3,
+            "targets": [{ "addr": "127.0.0.1:1", "weight": 1 }],
+            "scheme": "http",
+            "health_check": { "type": "tcp" }
+        }))
+        .unwrap();
+        config
+            .hosts
+            .insert("*.example.com".to_string(), host.clone());
+        config.hosts.insert(
+            "plain.example.com".to_string(),
+            HostConfig {
+                id: 4,
+                health_check: None,
+                ..host
+            },
+        );
+
+        let pools = probed_pools(&config);
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].pool_key, "3");
+        assert_eq!(pools[0].host_header, None);
+        assert_eq!(pools[0].check.interval_ms, 5000);
+    }
+}
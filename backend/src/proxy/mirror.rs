@@ -0,0 +1,243 @@
+use super::resolve_peer;
+use crate::state::{HostConfig, LocationConfig, MirrorConfig};
+use bytes::Bytes;
+use pingora::connectors::http::Connector as HttpConnector;
+use pingora::http::RequestHeader;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Mirrored requests allowed in flight at once; copies beyond it are dropped
+/// so a slow shadow upstream cannot pile up work.
+const MAX_IN_FLIGHT: usize = 256;
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+fn connector() -> &'static HttpConnector {
+    static CONNECTOR: OnceLock<HttpConnector> = OnceLock::new();
+    CONNECTOR.get_or_init(|| HttpConnector::new(None))
+}
+
+/// The location's mirror replaces the host's.
+pub fn effective_config<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a MirrorConfig> {
+    location
+        .and_then(|loc| loc.mirror.as_ref())
+        .or(host.mirror.as_ref())
+}
+
+/// Whether a request falls into the mirrored share; `roll` is in `0..100`.
+fn sampled(percent: u8, roll: u8) -> bool {
+    roll < percent
+}
+
+/// A copy of a request, collected while it is proxied and sent to the
+/// shadow upstream once the request ends.
+pub struct MirrorRequest {
+    config: MirrorConfig,
+    header: Option<RequestHeader>,
+    body: Vec<u8>,
+    /// Set when the body outgrew `max_body_bytes`; the copy is then dropped.
+    oversized: bool,
+}
+
+impl MirrorRequest {
+    /// Starts a copy for `config`, or `None` when the request is not sampled.
+    pub fn sample(config: &MirrorConfig) -> Option<Self> {
+        sampled(config.percent, rand::random_range(0..100)).then(|| Self {
+            config: config.clone(),
+            header: None,
+            body: Vec::new(),
+            oversized: false,
+        })
+    }
+
+    /// Keeps the first upstream request header, as sent after rewrites and
+    /// header rules; retries do not replace it.
+    pub fn capture_header(&mut self, req: &RequestHeader) {
+        if self.header.is_none() {
+            self.header = Some(req.clone());
+        }
+    }
+
+    pub fn push_body(&mut self, chunk: &[u8]) {
+        if self.oversized {
+            return;
+        }
+        if (self.body.len() + chunk.len()) as u64 > self.config.max_body_bytes {
+            self.oversized = true;
+            self.body = Vec::new();
+            return;
+        }
+        self.body.extend_from_slice(chunk);
+    }
+
+    /// Sends the copy in the background. Nothing is sent when the request
+    /// never reached an upstream or its body was too large.
+    pub fn dispatch(self, host: &str) {
+        let Some(header) = self.header else {
+            return;
+        };
+        if self.oversized {
+            tracing::debug!("Skipping mirror for {}: body exceeds max_body_bytes", host);
+            return;
+        }
+        if IN_FLIGHT.fetch_add(1, Ordering::Relaxed) >= MAX_IN_FLIGHT {
+            IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Dropping mirror for {}: {} mirrored requests in flight",
+                host,
+                MAX_IN_FLIGHT
+            );
+            return;
+        }
+
+        let host = host.to_string();
+        let config = self.config;
+        let body = Bytes::from(self.body);
+        tokio::spawn(async move {
+            match send(&config, header, body).await {
+                Ok(status) => tracing::debug!(
+                    "🪞 Mirrored request for {} to {}: {}",
+                    host,
+                    config.target,
+                    status
+                ),
+                Err(e) => tracing::debug!(
+                    "Mirror request for {} to {} failed: {}",
+                    host,
+                    config.target,
+                    e
+                ),
+            }
+            IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+/// The upstream request adjusted for a body that is sent in one piece.
+fn mirror_header(mut header: RequestHeader, body_len: usize) -> Result<RequestHeader, String> {
+    let had_body = header.headers.contains_key(http::header::CONTENT_LENGTH)
+        || header.headers.contains_key(http::header::TRANSFER_ENCODING);
+    let _ = header.remove_header(&http::header::TRANSFER_ENCODING);
+    let _ = header.remove_header(&http::header::CONTENT_LENGTH);
+    if had_body || body_len > 0 {
+        header
+            .insert_header(http::header::CONTENT_LENGTH, body_len.to_string())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(header)
+}
+
+async fn send(config: &MirrorConfig, header: RequestHeader, body: Bytes) -> Result<u16, String> {
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let tls = config.scheme == "https";
+
+    let sni = config
+        .target
+        .rsplit_once(':')
+        .map_or(config.target.as_str(), |(host, _)| host)
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+
+    let mut peer = resolve_peer(&config.target, tls, sni).await?;
+    peer.options.connection_timeout = Some(timeout);
+    peer.options.read_timeout = Some(timeout);
+    peer.options.write_timeout = Some(timeout);
+    peer.options.verify_cert = config.verify_ssl;
+    peer.options.verify_hostname = config.verify_ssl;
+
+    let header = mirror_header(header, body.len())?;
+    let (mut session, _) = connector()
+        .get_http_session(&peer)
+        .await
+        .map_err(|e| e.to_string())?;
+    session.set_read_timeout(Some(timeout));
+    session.set_write_timeout(Some(timeout));
+
+    session
+        .write_request_header(Box::new(header))
+        .await
+        .map_err(|e| e.to_string())?;
+    if !body.is_empty() {
+        session
+            .write_request_body(body, true)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    session
+        .finish_request_body()
+        .await
+        .map_err(|e| e.to_string())?;
+    session
+        .read_response_header()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = session
+        .response_header()
+        .map(|resp| resp.status.as_u16())
+        .ok_or_else(|| "no response header".to_string())?;
+
+    // Drain the discarded body so the connection can be reused
+    while session
+        .read_response_body()
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some()
+    {}
+    connector()
+        .release_http_session(session, &peer, Some(timeout))
+        .await;
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_body_bytes: u64) -> MirrorConfig {
+        MirrorConfig {
+            target: "10.0.0.9:8080".to_string(),
+            scheme: "http".to_string(),
+            verify_ssl: true,
+            percent: 100,
+            max_body_bytes,
+            timeout_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn sampling_follows_the_percentage() {
+        assert!(!sampled(0, 0));
+        assert!(sampled(5, 4));
+        assert!(!sampled(5, 5));
+        assert!(sampled(100, 99));
+        assert!(MirrorRequest::sample(&config(10)).is_some());
+    }
+
+    #[test]
+    fn oversized_bodies_are_not_buffered() {
+        let mut mirror = MirrorRequest::sample(&config(4)).unwrap();
+        mirror.push_body(b"abc");
+        assert!(!mirror.oversized);
+        mirror.push_body(b"de");
+        assert!(mirror.oversized);
+        assert!(mirror.body.is_empty());
+    }
+
+    #[test]
+    fn buffered_bodies_are_sent_with_a_content_length() {
+        let mut req = RequestHeader::build("POST", b"/orders", None).unwrap();
+        req.insert_header("Transfer-Encoding", "chunked").unwrap();
+        let req = mirror_header(req, 12).unwrap();
+        assert!(req.headers.get("Transfer-Encoding").is_none());
+        assert_eq!(req.headers.get("Content-Length").unwrap(), "12");
+
+        let get = RequestHeader::build("GET", b"/", None).unwrap();
+        let get = mirror_header(get, 0).unwrap();
+        assert!(get.headers.get("Content-Length").is_none());
+    }
+}
@@ -1,17 +1,40 @@
+pub mod balancer;
+pub mod cache;
+pub mod compression;
 pub mod connection_filter;
+pub mod error_page;
 pub mod filters;
-
+pub mod forwarded;
+pub mod grpc;
+pub mod header_vars;
+pub mod health;
+pub mod mirror;
+pub mod outlier;
+pub mod retry;
+pub mod sticky;
+pub mod target_groups;
+
+use self::balancer::InFlightGuard;
 use self::filters::{FilterResult, ProxyFilter};
+use self::retry::{AttemptFailure, RetryState};
 use crate::constants;
-use crate::state::{AppState, HostConfig, LocationConfig};
+use crate::state::{
+    AppState, HeaderConfig, HeaderOperation, HostConfig, LbMethod, LocationConfig,
+    LocationMatchType, TcpKeepaliveConfig, TlsVersion, UpstreamConnectionConfig, UpstreamProtocol,
+    UpstreamTarget, UpstreamTlsConfig, UNIX_TARGET_PREFIX,
+};
 use async_trait::async_trait;
 use http::header::HeaderName;
+use pingora::cache::{CacheKey, NoCacheReason, RespCacheable};
 use pingora::http::ResponseHeader;
 use pingora::prelude::*;
-use rand::prelude::IndexedRandom;
+use pingora::protocols::l4::ext::TcpKeepalive;
+use pingora::protocols::Digest;
+use pingora::proxy::FailToProxy;
+use pingora::ErrorSource;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, OnceLock};
-use std::time::Duration; // Fix for rand 0.9
+use std::time::Duration;
 
 pub struct DynamicProxy {
     pub state: Arc<AppState>,
@@ -27,28 +50,94 @@ fn upstream_body_throttle() -> Option<Duration> {
     })
 }
 
+/// Idle upstream connections kept for reuse (`PPM_UPSTREAM_POOL_SIZE`).
+/// Pingora keeps one pool for all upstreams, so this is not set per host.
+pub fn upstream_pool_size() -> Option<usize> {
+    std::env::var("PPM_UPSTREAM_POOL_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+}
+
 pub struct ProxyCtx {
     pub host: String,
+    pub request_id: String,
     pub host_config: Option<HostConfig>,
     pub matched_location: Option<LocationConfig>,
     pub retry_count: usize,
     pub attempted_targets: Vec<String>,
+    /// Keeps the selected target's in-flight count raised until the request ends.
+    pub in_flight: Option<InFlightGuard>,
+    /// Status of the upstream response, if one was received.
+    pub upstream_status: Option<u16>,
+    /// Set when the current target's failure was already reported to the
+    /// outlier detector (connect errors are reported in `fail_to_connect`).
+    pub upstream_failure_recorded: bool,
+    /// Whether the target was chosen from a valid affinity cookie.
+    pub sticky_pinned: bool,
     pub effective_max_request_body_bytes: Option<u64>,
+    /// Whether response caching applies to the matched host or location.
+    pub cache_enabled: bool,
+    /// Encodes the downstream body when compression was negotiated.
+    pub compressor: Option<compression::Encoder>,
+    /// Rate-limit state of the client, echoed in `X-RateLimit-*` headers.
+    pub rate_limit: Option<filters::rate_limit::RateLimitStatus>,
+    /// Path and query produced by the rewrite rules, sent upstream instead of the original.
+    pub rewritten_uri: Option<String>,
+    /// Copy of the request for the shadow upstream, when it was sampled.
+    pub mirror: Option<mirror::MirrorRequest>,
+    /// Target group picked for the request when target groups are configured;
+    /// kept across retries.
+    pub target_group: Option<String>,
+    /// When an upgraded connection is closed (`websocket_max_lifetime_ms`).
+    pub upgrade_deadline: Option<std::time::Instant>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 struct EffectiveUpstreamConfig {
     connection_timeout_ms: u64,
     read_timeout_ms: Option<u64>,
     write_timeout_ms: Option<u64>,
     max_request_body_bytes: Option<u64>,
+    /// Only set for upgraded connections.
+    websocket_max_lifetime_ms: Option<u64>,
+    /// How long a released connection may sit in the pool before it is
+    /// closed instead of reused.
+    idle_timeout_ms: Option<u64>,
+    tcp_keepalive: Option<TcpKeepaliveConfig>,
+    tcp_nodelay: Option<bool>,
+    tcp_fast_open: bool,
+}
+
+/// How a request uses its upstream connection, which decides its limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequestKind {
+    Standard,
+    /// WebSocket and other upgraded connections: no body limit, and only the
+    /// WebSocket idle timeout and lifetime apply.
+    Upgrade,
+    /// gRPC calls can stream for as long as they like, so the read timeout
+    /// does not apply.
+    Grpc,
+}
+
+impl RequestKind {
+    fn of(session: &Session) -> Self {
+        if session.is_upgrade_req() {
+            RequestKind::Upgrade
+        } else if grpc::is_grpc(session.req_header()) {
+            RequestKind::Grpc
+        } else {
+            RequestKind::Standard
+        }
+    }
 }
 
 fn resolve_effective_upstream_config(
     host: &HostConfig,
     location: Option<&LocationConfig>,
-    is_upgrade_request: bool,
+    kind: RequestKind,
 ) -> EffectiveUpstreamConfig {
+    let is_upgrade_request = kind == RequestKind::Upgrade;
     let connection_timeout_ms = location
         .and_then(|loc| loc.connection_timeout_ms)
         .or(host.connection_timeout_ms)
@@ -62,26 +151,39 @@ fn resolve_effective_upstream_config(
             .or(host.max_request_body_bytes)
     };
 
+    // The retry policy's per-try timeout caps both I/O timeouts of every attempt
+    let per_try_timeout_ms = retry::effective_policy(host, location).per_try_timeout_ms;
+    let cap = |ms: u64| per_try_timeout_ms.map_or(ms, |limit| ms.min(limit));
+
+    // A silent upgraded connection is also closed once its lifetime ends
+    let websocket_max_lifetime_ms = location
+        .and_then(|loc| loc.websocket_max_lifetime_ms)
+        .or(host.websocket_max_lifetime_ms)
+        .filter(|_| is_upgrade_request);
+    let websocket_timeout_ms = location
+        .and_then(|loc| loc.websocket_idle_timeout_ms)
+        .or(host.websocket_idle_timeout_ms)
+        .map(|idle| websocket_max_lifetime_ms.map_or(idle, |lifetime| idle.min(lifetime)))
+        .or(websocket_max_lifetime_ms);
+
     let read_timeout_ms = if is_upgrade_request {
+        websocket_timeout_ms
+    } else if kind == RequestKind::Grpc {
         None
     } else {
-        Some(
-            location
-                .and_then(|loc| loc.read_timeout_ms)
-                .or(host.read_timeout_ms)
-                .unwrap_or(constants::timeout::READ_SECS * 1000),
-        )
+        Some(cap(location
+            .and_then(|loc| loc.read_timeout_ms)
+            .or(host.read_timeout_ms)
+            .unwrap_or(constants::timeout::READ_SECS * 1000)))
     };
 
     let write_timeout_ms = if is_upgrade_request {
-        None
+        websocket_timeout_ms
     } else {
-        Some(
-            location
-                .and_then(|loc| loc.write_timeout_ms)
-                .or(host.write_timeout_ms)
-                .unwrap_or(constants::timeout::WRITE_SECS * 1000),
-        )
+        Some(cap(location
+            .and_then(|loc| loc.write_timeout_ms)
+            .or(host.write_timeout_ms)
+            .unwrap_or(constants::timeout::WRITE_SECS * 1000)))
     };
 
     EffectiveUpstreamConfig {
@@ -89,14 +191,276 @@ fn resolve_effective_upstream_config(
         read_timeout_ms,
         write_timeout_ms,
         max_request_body_bytes,
+        websocket_max_lifetime_ms,
+        idle_timeout_ms: connection_setting(host, location, |c| c.idle_timeout_ms),
+        tcp_keepalive: connection_setting(host, location, |c| c.tcp_keepalive),
+        tcp_nodelay: connection_setting(host, location, |c| c.tcp_nodelay),
+        tcp_fast_open: connection_setting(host, location, |c| c.tcp_fast_open).unwrap_or(false),
+    }
+}
+
+/// Resolves one `upstream_connection` field; a location falls back to the
+/// host one field at a time.
+fn connection_setting<T>(
+    host: &HostConfig,
+    location: Option<&LocationConfig>,
+    field: impl Fn(&UpstreamConnectionConfig) -> Option<T>,
+) -> Option<T> {
+    location
+        .and_then(|loc| loc.upstream_connection.as_ref())
+        .and_then(&field)
+        .or_else(|| host.upstream_connection.as_ref().and_then(&field))
+}
+
+/// Fails the request once an upgraded connection outlived its lifetime, which
+/// closes both sides.
+fn check_upgrade_deadline(ctx: &ProxyCtx) -> Result<()> {
+    match ctx.upgrade_deadline {
+        Some(deadline) if std::time::Instant::now() >= deadline => {
+            tracing::info!(
+                "Closing upgraded connection for {}: websocket_max_lifetime_ms reached",
+                ctx.host
+            );
+            Err(Error::explain(
+                ErrorType::InternalError,
+                "Upgraded connection reached its maximum lifetime",
+            ))
+        }
+        _ => Ok(()),
     }
 }
 
-fn configure_upstream_timeouts(peer: &mut HttpPeer, config: EffectiveUpstreamConfig) {
+fn configure_upstream_connection(peer: &mut HttpPeer, config: EffectiveUpstreamConfig) {
     peer.options.connection_timeout = Some(Duration::from_millis(config.connection_timeout_ms));
 
     peer.options.read_timeout = config.read_timeout_ms.map(Duration::from_millis);
     peer.options.write_timeout = config.write_timeout_ms.map(Duration::from_millis);
+
+    // Dropping idle connections before the upstream does avoids reusing one
+    // it already closed
+    peer.options.idle_timeout = config.idle_timeout_ms.map(Duration::from_millis);
+    peer.options.tcp_keepalive = config.tcp_keepalive.map(|keepalive| TcpKeepalive {
+        idle: Duration::from_secs(keepalive.idle_secs),
+        interval: Duration::from_secs(keepalive.interval_secs),
+        count: keepalive.count as usize,
+        // Zero leaves TCP_USER_TIMEOUT at the kernel default
+        #[cfg(target_os = "linux")]
+        user_timeout: keepalive
+            .user_timeout_ms
+            .map_or(Duration::ZERO, Duration::from_millis),
+    });
+    peer.options.tcp_fast_open = config.tcp_fast_open;
+}
+
+/// Applies `tcp_nodelay` to a new upstream socket. Pingora enables it on
+/// every connection, so only an explicit setting is applied.
+#[cfg(unix)]
+fn set_tcp_nodelay(fd: std::os::unix::io::RawFd, nodelay: bool) -> std::io::Result<()> {
+    // SAFETY: Pingora passes the descriptor of the connection it just opened,
+    // which stays open for the duration of this call.
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    socket2::SockRef::from(&fd).set_tcp_nodelay(nodelay)
+}
+
+/// Offers HTTP/2 to the upstream. Pingora speaks cleartext HTTP/2 when a
+/// plain-text peer only allows version 2.
+pub(crate) fn configure_upstream_protocol(peer: &mut HttpPeer, protocol: UpstreamProtocol) {
+    match protocol {
+        UpstreamProtocol::Http1 => {}
+        UpstreamProtocol::H2 => peer.options.set_http_version(2, 1),
+        UpstreamProtocol::H2c => peer.options.set_http_version(2, 2),
+    }
+}
+
+/// A matched location only uses its own upstream TLS settings, like `scheme`.
+pub(crate) fn effective_upstream_tls<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a UpstreamTlsConfig> {
+    match location {
+        Some(loc) => loc.upstream_tls.as_ref(),
+        None => host.upstream_tls.as_ref(),
+    }
+}
+
+/// Presents the client certificate and trusts the CA bundle of `tls`. The
+/// minimum version is checked once the handshake is done.
+pub(crate) fn configure_upstream_tls(peer: &mut HttpPeer, tls: &UpstreamTlsConfig) {
+    peer.client_cert_key = tls.material.client_cert_key.clone();
+    peer.options.ca = tls.material.ca.clone();
+}
+
+/// Peer for a target address, connecting to a Unix domain socket for
+/// `unix:` targets. TCP addresses are resolved here since `HttpPeer::new`
+/// panics on addresses that fail to resolve.
+pub(crate) async fn resolve_peer(addr: &str, tls: bool, sni: String) -> Result<HttpPeer, String> {
+    if let Some(path) = addr.strip_prefix(UNIX_TARGET_PREFIX) {
+        return HttpPeer::new_uds(path, tls, sni).map_err(|e| e.to_string());
+    }
+    let socket_addr = tokio::net::lookup_host(addr)
+        .await
+        .map_err(|e| format!("resolve failed: {}", e))?
+        .next()
+        .ok_or_else(|| "resolve failed: no addresses".to_string())?;
+    Ok(HttpPeer::new(socket_addr, tls, sni))
+}
+
+/// Path relative to `loc` when the location strips its prefix (`rewrite`).
+/// Regex locations have no literal prefix to strip.
+pub(crate) fn strip_location_prefix(loc: &LocationConfig, path: &str) -> Option<String> {
+    if !loc.rewrite || loc.match_type.is_regex() {
+        return None;
+    }
+    let suffix = path.strip_prefix(loc.path.as_str())?;
+    Some(if suffix.starts_with('/') {
+        suffix.to_string()
+    } else {
+        format!("/{}", suffix)
+    })
+}
+
+/// Picks the location for `path` using nginx-style precedence: exact match,
+/// then the first matching regex in declaration order, then the longest prefix.
+fn match_location<'a>(locations: &'a [LocationConfig], path: &str) -> Option<&'a LocationConfig> {
+    if let Some(exact) = locations
+        .iter()
+        .find(|loc| loc.match_type == LocationMatchType::Exact && loc.path == path)
+    {
+        return Some(exact);
+    }
+
+    if let Some(regex_match) = locations.iter().find(|loc| {
+        loc.match_type.is_regex() && loc.regex.as_ref().is_some_and(|re| re.is_match(path))
+    }) {
+        return Some(regex_match);
+    }
+
+    locations
+        .iter()
+        .filter(|loc| loc.match_type == LocationMatchType::Prefix && path.starts_with(&loc.path))
+        .max_by_key(|loc| loc.path.len())
+}
+
+/// Header rules of `target` that apply to the request: host-wide rules plus
+/// those of the matched location. A location rule replaces the host rules for
+/// the same header name.
+fn effective_header_rules(
+    headers: Vec<HeaderConfig>,
+    location: Option<&LocationConfig>,
+    target: &str,
+) -> Vec<HeaderConfig> {
+    let (scoped, host_wide): (Vec<_>, Vec<_>) = headers
+        .into_iter()
+        .filter(|h| h.target == target)
+        .partition(|h| h.location.is_some());
+    let scoped: Vec<HeaderConfig> = scoped
+        .into_iter()
+        .filter(|h| {
+            location.is_some_and(|loc| h.location.as_ref().is_some_and(|hl| hl.matches(loc)))
+        })
+        .collect();
+
+    host_wide
+        .into_iter()
+        .filter(|h| !scoped.iter().any(|s| s.name.eq_ignore_ascii_case(&h.name)))
+        .chain(scoped)
+        .collect()
+}
+
+/// Request or response headers that header rules can change. Edits go through
+/// Pingora's methods so the preserved header-name case stays in sync.
+trait HeaderRuleTarget {
+    fn contains(&self, name: &HeaderName) -> bool;
+    fn remove(&mut self, name: &HeaderName);
+    fn insert(&mut self, name: HeaderName, value: String) -> Result<()>;
+    fn append(&mut self, name: HeaderName, value: String) -> Result<()>;
+}
+
+impl HeaderRuleTarget for RequestHeader {
+    fn contains(&self, name: &HeaderName) -> bool {
+        self.headers.contains_key(name)
+    }
+
+    fn remove(&mut self, name: &HeaderName) {
+        self.remove_header(name);
+    }
+
+    fn insert(&mut self, name: HeaderName, value: String) -> Result<()> {
+        self.insert_header(name, value)
+    }
+
+    fn append(&mut self, name: HeaderName, value: String) -> Result<()> {
+        self.append_header(name, value).map(|_| ())
+    }
+}
+
+impl HeaderRuleTarget for ResponseHeader {
+    fn contains(&self, name: &HeaderName) -> bool {
+        self.headers.contains_key(name)
+    }
+
+    fn remove(&mut self, name: &HeaderName) {
+        self.remove_header(name);
+    }
+
+    fn insert(&mut self, name: HeaderName, value: String) -> Result<()> {
+        self.insert_header(name, value)
+    }
+
+    fn append(&mut self, name: HeaderName, value: String) -> Result<()> {
+        self.append_header(name, value).map(|_| ())
+    }
+}
+
+/// Applies header rules in order, expanding variables in their values.
+fn apply_header_rules(
+    target: &mut impl HeaderRuleTarget,
+    rules: &[HeaderConfig],
+    vars: &header_vars::HeaderVars<'_>,
+) -> Result<()> {
+    for h in rules {
+        let Ok(name) = HeaderName::from_bytes(h.name.as_bytes()) else {
+            continue;
+        };
+        let inserted = match h.operation {
+            HeaderOperation::Remove => {
+                target.remove(&name);
+                Ok(())
+            }
+            HeaderOperation::SetIfMissing if target.contains(&name) => Ok(()),
+            HeaderOperation::Append => target.append(name, vars.expand(&h.value)),
+            HeaderOperation::Set | HeaderOperation::SetIfMissing => {
+                target.remove(&name);
+                target.insert(name, vars.expand(&h.value))
+            }
+        };
+        inserted.map_err(|e| {
+            Error::explain(
+                ErrorType::InternalError,
+                format!("Failed to insert header {}: {}", h.name, e),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether a failed attempt may be retried under the request's retry policy.
+fn retry_allowed(session: &Session, ctx: &ProxyCtx, failure: AttemptFailure) -> bool {
+    let Some(host) = &ctx.host_config else {
+        return false;
+    };
+    let location = ctx.matched_location.as_ref();
+    let targets = location.map_or(&host.targets, |loc| &loc.targets);
+
+    let state = RetryState {
+        attempts: ctx.attempted_targets.len(),
+        untried_target_left: targets
+            .iter()
+            .any(|t| !ctx.attempted_targets.contains(&t.addr)),
+        idempotent: retry::is_idempotent(&session.req_header().method),
+        body_replayable: !session.as_ref().retry_buffer_truncated(),
+    };
+    retry::should_retry(retry::effective_policy(host, location), state, failure)
 }
 
 fn request_body_limit_exceeded(body_bytes_read: usize, max_request_body_bytes: u64) -> bool {
@@ -110,44 +474,201 @@ impl ProxyHttp for DynamicProxy {
     fn new_ctx(&self) -> Self::CTX {
         ProxyCtx {
             host: String::new(),
+            request_id: format!("{:032x}", rand::random::<u128>()),
             host_config: None,
             matched_location: None,
             retry_count: 0,
             attempted_targets: Vec::new(),
+            in_flight: None,
+            upstream_status: None,
+            upstream_failure_recorded: false,
+            sticky_pinned: false,
             effective_max_request_body_bytes: None,
+            cache_enabled: false,
+            compressor: None,
+            rate_limit: None,
+            rewritten_uri: None,
+            mirror: None,
+            target_group: None,
+            upgrade_deadline: None,
         }
     }
 
+    /// Applies `upstream_connection.tcp_nodelay` to new connections and
+    /// enforces `upstream_tls.min_version` on new and reused ones.
+    async fn connected_to_upstream(
+        &self,
+        session: &mut Session,
+        reused: bool,
+        peer: &HttpPeer,
+        #[cfg(unix)] fd: std::os::unix::io::RawFd,
+        #[cfg(windows)] _sock: std::os::windows::io::RawSocket,
+        digest: Option<&Digest>,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        let Some(host_config) = &ctx.host_config else {
+            return Ok(());
+        };
+
+        #[cfg(unix)]
+        if !reused && peer._address.as_inet().is_some() {
+            let config = resolve_effective_upstream_config(
+                host_config,
+                ctx.matched_location.as_ref(),
+                RequestKind::of(session),
+            );
+            if let Some(nodelay) = config.tcp_nodelay {
+                if let Err(e) = set_tcp_nodelay(fd, nodelay) {
+                    tracing::warn!("Failed to set TCP_NODELAY on upstream {}: {}", peer, e);
+                }
+            }
+        }
+        let Some(min_version) = effective_upstream_tls(host_config, ctx.matched_location.as_ref())
+            .and_then(|tls| tls.min_version)
+        else {
+            return Ok(());
+        };
+        let Some(ssl) = digest.and_then(|d| d.ssl_digest.as_ref()) else {
+            return Ok(());
+        };
+
+        let negotiated = TlsVersion::from_protocol(&ssl.version);
+        if negotiated.is_some_and(|version| version >= min_version) {
+            return Ok(());
+        }
+        tracing::warn!(
+            "Rejecting upstream {} for {}: negotiated {} is below the minimum TLS version",
+            peer,
+            ctx.host,
+            ssl.version
+        );
+        Err(Error::explain(
+            ErrorType::TLSHandshakeFailure,
+            format!("Upstream negotiated {}", ssl.version),
+        ))
+    }
+
     fn fail_to_connect(
         &self,
-        _session: &mut Session,
+        session: &mut Session,
         _peer: &HttpPeer,
         ctx: &mut Self::CTX,
         mut e: Box<Error>,
     ) -> Box<Error> {
-        let target_count = if let Some(loc) = &ctx.matched_location {
-            loc.targets.len()
-        } else if let Some(host) = &ctx.host_config {
-            host.targets.len()
-        } else {
-            0
-        };
+        if let Some(target) = ctx.attempted_targets.last() {
+            self.state
+                .outliers
+                .record_failure(target, std::time::Instant::now());
+            ctx.upstream_failure_recorded = true;
+        }
+
+        if retry_allowed(session, ctx, AttemptFailure::Connect) {
+            ctx.retry_count += 1;
+            e.set_retry(true);
+            tracing::warn!(
+                "Upstream connect failed for host {}. Retrying with another target (retry {})",
+                ctx.host,
+                ctx.retry_count
+            );
+        }
+
+        e
+    }
+
+    fn error_while_proxy(
+        &self,
+        peer: &HttpPeer,
+        session: &mut Session,
+        e: Box<Error>,
+        ctx: &mut Self::CTX,
+        client_reused: bool,
+    ) -> Box<Error> {
+        let mut e = e.more_context(format!("Peer: {}", peer));
+        if e.retry() {
+            // Already decided by upstream_response_filter (retryable status)
+            return e;
+        }
 
-        const MAX_RETRIES: usize = 2;
-        if target_count > 1 && ctx.retry_count < MAX_RETRIES {
+        // Same as the default: a stale pooled connection may be retried
+        e.retry
+            .decide_reuse(client_reused && !session.as_ref().retry_buffer_truncated());
+
+        // Only a timeout before any response header arrived can be retried safely
+        let timed_out = matches!(
+            e.etype(),
+            ErrorType::ReadTimedout | ErrorType::WriteTimedout
+        );
+        if !e.retry()
+            && timed_out
+            && ctx.upstream_status.is_none()
+            && retry_allowed(session, ctx, AttemptFailure::Timeout)
+        {
+            if let Some(target) = ctx.attempted_targets.last() {
+                self.state
+                    .outliers
+                    .record_failure(target, std::time::Instant::now());
+                ctx.upstream_failure_recorded = true;
+            }
             ctx.retry_count += 1;
             e.set_retry(true);
             tracing::warn!(
-                "Upstream connect failed for host {}. Retrying with another target (attempt {}/{})",
+                "Upstream timed out for host {}. Retrying with another target (retry {})",
                 ctx.host,
-                ctx.retry_count,
-                MAX_RETRIES
+                ctx.retry_count
             );
         }
 
         e
     }
 
+    /// 프록시 실패 시 커스텀 에러 페이지 렌더링
+    async fn fail_to_proxy(
+        &self,
+        session: &mut Session,
+        e: &Error,
+        ctx: &mut Self::CTX,
+    ) -> FailToProxy {
+        let code = match e.etype() {
+            ErrorType::HTTPStatus(code) => *code,
+            _ => match e.esource() {
+                ErrorSource::Upstream => 502,
+                ErrorSource::Downstream => match e.etype() {
+                    ErrorType::WriteError | ErrorType::ReadError | ErrorType::ConnectionClosed => 0,
+                    _ => 400,
+                },
+                ErrorSource::Internal | ErrorSource::Unset => 500,
+            },
+        };
+
+        // Nothing can be sent once a response, such as a `101`, has started
+        if code > 0 && session.response_written().is_none() {
+            let host_id = ctx.host_config.as_ref().map(|h| h.id);
+            let template = self.state.get_error_template(host_id, code);
+            let html = error_page::render(
+                &template,
+                &error_page::ErrorPageVars {
+                    status_code: code,
+                    host: &ctx.host,
+                    request_id: &ctx.request_id,
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                },
+            );
+
+            if let Err(write_err) = error_page::respond(session, code, html).await {
+                tracing::error!(
+                    "Failed to send error page for host {}: {}",
+                    ctx.host,
+                    write_err
+                );
+            }
+        }
+
+        FailToProxy {
+            error_code: code,
+            can_reuse_downstream: false,
+        }
+    }
+
     /// 요청 필터링: ACME Challenge 처리 및 라우팅 정보 조회
     async fn request_filter(&self, session: &mut Session, ctx: &mut Self::CTX) -> Result<bool> {
         // 1. 초기 필터 (Host 정보 없이 가능한 것들)
@@ -183,12 +704,24 @@ impl ProxyHttp for DynamicProxy {
         if let Some(host_config) = self.state.get_host_config(&host) {
             ctx.host_config = Some(host_config.clone());
 
+            // 4. Location Matching (location-scoped filters need it)
+            let path = session.req_header().uri.path();
+            ctx.matched_location = match_location(&host_config.locations, path).cloned();
+
             let host_filters: Vec<Box<dyn ProxyFilter>> = vec![
                 Box::new(filters::ssl::SslFilter),
                 Box::new(filters::redirect::RedirectFilter),
+                Box::new(filters::maintenance::MaintenanceFilter {
+                    state: self.state.clone(),
+                }),
                 Box::new(filters::acl::AclFilter {
                     state: self.state.clone(),
                 }),
+                Box::new(filters::rate_limit::RateLimitFilter {
+                    state: self.state.clone(),
+                }),
+                Box::new(filters::rewrite::RewriteFilter),
+                Box::new(filters::static_files::StaticFilesFilter),
             ];
 
             for filter in host_filters {
@@ -197,25 +730,15 @@ impl ProxyHttp for DynamicProxy {
                 }
             }
 
-            // 4. Location Matching
-            let path = session.req_header().uri.path();
-            let mut best_match_len = 0;
-            let mut matched_loc = None;
-
-            for loc in &host_config.locations {
-                if path.starts_with(&loc.path) && loc.path.len() > best_match_len {
-                    matched_loc = Some(loc.clone());
-                    best_match_len = loc.path.len();
-                }
-            }
-            ctx.matched_location = matched_loc;
-
             let effective_config = resolve_effective_upstream_config(
                 &host_config,
                 ctx.matched_location.as_ref(),
-                session.is_upgrade_req(),
+                RequestKind::of(session),
             );
             ctx.effective_max_request_body_bytes = effective_config.max_request_body_bytes;
+            ctx.upgrade_deadline = effective_config
+                .websocket_max_lifetime_ms
+                .map(|ms| std::time::Instant::now() + Duration::from_millis(ms));
 
             if let Some(max_request_body_bytes) = effective_config.max_request_body_bytes {
                 let content_length_too_large = session
@@ -232,12 +755,15 @@ impl ProxyHttp for DynamicProxy {
                         ctx.host,
                         max_request_body_bytes
                     );
-                    session
-                        .respond_error(constants::http::PAYLOAD_TOO_LARGE)
-                        .await?;
+                    grpc::respond_error(session, constants::http::PAYLOAD_TOO_LARGE).await?;
                     return Ok(true);
                 }
             }
+
+            if !session.is_upgrade_req() {
+                ctx.mirror = mirror::effective_config(&host_config, ctx.matched_location.as_ref())
+                    .and_then(mirror::MirrorRequest::sample);
+            }
         }
 
         Ok(false)
@@ -246,10 +772,12 @@ impl ProxyHttp for DynamicProxy {
     async fn request_body_filter(
         &self,
         session: &mut Session,
-        _body: &mut Option<bytes::Bytes>,
+        body: &mut Option<bytes::Bytes>,
         _end_of_stream: bool,
         ctx: &mut Self::CTX,
     ) -> Result<()> {
+        check_upgrade_deadline(ctx)?;
+
         if let Some(max_request_body_bytes) = ctx.effective_max_request_body_bytes {
             if request_body_limit_exceeded(session.body_bytes_read(), max_request_body_bytes) {
                 tracing::warn!(
@@ -264,6 +792,10 @@ impl ProxyHttp for DynamicProxy {
             }
         }
 
+        if let (Some(mirror), Some(body)) = (ctx.mirror.as_mut(), body.as_ref()) {
+            mirror.push_body(body);
+        }
+
         Ok(())
     }
 
@@ -274,56 +806,64 @@ impl ProxyHttp for DynamicProxy {
         upstream_request: &mut RequestHeader,
         ctx: &mut Self::CTX,
     ) -> Result<()> {
+        // 1. Rewrite rules (evaluated by RewriteFilter), then prefix stripping
+        if let Some(new_uri) = &ctx.rewritten_uri {
+            upstream_request.set_uri(new_uri.parse().map_err(|e| {
+                Error::explain(
+                    ErrorType::InternalError,
+                    format!("Failed to parse rewritten URI '{}': {}", new_uri, e),
+                )
+            })?);
+            tracing::info!(
+                "🔄 Rewrote URI: {} -> {}",
+                session.req_header().uri,
+                new_uri
+            );
+        }
+
         if let Some(loc) = &ctx.matched_location {
-            if loc.rewrite {
-                let original_path = session.req_header().uri.path().to_string();
-                if original_path.starts_with(&loc.path) {
-                    let rewritten_path = if original_path.len() == loc.path.len() {
-                        "/".to_string()
-                    } else {
-                        let suffix = &original_path[loc.path.len()..];
-                        if suffix.starts_with('/') {
-                            suffix.to_string()
-                        } else {
-                            format!("/{}", suffix)
-                        }
-                    };
-
-                    let new_uri = if let Some(query) = session.req_header().uri.query() {
-                        format!("{}?{}", rewritten_path, query)
-                    } else {
-                        rewritten_path.clone()
-                    };
-
-                    upstream_request.set_uri(new_uri.parse().map_err(|e| {
-                        Error::explain(
-                            ErrorType::InternalError,
-                            format!("Failed to parse URI: {}", e),
-                        )
-                    })?);
-                    tracing::info!("🔄 Rewrote path: {} -> {}", original_path, rewritten_path);
-                }
+            let original_path = upstream_request.uri.path().to_string();
+            if let Some(rewritten_path) = strip_location_prefix(loc, &original_path) {
+                let new_uri = if let Some(query) = upstream_request.uri.query() {
+                    format!("{}?{}", rewritten_path, query)
+                } else {
+                    rewritten_path.clone()
+                };
+
+                upstream_request.set_uri(new_uri.parse().map_err(|e| {
+                    Error::explain(
+                        ErrorType::InternalError,
+                        format!("Failed to parse URI: {}", e),
+                    )
+                })?);
+                tracing::info!("🔄 Rewrote path: {} -> {}", original_path, rewritten_path);
             }
         }
 
-        // 2. Custom Request Headers
+        // 2. Forwarding headers, before custom rules so those can override them
+        if let Some(config) = ctx
+            .host_config
+            .as_ref()
+            .and_then(|h| h.forwarded_headers.as_ref())
+            .filter(|config| config.enabled)
+        {
+            forwarded::apply(config, &forwarded::Hop::new(session), upstream_request)?;
+        }
+
+        // 3. Custom Request Headers
         if let Some(host_config) = &ctx.host_config {
-            let headers = self.state.get_headers(host_config.id);
-            for h in headers {
-                if h.target == "request" {
-                    if let Ok(header_name) = HeaderName::from_bytes(h.name.as_bytes()) {
-                        let _ = upstream_request.remove_header(&header_name);
-                        upstream_request
-                            .insert_header(header_name, &h.value)
-                            .map_err(|e| {
-                                Error::explain(
-                                    ErrorType::InternalError,
-                                    format!("Failed to insert request header: {}", e),
-                                )
-                            })?;
-                    }
-                }
-            }
+            let headers = effective_header_rules(
+                self.state.get_headers(host_config.id),
+                ctx.matched_location.as_ref(),
+                "request",
+            );
+            let vars = header_vars::HeaderVars::new(session, ctx);
+            apply_header_rules(upstream_request, &headers, &vars)?;
+        }
+
+        // 4. Mirror the request exactly as the upstream receives it
+        if let Some(mirror) = ctx.mirror.as_mut() {
+            mirror.capture_header(upstream_request);
         }
 
         Ok(())
@@ -331,32 +871,219 @@ impl ProxyHttp for DynamicProxy {
 
     /// 업스트림 응답 필터링 (Response Headers 수행)
     async fn upstream_response_filter(
+        &self,
+        session: &mut Session,
+        upstream_response: &mut ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<()> {
+        let status = upstream_response.status.as_u16();
+        ctx.upstream_status = Some(status);
+
+        // Nothing has been sent downstream yet, so the response can still be
+        // dropped in favour of another target
+        if retry_allowed(session, ctx, AttemptFailure::Status(status)) {
+            if let Some(target) = ctx.attempted_targets.last() {
+                self.state
+                    .outliers
+                    .record_failure(target, std::time::Instant::now());
+                ctx.upstream_failure_recorded = true;
+            }
+            ctx.retry_count += 1;
+            tracing::warn!(
+                "Upstream returned {} for host {}. Retrying with another target (retry {})",
+                status,
+                ctx.host,
+                ctx.retry_count
+            );
+            // The next attempt starts without a response, so its timeouts can
+            // still be retried
+            ctx.upstream_status = None;
+            let mut e = Error::explain(
+                ErrorType::HTTPStatus(status),
+                "Retryable upstream response status",
+            );
+            e.set_retry(true);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn request_cache_filter(&self, session: &mut Session, ctx: &mut Self::CTX) -> Result<()> {
+        let Some(host) = ctx.host_config.as_ref() else {
+            return Ok(());
+        };
+        let location = ctx.matched_location.as_ref();
+        let Some(config) = cache::effective_config(host, location) else {
+            return Ok(());
+        };
+        let req = session.req_header();
+        let cacheable_request = matches!(req.method, http::Method::GET | http::Method::HEAD)
+            && !session.is_upgrade_req()
+            && !cache::bypassed(config, req);
+        let max_object_bytes = usize::try_from(config.max_object_bytes).unwrap_or(usize::MAX);
+        ctx.cache_enabled = true;
+
+        if cacheable_request {
+            session
+                .cache
+                .enable(cache::zone(host, location, config), None, None, None, None);
+            session.cache.set_max_file_size_bytes(max_object_bytes);
+        }
+        Ok(())
+    }
+
+    fn cache_key_callback(&self, session: &Session, ctx: &mut Self::CTX) -> Result<CacheKey> {
+        let config = ctx
+            .host_config
+            .as_ref()
+            .and_then(|host| cache::effective_config(host, ctx.matched_location.as_ref()))
+            .ok_or_else(|| {
+                Error::explain(
+                    ErrorType::InternalError,
+                    "Cache enabled without a cache config",
+                )
+            })?;
+        let scheme = if filters::ssl::is_tls_request(session) {
+            "https"
+        } else {
+            "http"
+        };
+        Ok(cache::cache_key(cache::primary_key(
+            config,
+            scheme,
+            &ctx.host,
+            session.req_header(),
+        )))
+    }
+
+    fn response_cache_filter(
+        &self,
+        session: &Session,
+        resp: &ResponseHeader,
+        ctx: &mut Self::CTX,
+    ) -> Result<RespCacheable> {
+        let config = ctx
+            .host_config
+            .as_ref()
+            .and_then(|host| cache::effective_config(host, ctx.matched_location.as_ref()));
+        Ok(match config {
+            Some(config) => cache::response_cacheable(config, session.req_header(), resp),
+            None => RespCacheable::Uncacheable(NoCacheReason::NeverEnabled),
+        })
+    }
+
+    fn should_serve_stale(
         &self,
         _session: &mut Session,
+        _ctx: &mut Self::CTX,
+        error: Option<&Error>,
+    ) -> bool {
+        // No error means a revalidation is pending (stale-while-revalidate);
+        // otherwise only upstream failures fall back to stale-if-error
+        error.is_none_or(|e| *e.esource() == ErrorSource::Upstream)
+    }
+
+    async fn response_filter(
+        &self,
+        session: &mut Session,
         upstream_response: &mut ResponseHeader,
         ctx: &mut Self::CTX,
     ) -> Result<()> {
+        // Applied here rather than upstream so cached responses are stored
+        // without them and every hit gets its own variable values
         if let Some(host_config) = &ctx.host_config {
-            let headers = self.state.get_headers(host_config.id);
-            for h in headers {
-                if h.target == "response" {
-                    if let Ok(header_name) = HeaderName::from_bytes(h.name.as_bytes()) {
-                        let _ = upstream_response.remove_header(&header_name);
-                        upstream_response
-                            .insert_header(header_name, &h.value)
-                            .map_err(|e| {
-                                Error::explain(
-                                    ErrorType::InternalError,
-                                    format!("Failed to insert response header: {}", e),
-                                )
-                            })?;
-                    }
-                }
+            let headers = effective_header_rules(
+                self.state.get_headers(host_config.id),
+                ctx.matched_location.as_ref(),
+                "response",
+            );
+            let vars = header_vars::HeaderVars::new(session, ctx);
+            apply_header_rules(upstream_response, &headers, &vars)?;
+        }
+
+        // Issue (or refresh after failover) the affinity cookie for the chosen
+        // target. Added after the cache decision, which refuses `Set-Cookie`
+        // responses; cache hits have no target and get no cookie.
+        if let (Some(host_config), Some(target), false) = (
+            &ctx.host_config,
+            ctx.attempted_targets.last(),
+            ctx.sticky_pinned,
+        ) {
+            let location = ctx.matched_location.as_ref();
+            let sticky_config = location
+                .and_then(|loc| loc.sticky_session.as_ref())
+                .or(host_config.sticky_session.as_ref());
+            if let Some(cfg) = sticky_config {
+                let pool_key = target_groups::selected_pool_key(
+                    host_config,
+                    location,
+                    ctx.target_group.as_deref(),
+                );
+                let secure = session
+                    .digest()
+                    .is_some_and(|digest| digest.ssl_digest.is_some());
+                upstream_response.append_header(
+                    http::header::SET_COOKIE,
+                    sticky::set_cookie(
+                        cfg,
+                        &sticky::cookie_value(&pool_key, target),
+                        sticky::cookie_path(location),
+                        secure,
+                    ),
+                )?;
+            }
+        }
+
+        if let Some(status) = ctx.rate_limit {
+            for (name, value) in status.headers() {
+                upstream_response.insert_header(name, value)?;
+            }
+        }
+
+        if ctx.cache_enabled {
+            upstream_response
+                .insert_header("X-Cache-Status", cache::status_label(session.cache.phase()))?;
+        }
+
+        // Compressed here rather than upstream so cached bodies stay identity-encoded
+        if let Some(config) = ctx
+            .host_config
+            .as_ref()
+            .and_then(|host| compression::effective_config(host, ctx.matched_location.as_ref()))
+        {
+            if let Some(algorithm) = compression::negotiate(config, session.req_header())
+                .filter(|_| compression::should_compress(config, upstream_response))
+            {
+                compression::apply_headers(upstream_response, algorithm, !session.is_http2())?;
+                ctx.compressor = Some(compression::Encoder::new(algorithm, config.level));
             }
         }
         Ok(())
     }
 
+    fn response_body_filter(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<bytes::Bytes>,
+        end_of_stream: bool,
+        ctx: &mut Self::CTX,
+    ) -> Result<Option<Duration>> {
+        check_upgrade_deadline(ctx)?;
+
+        if let Some(encoder) = ctx.compressor.as_mut() {
+            let input = body.take().unwrap_or_default();
+            let output = encoder.encode(&input, end_of_stream).map_err(|e| {
+                Error::explain(
+                    ErrorType::InternalError,
+                    format!("Failed to compress response body: {}", e),
+                )
+            })?;
+            *body = (!output.is_empty()).then_some(output);
+        }
+        Ok(None)
+    }
+
     fn upstream_response_body_filter(
         &self,
         _session: &mut Session,
@@ -378,13 +1105,14 @@ impl ProxyHttp for DynamicProxy {
             // Check matched_location first, then host_config.
             // Both now support multiple targets (Vec<String>).
 
-            let (targets, scheme, verify_ssl, upstream_sni) =
+            let (targets, scheme, verify_ssl, upstream_sni, upstream_protocol) =
                 if let Some(loc) = &ctx.matched_location {
                     (
                         &loc.targets,
                         &loc.scheme,
                         loc.verify_ssl,
                         loc.upstream_sni.as_ref(),
+                        loc.upstream_protocol,
                     )
                 } else {
                     (
@@ -392,36 +1120,110 @@ impl ProxyHttp for DynamicProxy {
                         &host_config.scheme,
                         host_config.verify_ssl,
                         host_config.upstream_sni.as_ref(),
+                        host_config.upstream_protocol,
                     )
                 };
 
-            // Select a target using simple Random Load Balancing
+            // Location settings win over the host's; a location without its own
+            // lb_method inherits both method and hash key.
+            let (lb_method, lb_hash_key) = match &ctx.matched_location {
+                Some(loc) if loc.lb_method.is_some() => {
+                    (loc.lb_method.unwrap_or_default(), loc.lb_hash_key.as_ref())
+                }
+                _ => (host_config.lb_method, host_config.lb_hash_key.as_ref()),
+            };
+            let pool_key = balancer::pool_key(host_config, ctx.matched_location.as_ref());
+
+            // Named target groups (canaries) replace the regular targets for
+            // their share of requests
+            let group = target_groups::effective_config(host_config, ctx.matched_location.as_ref())
+                .and_then(|config| {
+                    let name = ctx.target_group.get_or_insert_with(|| {
+                        let roll = rand::random_range(0..100);
+                        let selected = target_groups::select(config, session.req_header(), roll);
+                        target_groups::name(selected).to_string()
+                    });
+                    config.group(name)
+                });
+            let (targets, pool_key) = match group {
+                Some(group) => (
+                    &group.targets,
+                    target_groups::pool_key(&pool_key, &group.name),
+                ),
+                None => (targets, pool_key),
+            };
+
+            let client_ip = session
+                .client_addr()
+                .and_then(|addr| addr.as_inet())
+                .map(|addr| addr.ip().to_string());
+            let hash_input = match lb_method {
+                LbMethod::IpHash => client_ip.as_deref().map(str::as_bytes),
+                LbMethod::ConsistentHash => {
+                    lb_hash_key.and_then(|key| balancer::hash_key_value(session.req_header(), key))
+                }
+                _ => None,
+            };
+
             // If targets is empty (shouldn't happen with valid config), error out.
-            let target = if targets.is_empty() {
+            if targets.is_empty() {
                 tracing::error!("No upstream targets configured for host: {}", ctx.host);
                 return Err(Error::explain(
                     ErrorType::HTTPStatus(constants::http::INTERNAL_ERROR),
                     "No upstream targets found",
                 ));
-            } else {
-                let candidates: Vec<&String> = targets
-                    .iter()
-                    .filter(|t| !ctx.attempted_targets.iter().any(|picked| picked == *t))
-                    .collect();
-
-                let mut rng = rand::rng(); // Fixed for rand 0.9
-                if let Some(next) = candidates.choose(&mut rng) {
-                    (*next).clone()
-                } else {
-                    targets.choose(&mut rng).cloned().ok_or_else(|| {
-                        Error::explain(
-                            ErrorType::HTTPStatus(constants::http::INTERNAL_ERROR),
-                            "No selectable upstream targets found",
-                        )
-                    })?
-                }
+            }
+
+            // Prefer healthy, non-ejected targets not yet tried by this request.
+            // If every target is down or already tried, fail open rather than refuse.
+            let balancer = &self.state.balancer;
+            let health = &self.state.health;
+            let outliers = &self.state.outliers;
+            let now = std::time::Instant::now();
+            let not_attempted = |t: &UpstreamTarget| !ctx.attempted_targets.contains(&t.addr);
+            let usable = |t: &UpstreamTarget| {
+                not_attempted(t)
+                    && health.is_healthy(&pool_key, &t.addr)
+                    && outliers.is_available(&t.addr, now)
             };
 
+            // A valid affinity cookie pins the target as long as it is still usable
+            let sticky_config = ctx
+                .matched_location
+                .as_ref()
+                .and_then(|loc| loc.sticky_session.as_ref())
+                .or(host_config.sticky_session.as_ref());
+            let pinned = sticky_config
+                .and_then(|cfg| {
+                    balancer::cookie_values(session.req_header(), &cfg.cookie)
+                        .find_map(|value| sticky::pinned_target(value, &pool_key, targets))
+                })
+                .filter(|&i| usable(&targets[i]));
+
+            let index = match pinned {
+                Some(index) => index,
+                None => {
+                    let pool = balancer.pool(&pool_key, targets, lb_method).await;
+                    balancer
+                        .select(&pool, hash_input, usable)
+                        .or_else(|| balancer.select(&pool, hash_input, not_attempted))
+                        .or_else(|| balancer.select(&pool, hash_input, |_| true))
+                        .ok_or_else(|| {
+                            Error::explain(
+                                ErrorType::HTTPStatus(constants::http::INTERNAL_ERROR),
+                                "No selectable upstream targets found",
+                            )
+                        })?
+                }
+            };
+            let target = targets[index].addr.clone();
+
+            // Replacing the guard on retry releases the previously tried target
+            ctx.in_flight = Some(balancer.track(&target));
+            outliers.on_selected(&target, now);
+            ctx.upstream_failure_recorded = false;
+            ctx.upstream_status = None;
+            ctx.sticky_pinned = pinned.is_some();
             ctx.attempted_targets.push(target.clone());
 
             let use_tls = scheme == "https";
@@ -429,9 +1231,11 @@ impl ProxyHttp for DynamicProxy {
             let is_upgrade_request = session.is_upgrade_req();
 
             tracing::info!(
-                "Routing {} -> {} (LB: Random/{} targets, TLS: {}, VerifySSL: {}, SNI: {}, Upgrade: {})",
+                "Routing {} -> {} (Group: {}, LB: {}/{} targets, TLS: {}, VerifySSL: {}, SNI: {}, Upgrade: {})",
                 ctx.host,
                 target,
+                ctx.target_group.as_deref().unwrap_or("-"),
+                lb_method.as_str(),
                 targets.len(),
                 use_tls,
                 verify_ssl,
@@ -439,7 +1243,17 @@ impl ProxyHttp for DynamicProxy {
                 is_upgrade_request,
             );
 
-            let mut peer = Box::new(HttpPeer::new(target, use_tls, sni.clone()));
+            // Host names are resolved here: `HttpPeer::new` panics on failure
+            let mut peer = match resolve_peer(&target, use_tls, sni.clone()).await {
+                Ok(peer) => Box::new(peer),
+                Err(e) => {
+                    tracing::warn!("Upstream {} for {} is unreachable: {}", target, ctx.host, e);
+                    return Err(Error::explain(
+                        ErrorType::HTTPStatus(constants::http::BAD_GATEWAY),
+                        format!("Failed to resolve upstream {}: {}", target, e),
+                    ));
+                }
+            };
 
             if use_tls {
                 peer.sni = sni;
@@ -449,15 +1263,25 @@ impl ProxyHttp for DynamicProxy {
                     Some(Arc::new("upstream_tls".to_string())
                         as Arc<dyn std::any::Any + Send + Sync>)
                 }));
+                if let Some(tls) =
+                    effective_upstream_tls(host_config, ctx.matched_location.as_ref())
+                {
+                    configure_upstream_tls(&mut peer, tls);
+                }
+            }
+
+            // Upgrades such as WebSocket are only proxied over HTTP/1.1
+            if !is_upgrade_request {
+                configure_upstream_protocol(&mut peer, upstream_protocol);
             }
 
             let effective_config = resolve_effective_upstream_config(
                 host_config,
                 ctx.matched_location.as_ref(),
-                is_upgrade_request,
+                RequestKind::of(session),
             );
 
-            configure_upstream_timeouts(&mut peer, effective_config);
+            configure_upstream_connection(&mut peer, effective_config);
 
             return Ok(peer);
         }
@@ -473,14 +1297,37 @@ impl ProxyHttp for DynamicProxy {
     async fn logging(
         &self,
         session: &mut Session,
-        _e: Option<&pingora::Error>,
+        e: Option<&pingora::Error>,
         ctx: &mut Self::CTX,
     ) {
+        if let Some(mirror) = ctx.mirror.take() {
+            mirror.dispatch(&ctx.host);
+        }
+
         self.state
             .metrics
             .total_requests
             .fetch_add(1, Ordering::Relaxed);
 
+        // Passive outlier detection: report how the last target handled this request
+        if let Some(target) = ctx.attempted_targets.last() {
+            if !ctx.upstream_failure_recorded {
+                let outliers = &self.state.outliers;
+                let upstream_error =
+                    e.is_some_and(|e| *e.esource() == pingora::ErrorSource::Upstream);
+                match ctx.upstream_status {
+                    Some(status) if status >= 500 => {
+                        outliers.record_failure(target, std::time::Instant::now());
+                    }
+                    Some(_) => outliers.record_success(target),
+                    None if upstream_error => {
+                        outliers.record_failure(target, std::time::Instant::now());
+                    }
+                    None => outliers.release_trial(target),
+                }
+            }
+        }
+
         if let Some(resp) = session.response_written() {
             let status = resp.status.as_u16();
             let body_len = session.body_bytes_sent() as u64;
@@ -516,6 +1363,8 @@ impl ProxyHttp for DynamicProxy {
                 bytes = body_len,
                 upstream_bytes = upstream_body_len,
                 host = %ctx.host,
+                request_id = %ctx.request_id,
+                target_group = ctx.target_group.as_deref(),
                 "Request handled"
             );
         }
@@ -525,7 +1374,7 @@ impl ProxyHttp for DynamicProxy {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::state::ProxyConfig;
+    use crate::state::{HeaderLocation, HostIndex, ProxyConfig};
     use std::collections::HashMap;
     use std::thread;
     use std::time::Instant;
@@ -539,6 +1388,9 @@ mod tests {
     const TEST_HANGING_ORIGIN_ADDR: &str = "127.0.0.1:39285";
     const TEST_SLOW_ORIGIN_ADDR: &str = "127.0.0.1:39286";
     const TEST_BODY_ORIGIN_ADDR: &str = "127.0.0.1:39287";
+    // Nothing listens here, so connections are refused immediately.
+    const TEST_DOWN_ORIGIN_ADDR: &str = "127.0.0.1:39288";
+    const TEST_UNAVAILABLE_ORIGIN_ADDR: &str = "127.0.0.1:39289";
 
     fn init_test_stack() {
         static INIT: OnceLock<()> = OnceLock::new();
@@ -548,6 +1400,7 @@ mod tests {
             spawn_hanging_origin();
             spawn_slow_origin();
             spawn_body_origin();
+            spawn_unavailable_origin();
             spawn_proxy();
             thread::sleep(Duration::from_millis(400));
         });
@@ -657,6 +1510,31 @@ mod tests {
         });
     }
 
+    fn spawn_unavailable_origin() {
+        thread::spawn(|| {
+            let runtime = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("build unavailable origin runtime");
+
+            runtime.block_on(async {
+                let listener = TcpListener::bind(TEST_UNAVAILABLE_ORIGIN_ADDR)
+                    .await
+                    .expect("bind unavailable origin");
+
+                loop {
+                    let (stream, _) = listener
+                        .accept()
+                        .await
+                        .expect("accept unavailable origin conn");
+                    tokio::spawn(async move {
+                        let _ = handle_unavailable_origin(stream).await;
+                    });
+                }
+            });
+        });
+    }
+
     fn test_proxy_config() -> ProxyConfig {
         let mut hosts = HashMap::new();
 
@@ -664,20 +1542,8 @@ mod tests {
             "upgrade.local".to_string(),
             HostConfig {
                 id: 1,
-                targets: vec![TEST_UPGRADE_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
-                locations: vec![],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
-                read_timeout_ms: None,
-                write_timeout_ms: None,
-                max_request_body_bytes: None,
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                targets: vec![TEST_UPGRADE_ORIGIN_ADDR.into()],
+                ..Default::default()
             },
         );
 
@@ -685,31 +1551,13 @@ mod tests {
             "location.local".to_string(),
             HostConfig {
                 id: 2,
-                targets: vec![TEST_HANGING_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
+                targets: vec![TEST_HANGING_ORIGIN_ADDR.into()],
                 locations: vec![LocationConfig {
                     path: "/api/socket.io/".to_string(),
-                    targets: vec![TEST_UPGRADE_ORIGIN_ADDR.to_string()],
-                    scheme: "http".to_string(),
-                    rewrite: false,
-                    verify_ssl: true,
-                    upstream_sni: None,
-                    connection_timeout_ms: None,
-                    read_timeout_ms: None,
-                    write_timeout_ms: None,
-                    max_request_body_bytes: None,
+                    targets: vec![TEST_UPGRADE_ORIGIN_ADDR.into()],
+                    ..Default::default()
                 }],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
-                read_timeout_ms: None,
-                write_timeout_ms: None,
-                max_request_body_bytes: None,
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                ..Default::default()
             },
         );
 
@@ -717,20 +1565,8 @@ mod tests {
             "hang.local".to_string(),
             HostConfig {
                 id: 3,
-                targets: vec![TEST_HANGING_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
-                locations: vec![],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
-                read_timeout_ms: None,
-                write_timeout_ms: None,
-                max_request_body_bytes: None,
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                targets: vec![TEST_HANGING_ORIGIN_ADDR.into()],
+                ..Default::default()
             },
         );
 
@@ -738,20 +1574,9 @@ mod tests {
             "slow-host.local".to_string(),
             HostConfig {
                 id: 4,
-                targets: vec![TEST_SLOW_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
-                locations: vec![],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
+                targets: vec![TEST_SLOW_ORIGIN_ADDR.into()],
                 read_timeout_ms: Some((constants::timeout::READ_SECS + 3) * 1000),
-                write_timeout_ms: None,
-                max_request_body_bytes: None,
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                ..Default::default()
             },
         );
 
@@ -759,31 +1584,15 @@ mod tests {
             "slow-location.local".to_string(),
             HostConfig {
                 id: 5,
-                targets: vec![TEST_HANGING_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
+                targets: vec![TEST_HANGING_ORIGIN_ADDR.into()],
                 locations: vec![LocationConfig {
                     path: "/api".to_string(),
-                    targets: vec![TEST_SLOW_ORIGIN_ADDR.to_string()],
-                    scheme: "http".to_string(),
-                    rewrite: false,
-                    verify_ssl: true,
-                    upstream_sni: None,
-                    connection_timeout_ms: None,
+                    targets: vec![TEST_SLOW_ORIGIN_ADDR.into()],
                     read_timeout_ms: Some((constants::timeout::READ_SECS + 3) * 1000),
-                    write_timeout_ms: None,
-                    max_request_body_bytes: None,
+                    ..Default::default()
                 }],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
                 read_timeout_ms: Some(1_000),
-                write_timeout_ms: None,
-                max_request_body_bytes: None,
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                ..Default::default()
             },
         );
 
@@ -791,27 +1600,117 @@ mod tests {
             "limited.local".to_string(),
             HostConfig {
                 id: 6,
-                targets: vec![TEST_BODY_ORIGIN_ADDR.to_string()],
-                scheme: "http".to_string(),
-                locations: vec![],
-                ssl_forced: false,
-                verify_ssl: true,
-                upstream_sni: None,
-                connection_timeout_ms: None,
-                read_timeout_ms: None,
-                write_timeout_ms: None,
+                targets: vec![TEST_BODY_ORIGIN_ADDR.into()],
                 max_request_body_bytes: Some(4),
-                redirect_to: None,
-                redirect_status: 301,
-                access_list_id: None,
-                headers: vec![],
+                ..Default::default()
+            },
+        );
+
+        hosts.insert(
+            "down.local".to_string(),
+            HostConfig {
+                id: 7,
+                targets: vec![TEST_DOWN_ORIGIN_ADDR.into()],
+                ..Default::default()
+            },
+        );
+
+        hosts.insert(
+            "retry.local".to_string(),
+            HostConfig {
+                id: 8,
+                // Round robin starts on the failing target
+                targets: vec![
+                    TEST_UNAVAILABLE_ORIGIN_ADDR.into(),
+                    TEST_BODY_ORIGIN_ADDR.into(),
+                ],
+                lb_method: LbMethod::RoundRobin,
+                retry_policy: Some(crate::state::RetryPolicy {
+                    retry_on_status: vec![503],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        hosts.insert(
+            "retry-timeout.local".to_string(),
+            HostConfig {
+                id: 11,
+                targets: vec![
+                    TEST_UNAVAILABLE_ORIGIN_ADDR.into(),
+                    TEST_HANGING_ORIGIN_ADDR.into(),
+                    TEST_BODY_ORIGIN_ADDR.into(),
+                ],
+                lb_method: LbMethod::RoundRobin,
+                retry_policy: Some(crate::state::RetryPolicy {
+                    retry_on: vec![
+                        crate::state::RetryCondition::ConnectError,
+                        crate::state::RetryCondition::Timeout,
+                    ],
+                    retry_on_status: vec![503],
+                    per_try_timeout_ms: Some(500),
+                    ..Default::default()
+                }),
+                ..Default::default()
             },
         );
 
+        hosts.insert(
+            "cached.local".to_string(),
+            HostConfig {
+                id: 9,
+                targets: vec![TEST_BODY_ORIGIN_ADDR.into()],
+                cache: Some(
+                    serde_json::from_value(serde_json::json!({ "default_ttl_secs": 60 }))
+                        .expect("valid cache config"),
+                ),
+                ..Default::default()
+            },
+        );
+
+        hosts.insert(
+            "sticky-cached.local".to_string(),
+            HostConfig {
+                id: 10,
+                targets: vec![TEST_BODY_ORIGIN_ADDR.into()],
+                sticky_session: Some(
+                    serde_json::from_value(serde_json::json!({})).expect("valid sticky config"),
+                ),
+                cache: Some(
+                    serde_json::from_value(serde_json::json!({ "default_ttl_secs": 60 }))
+                        .expect("valid cache config"),
+                ),
+                ..Default::default()
+            },
+        );
+
+        let mut headers = HashMap::new();
+        headers.insert(
+            9,
+            vec![HeaderConfig {
+                id: 1,
+                name: "X-Served-Request".to_string(),
+                value: "$request_id".to_string(),
+                target: "response".to_string(),
+                operation: HeaderOperation::Set,
+                location: None,
+            }],
+        );
+
+        let mut error_pages = HashMap::new();
+        error_pages.insert(
+            (Some(7), Some(502)),
+            "<p>{{host}} is down ({{status_code}}, {{request_id}})</p>".to_string(),
+        );
+
+        let host_index = HostIndex::build(&hosts);
         ProxyConfig {
             hosts,
+            host_index,
             access_lists: HashMap::new(),
-            headers: HashMap::new(),
+            headers,
+            error_pages,
         }
     }
 
@@ -858,6 +1757,17 @@ mod tests {
         Ok(())
     }
 
+    async fn handle_unavailable_origin(mut stream: TcpStream) -> std::io::Result<()> {
+        let _ = read_full_request(&mut stream).await?;
+        stream
+            .write_all(
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\nConnection: close\r\n\r\nDOWN",
+            )
+            .await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
     async fn read_until_header_end(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
         let mut data = Vec::new();
         let mut buf = [0_u8; 1024];
@@ -945,6 +1855,20 @@ mod tests {
         Ok((status, response[header_end..].to_vec()))
     }
 
+    async fn read_full_response(stream: &mut TcpStream) -> std::io::Result<(u16, String)> {
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8(response).expect("utf8 response");
+        let status = response
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .expect("status code");
+
+        Ok((status, response))
+    }
+
     async fn send_upgrade_request(host: &str, path: &str) -> TcpStream {
         let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
             .await
@@ -978,25 +1902,16 @@ mod tests {
         let mut peer = HttpPeer::new("127.0.0.1:80", false, String::new());
         let host = HostConfig {
             id: 1,
-            targets: vec!["127.0.0.1:80".to_string()],
-            scheme: "http".to_string(),
-            locations: vec![],
-            ssl_forced: false,
-            verify_ssl: true,
-            upstream_sni: None,
-            connection_timeout_ms: None,
+            targets: vec!["127.0.0.1:80".into()],
             read_timeout_ms: Some(90_000),
             write_timeout_ms: Some(45_000),
             max_request_body_bytes: Some(1_024),
-            redirect_to: None,
-            redirect_status: 301,
-            access_list_id: None,
-            headers: vec![],
+            ..Default::default()
         };
 
-        configure_upstream_timeouts(
+        configure_upstream_connection(
             &mut peer,
-            resolve_effective_upstream_config(&host, None, true),
+            resolve_effective_upstream_config(&host, None, RequestKind::Upgrade),
         );
 
         assert_eq!(
@@ -1011,23 +1926,14 @@ mod tests {
     fn upgraded_requests_ignore_request_body_limits() {
         let host = HostConfig {
             id: 1,
-            targets: vec!["127.0.0.1:80".to_string()],
-            scheme: "http".to_string(),
-            locations: vec![],
-            ssl_forced: false,
-            verify_ssl: true,
-            upstream_sni: None,
-            connection_timeout_ms: None,
+            targets: vec!["127.0.0.1:80".into()],
             read_timeout_ms: Some(90_000),
             write_timeout_ms: Some(45_000),
             max_request_body_bytes: Some(1_024),
-            redirect_to: None,
-            redirect_status: 301,
-            access_list_id: None,
-            headers: vec![],
+            ..Default::default()
         };
 
-        let effective = resolve_effective_upstream_config(&host, None, true);
+        let effective = resolve_effective_upstream_config(&host, None, RequestKind::Upgrade);
 
         assert!(effective.max_request_body_bytes.is_none());
     }
@@ -1037,25 +1943,13 @@ mod tests {
         let mut peer = HttpPeer::new("127.0.0.1:80", false, String::new());
         let host = HostConfig {
             id: 1,
-            targets: vec!["127.0.0.1:80".to_string()],
-            scheme: "http".to_string(),
-            locations: vec![],
-            ssl_forced: false,
-            verify_ssl: true,
-            upstream_sni: None,
-            connection_timeout_ms: None,
-            read_timeout_ms: None,
-            write_timeout_ms: None,
-            max_request_body_bytes: None,
-            redirect_to: None,
-            redirect_status: 301,
-            access_list_id: None,
-            headers: vec![],
+            targets: vec!["127.0.0.1:80".into()],
+            ..Default::default()
         };
 
-        configure_upstream_timeouts(
+        configure_upstream_connection(
             &mut peer,
-            resolve_effective_upstream_config(&host, None, false),
+            resolve_effective_upstream_config(&host, None, RequestKind::Standard),
         );
 
         assert_eq!(
@@ -1072,39 +1966,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn upgraded_requests_use_websocket_idle_timeout_and_lifetime() {
+        let mut host: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "targets": [{ "addr": "127.0.0.1:80" }],
+            "scheme": "http",
+            "read_timeout_ms": 2000,
+            "websocket_idle_timeout_ms": 60_000
+        }))
+        .unwrap();
+
+        let effective = resolve_effective_upstream_config(&host, None, RequestKind::Upgrade);
+        assert_eq!(effective.read_timeout_ms, Some(60_000));
+        assert_eq!(effective.write_timeout_ms, Some(60_000));
+        assert_eq!(effective.websocket_max_lifetime_ms, None);
+
+        // A lifetime shorter than the idle timeout also bounds a silent connection
+        host.websocket_max_lifetime_ms = Some(30_000);
+        let effective = resolve_effective_upstream_config(&host, None, RequestKind::Upgrade);
+        assert_eq!(effective.read_timeout_ms, Some(30_000));
+        assert_eq!(effective.websocket_max_lifetime_ms, Some(30_000));
+
+        let standard = resolve_effective_upstream_config(&host, None, RequestKind::Standard);
+        assert_eq!(standard.read_timeout_ms, Some(2000));
+        assert_eq!(standard.websocket_max_lifetime_ms, None);
+    }
+
+    #[test]
+    fn grpc_requests_stream_without_read_timeout() {
+        let host: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "targets": [{ "addr": "127.0.0.1:50051" }],
+            "scheme": "http",
+            "upstream_protocol": "h2c",
+            "read_timeout_ms": 2000,
+            "max_request_body_bytes": 1024
+        }))
+        .unwrap();
+
+        let effective = resolve_effective_upstream_config(&host, None, RequestKind::Grpc);
+        assert_eq!(effective.read_timeout_ms, None);
+        assert_eq!(
+            effective.write_timeout_ms,
+            Some(constants::timeout::WRITE_SECS * 1000)
+        );
+        assert_eq!(effective.max_request_body_bytes, Some(1024));
+        assert_eq!(host.upstream_protocol, UpstreamProtocol::H2c);
+    }
+
+    #[test]
+    fn upstream_connection_settings_fall_back_to_host_per_field() {
+        let host: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "targets": [{ "addr": "127.0.0.1:80" }],
+            "scheme": "http",
+            "upstream_connection": {
+                "idle_timeout_ms": 4000,
+                "tcp_keepalive": { "idle_secs": 30, "user_timeout_ms": 20000 },
+                "tcp_fast_open": true
+            }
+        }))
+        .unwrap();
+        let mut location = test_location("/api", LocationMatchType::Prefix, "127.0.0.1:81");
+        location.upstream_connection = Some(UpstreamConnectionConfig {
+            idle_timeout_ms: Some(0),
+            tcp_nodelay: Some(false),
+            ..Default::default()
+        });
+
+        let effective =
+            resolve_effective_upstream_config(&host, Some(&location), RequestKind::Standard);
+        assert_eq!(effective.idle_timeout_ms, Some(0));
+        assert_eq!(effective.tcp_nodelay, Some(false));
+        assert!(effective.tcp_fast_open);
+
+        let mut peer = HttpPeer::new("127.0.0.1:81", false, String::new());
+        configure_upstream_connection(&mut peer, effective);
+        assert_eq!(peer.options.idle_timeout, Some(Duration::ZERO));
+        assert!(peer.options.tcp_fast_open);
+        let keepalive = peer
+            .options
+            .tcp_keepalive
+            .as_ref()
+            .expect("host keepalive applies");
+        assert_eq!(keepalive.idle, Duration::from_secs(30));
+        assert_eq!(keepalive.interval, Duration::from_secs(5));
+        assert_eq!(keepalive.count, 3);
+        #[cfg(target_os = "linux")]
+        assert_eq!(keepalive.user_timeout, Duration::from_secs(20));
+
+        // Without a setting, pooled connections are kept until the pool evicts them
+        let plain: HostConfig = serde_json::from_value(serde_json::json!({
+            "id": 2,
+            "targets": [{ "addr": "127.0.0.1:80" }],
+            "scheme": "http"
+        }))
+        .unwrap();
+        let effective = resolve_effective_upstream_config(&plain, None, RequestKind::Standard);
+        assert_eq!(effective.idle_timeout_ms, None);
+        assert_eq!(effective.tcp_keepalive, None);
+    }
+
     #[test]
     fn location_timeout_overrides_host_timeout() {
         let host = HostConfig {
             id: 1,
-            targets: vec!["127.0.0.1:80".to_string()],
-            scheme: "http".to_string(),
-            locations: vec![],
-            ssl_forced: false,
-            verify_ssl: true,
-            upstream_sni: None,
+            targets: vec!["127.0.0.1:80".into()],
             connection_timeout_ms: Some(2_000),
             read_timeout_ms: Some(10_000),
             write_timeout_ms: Some(5_000),
             max_request_body_bytes: Some(1024),
-            redirect_to: None,
-            redirect_status: 301,
-            access_list_id: None,
-            headers: vec![],
+            ..Default::default()
         };
         let location = LocationConfig {
             path: "/api".to_string(),
-            targets: vec!["127.0.0.1:81".to_string()],
-            scheme: "http".to_string(),
-            rewrite: false,
-            verify_ssl: true,
-            upstream_sni: None,
+            targets: vec!["127.0.0.1:81".into()],
             connection_timeout_ms: Some(7_500),
             read_timeout_ms: Some(90_000),
             write_timeout_ms: Some(30_000),
             max_request_body_bytes: Some(2048),
+            ..Default::default()
         };
 
-        let effective = resolve_effective_upstream_config(&host, Some(&location), false);
+        let effective =
+            resolve_effective_upstream_config(&host, Some(&location), RequestKind::Standard);
 
         assert_eq!(
             effective,
@@ -1113,10 +2099,88 @@ mod tests {
                 read_timeout_ms: Some(90_000),
                 write_timeout_ms: Some(30_000),
                 max_request_body_bytes: Some(2048),
+                ..Default::default()
             }
         );
     }
 
+    fn test_location(path: &str, match_type: LocationMatchType, target: &str) -> LocationConfig {
+        LocationConfig {
+            path: path.to_string(),
+            match_type,
+            regex: match_type.compile(path).expect("valid test regex"),
+            targets: vec![target.into()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn location_matching_follows_exact_regex_prefix_precedence() {
+        let locations = vec![
+            test_location("/api", LocationMatchType::Prefix, "prefix-short"),
+            test_location("/api/v1/users", LocationMatchType::Prefix, "prefix-long"),
+            test_location(r"^/api/v[0-9]+/", LocationMatchType::Regex, "regex"),
+            test_location(r"\.PNG$", LocationMatchType::RegexInsensitive, "regex-ci"),
+            test_location("/api/health", LocationMatchType::Exact, "exact"),
+        ];
+        let target_for =
+            |path: &str| match_location(&locations, path).map(|loc| loc.targets[0].addr.as_str());
+
+        assert_eq!(target_for("/api/health"), Some("exact"));
+        assert_eq!(target_for("/api/health/deep"), Some("prefix-short"));
+        assert_eq!(target_for("/api/v1/users"), Some("regex"));
+        assert_eq!(target_for("/api/v2/orders"), Some("regex"));
+        assert_eq!(target_for("/api/legacy"), Some("prefix-short"));
+        assert_eq!(target_for("/static/logo.png"), Some("regex-ci"));
+        assert_eq!(target_for("/other"), None);
+    }
+
+    #[test]
+    fn location_matching_prefers_first_declared_regex() {
+        let locations = vec![
+            test_location(r"^/v[0-9]+/", LocationMatchType::Regex, "first"),
+            test_location(r"^/v1/", LocationMatchType::Regex, "second"),
+        ];
+
+        let matched = match_location(&locations, "/v1/items").expect("regex should match");
+        assert_eq!(matched.targets[0].addr, "first");
+    }
+
+    #[test]
+    fn location_header_rules_replace_host_rules_of_the_same_name() {
+        let rule = |id, name: &str, target: &str, location: Option<&str>| HeaderConfig {
+            id,
+            name: name.to_string(),
+            value: String::new(),
+            target: target.to_string(),
+            operation: HeaderOperation::Set,
+            location: location.map(|path| HeaderLocation {
+                path: path.to_string(),
+                match_type: LocationMatchType::Prefix,
+            }),
+        };
+        let headers = vec![
+            rule(1, "Cache-Control", "response", None),
+            rule(2, "X-Frame-Options", "response", None),
+            rule(3, "cache-control", "response", Some("/admin")),
+            rule(4, "X-Robots-Tag", "response", Some("/admin")),
+            rule(5, "X-Other", "response", Some("/other")),
+            rule(6, "X-Request", "request", None),
+        ];
+        let ids = |location: Option<&LocationConfig>| -> Vec<i64> {
+            effective_header_rules(headers.clone(), location, "response")
+                .iter()
+                .map(|h| h.id)
+                .collect()
+        };
+
+        let admin = test_location("/admin", LocationMatchType::Prefix, "admin");
+        let admin_exact = test_location("/admin", LocationMatchType::Exact, "admin");
+        assert_eq!(ids(None), [1, 2]);
+        assert_eq!(ids(Some(&admin)), [2, 3, 4]);
+        assert_eq!(ids(Some(&admin_exact)), [1, 2]);
+    }
+
     #[test]
     fn request_body_limit_uses_total_bytes_read_without_double_counting() {
         assert!(!request_body_limit_exceeded(4, 4));
@@ -1264,6 +2328,201 @@ mod tests {
         assert_eq!(status, 200);
     }
 
+    #[tokio::test]
+    async fn upstream_connect_failure_renders_host_error_page_override() {
+        init_test_stack();
+
+        let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+            .await
+            .expect("connect to test proxy");
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: down.local\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("write down host request");
+        stream.flush().await.expect("flush down host request");
+
+        let (status, response) = timeout(Duration::from_secs(5), read_full_response(&mut stream))
+            .await
+            .expect("error page should arrive")
+            .expect("read error page response");
+
+        assert_eq!(status, 502);
+        assert!(response.contains("text/html"));
+        assert!(
+            response.contains("<p>down.local is down (502, "),
+            "unexpected error page: {response}"
+        );
+    }
+
+    #[tokio::test]
+    async fn retryable_status_is_retried_on_another_target() {
+        init_test_stack();
+
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+                .await
+                .expect("connect to test proxy");
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: retry.local\r\nConnection: close\r\n\r\n")
+                .await
+                .expect("write retry host request");
+            stream.flush().await.expect("flush retry host request");
+
+            let (status, response) =
+                timeout(Duration::from_secs(5), read_full_response(&mut stream))
+                    .await
+                    .expect("retried response should arrive")
+                    .expect("read retried response");
+
+            assert_eq!(status, 200, "unexpected response: {response}");
+            assert!(response.ends_with("OK"));
+        }
+    }
+
+    #[tokio::test]
+    async fn timeout_after_retried_status_is_retried_too() {
+        init_test_stack();
+
+        // Whatever order the targets are tried in, a 503 followed by a
+        // timeout must still reach the healthy target
+        for _ in 0..3 {
+            let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+                .await
+                .expect("connect to test proxy");
+            stream
+                .write_all(
+                    b"GET / HTTP/1.1\r\nHost: retry-timeout.local\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .expect("write retry-timeout host request");
+            stream
+                .flush()
+                .await
+                .expect("flush retry-timeout host request");
+
+            let (status, response) =
+                timeout(Duration::from_secs(5), read_full_response(&mut stream))
+                    .await
+                    .expect("retried response should arrive")
+                    .expect("read retried response");
+
+            assert_eq!(status, 200, "unexpected response: {response}");
+            assert!(response.ends_with("OK"));
+        }
+    }
+
+    #[tokio::test]
+    async fn cached_responses_get_fresh_response_header_values() {
+        init_test_stack();
+
+        let mut served = Vec::new();
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+                .await
+                .expect("connect to test proxy");
+            stream
+                .write_all(
+                    b"GET /cached HTTP/1.1\r\nHost: cached.local\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .expect("write cached host request");
+            stream.flush().await.expect("flush cached host request");
+
+            let (status, response) =
+                timeout(Duration::from_secs(5), read_full_response(&mut stream))
+                    .await
+                    .expect("cached host response should arrive")
+                    .expect("read cached host response");
+            assert_eq!(status, 200, "unexpected response: {response}");
+
+            served.push((
+                response_header(&response, "X-Cache-Status"),
+                response_header(&response, "X-Served-Request"),
+            ));
+        }
+
+        assert_eq!(served[0].0.as_deref(), Some("MISS"));
+        assert_eq!(served[1].0.as_deref(), Some("HIT"));
+        let first = served[0].1.clone().expect("rule applies to the miss");
+        let second = served[1].1.clone().expect("rule applies to the hit");
+        assert!(!first.is_empty());
+        assert_ne!(
+            first, second,
+            "the hit must not replay the first request id"
+        );
+    }
+
+    #[tokio::test]
+    async fn sticky_sessions_do_not_prevent_caching() {
+        init_test_stack();
+
+        let mut served = Vec::new();
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+                .await
+                .expect("connect to test proxy");
+            stream
+                .write_all(
+                    b"GET /sticky HTTP/1.1\r\nHost: sticky-cached.local\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .expect("write sticky host request");
+            stream.flush().await.expect("flush sticky host request");
+
+            let (status, response) =
+                timeout(Duration::from_secs(5), read_full_response(&mut stream))
+                    .await
+                    .expect("sticky host response should arrive")
+                    .expect("read sticky host response");
+            assert_eq!(status, 200, "unexpected response: {response}");
+            served.push((
+                response_header(&response, "X-Cache-Status"),
+                response_header(&response, "Set-Cookie"),
+            ));
+        }
+
+        assert_eq!(served[0].0.as_deref(), Some("MISS"));
+        assert!(served[0]
+            .1
+            .as_deref()
+            .is_some_and(|cookie| cookie.starts_with("ppm_affinity=")));
+        assert_eq!(served[1].0.as_deref(), Some("HIT"));
+        assert_eq!(served[1].1, None, "hits carry no affinity cookie");
+    }
+
+    fn response_header(response: &str, name: &str) -> Option<String> {
+        response.lines().find_map(|line| {
+            line.split_once(':')
+                .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.trim().to_string())
+        })
+    }
+
+    #[tokio::test]
+    async fn unknown_host_renders_global_error_template() {
+        init_test_stack();
+
+        let mut stream = TcpStream::connect(TEST_PROXY_ADDR)
+            .await
+            .expect("connect to test proxy");
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: missing.local\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("write unknown host request");
+        stream.flush().await.expect("flush unknown host request");
+
+        let (status, response) = timeout(Duration::from_secs(5), read_full_response(&mut stream))
+            .await
+            .expect("error page should arrive")
+            .expect("read error page response");
+
+        assert_eq!(status, constants::http::NOT_FOUND);
+        assert!(
+            response.contains("<h1>404 Not Found</h1>"),
+            "unexpected error page: {response}"
+        );
+    }
+
     #[tokio::test]
     async fn oversized_content_length_returns_payload_too_large() {
         init_test_stack();
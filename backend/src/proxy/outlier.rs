@@ -0,0 +1,170 @@
+use crate::constants::outlier::{BASE_EJECTION_SECS, CONSECUTIVE_FAILURES, MAX_EJECTION_SECS};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct TargetState {
+    consecutive_failures: u32,
+    /// Ejections since the target last served a successful request; drives
+    /// the exponential backoff.
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    /// Set while the single trial request of a half-open target is running.
+    trial_in_flight: bool,
+}
+
+impl TargetState {
+    fn is_half_open(&self, now: Instant) -> bool {
+        self.ejected_until.is_some_and(|until| now >= until)
+    }
+}
+
+/// Passive failure tracking per upstream address (a circuit breaker).
+///
+/// After `CONSECUTIVE_FAILURES` connect errors, timeouts or 5xx responses a
+/// target is ejected. Once the ejection window passes it is half-open: one
+/// trial request is let through, and its outcome either closes the circuit or
+/// ejects the target again for twice as long.
+#[derive(Default)]
+pub struct OutlierDetector {
+    targets: RwLock<HashMap<String, TargetState>>,
+}
+
+impl OutlierDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `addr` may be picked: not ejected, or half-open with no trial
+    /// request running yet.
+    pub fn is_available(&self, addr: &str, now: Instant) -> bool {
+        match self.targets.read().unwrap().get(addr) {
+            Some(state) => match state.ejected_until {
+                Some(_) => state.is_half_open(now) && !state.trial_in_flight,
+                None => true,
+            },
+            None => true,
+        }
+    }
+
+    /// Called once a target has been chosen; claims the trial slot of a
+    /// half-open target.
+    pub fn on_selected(&self, addr: &str, now: Instant) {
+        if let Some(state) = self.targets.write().unwrap().get_mut(addr) {
+            if state.is_half_open(now) {
+                state.trial_in_flight = true;
+            }
+        }
+    }
+
+    /// Frees the trial slot when a request ends without reaching the upstream.
+    pub fn release_trial(&self, addr: &str) {
+        if let Some(state) = self.targets.write().unwrap().get_mut(addr) {
+            state.trial_in_flight = false;
+        }
+    }
+
+    pub fn record_success(&self, addr: &str) {
+        // Fast path for the common case of a target with no failure history
+        if !self.targets.read().unwrap().contains_key(addr) {
+            return;
+        }
+
+        let mut targets = self.targets.write().unwrap();
+        if let Some(state) = targets.get_mut(addr) {
+            if state.ejected_until.is_some() {
+                tracing::info!("Upstream {} recovered, closing circuit", addr);
+            }
+            targets.remove(addr);
+        }
+    }
+
+    /// Records a failure and returns the ejection window if the target was
+    /// (re-)ejected by it.
+    pub fn record_failure(&self, addr: &str, now: Instant) -> Option<Duration> {
+        let mut targets = self.targets.write().unwrap();
+        let state = targets.entry(addr.to_string()).or_default();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+
+        let trial_failed = state.is_half_open(now);
+        if !trial_failed
+            && (state.ejected_until.is_some() || state.consecutive_failures < CONSECUTIVE_FAILURES)
+        {
+            return None;
+        }
+
+        let backoff_secs = BASE_EJECTION_SECS
+            .saturating_mul(1u64 << state.ejections.min(16))
+            .min(MAX_EJECTION_SECS);
+        let window = Duration::from_secs(backoff_secs);
+        state.ejections = state.ejections.saturating_add(1);
+        state.ejected_until = Some(now + window);
+        state.trial_in_flight = false;
+
+        tracing::warn!(
+            "Ejecting upstream {} for {}s after {} consecutive failures",
+            addr,
+            backoff_secs,
+            state.consecutive_failures
+        );
+        Some(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail_until_ejected(detector: &OutlierDetector, addr: &str, now: Instant) -> Duration {
+        for _ in 1..CONSECUTIVE_FAILURES {
+            assert_eq!(detector.record_failure(addr, now), None);
+        }
+        detector
+            .record_failure(addr, now)
+            .expect("threshold should eject")
+    }
+
+    #[test]
+    fn ejects_after_consecutive_failures_and_backs_off_exponentially() {
+        let detector = OutlierDetector::new();
+        let now = Instant::now();
+
+        let first = fail_until_ejected(&detector, "a", now);
+        assert_eq!(first, Duration::from_secs(BASE_EJECTION_SECS));
+        assert!(!detector.is_available("a", now));
+        assert!(detector.is_available("b", now));
+
+        // Half-open: exactly one trial request is admitted
+        let reopened = now + first;
+        assert!(detector.is_available("a", reopened));
+        detector.on_selected("a", reopened);
+        assert!(!detector.is_available("a", reopened));
+
+        // The trial fails, so the window doubles
+        let second = detector.record_failure("a", reopened).unwrap();
+        assert_eq!(second, Duration::from_secs(BASE_EJECTION_SECS * 2));
+        assert!(!detector.is_available("a", reopened));
+    }
+
+    #[test]
+    fn success_resets_failures_and_closes_circuit() {
+        let detector = OutlierDetector::new();
+        let now = Instant::now();
+
+        for _ in 1..CONSECUTIVE_FAILURES {
+            detector.record_failure("a", now);
+        }
+        detector.record_success("a");
+        assert_eq!(detector.record_failure("a", now), None);
+
+        let window = fail_until_ejected(&detector, "b", now);
+        detector.on_selected("b", now + window);
+        detector.record_success("b");
+        assert!(detector.is_available("b", now + window));
+
+        // Backoff starts over after recovery
+        let again = fail_until_ejected(&detector, "b", now + window);
+        assert_eq!(again, Duration::from_secs(BASE_EJECTION_SECS));
+    }
+}
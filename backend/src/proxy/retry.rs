@@ -0,0 +1,157 @@
+use crate::state::{HostConfig, LocationConfig, RetryCondition, RetryPolicy};
+use http::Method;
+use std::sync::OnceLock;
+
+/// Why an upstream attempt failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptFailure {
+    Connect,
+    Timeout,
+    Status(u16),
+}
+
+/// Policy for a request: the location's, then the host's, then the default.
+pub fn effective_policy<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> &'a RetryPolicy {
+    static DEFAULT: OnceLock<RetryPolicy> = OnceLock::new();
+    location
+        .and_then(|loc| loc.retry_policy.as_ref())
+        .or(host.retry_policy.as_ref())
+        .unwrap_or_else(|| DEFAULT.get_or_init(RetryPolicy::default))
+}
+
+/// Methods that may be repeated without changing the result (RFC 9110 9.2.2).
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Request properties that decide whether a failed attempt may be repeated.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryState {
+    /// Attempts made so far, including the failed one.
+    pub attempts: usize,
+    /// Whether a target not yet tried by this request is left.
+    pub untried_target_left: bool,
+    pub idempotent: bool,
+    /// Whether the request body can be sent again (it fit in the retry buffer).
+    pub body_replayable: bool,
+}
+
+/// Decides whether `failure` should be retried on another target.
+pub fn should_retry(policy: &RetryPolicy, state: RetryState, failure: AttemptFailure) -> bool {
+    if state.attempts >= policy.max_attempts as usize || !state.untried_target_left {
+        return false;
+    }
+
+    // Past a connect error the upstream may have acted on the request already
+    let may_resend = state.body_replayable && (state.idempotent || !policy.idempotent_only);
+
+    match failure {
+        AttemptFailure::Connect => policy.retry_on.contains(&RetryCondition::ConnectError),
+        AttemptFailure::Timeout => may_resend && policy.retry_on.contains(&RetryCondition::Timeout),
+        AttemptFailure::Status(status) => may_resend && policy.retry_on_status.contains(&status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(attempts: usize, idempotent: bool) -> RetryState {
+        RetryState {
+            attempts,
+            untried_target_left: true,
+            idempotent,
+            body_replayable: true,
+        }
+    }
+
+    #[test]
+    fn default_policy_only_retries_connect_errors() {
+        let policy = RetryPolicy::default();
+
+        assert!(should_retry(
+            &policy,
+            state(1, false),
+            AttemptFailure::Connect
+        ));
+        assert!(!should_retry(
+            &policy,
+            state(1, true),
+            AttemptFailure::Timeout
+        ));
+        assert!(!should_retry(
+            &policy,
+            state(1, true),
+            AttemptFailure::Status(502)
+        ));
+        // Three attempts in total
+        assert!(!should_retry(
+            &policy,
+            state(3, true),
+            AttemptFailure::Connect
+        ));
+    }
+
+    #[test]
+    fn status_and_timeout_retries_respect_idempotency_and_targets() {
+        let policy = RetryPolicy {
+            retry_on: vec![RetryCondition::ConnectError, RetryCondition::Timeout],
+            retry_on_status: vec![502, 503],
+            ..RetryPolicy::default()
+        };
+
+        assert!(should_retry(
+            &policy,
+            state(1, true),
+            AttemptFailure::Status(503)
+        ));
+        assert!(!should_retry(
+            &policy,
+            state(1, true),
+            AttemptFailure::Status(500)
+        ));
+        assert!(should_retry(
+            &policy,
+            state(1, true),
+            AttemptFailure::Timeout
+        ));
+
+        // POST is only resent once the guard is lifted
+        assert!(!should_retry(
+            &policy,
+            state(1, false),
+            AttemptFailure::Status(502)
+        ));
+        let lenient = RetryPolicy {
+            idempotent_only: false,
+            ..policy.clone()
+        };
+        assert!(should_retry(
+            &lenient,
+            state(1, false),
+            AttemptFailure::Status(502)
+        ));
+
+        let truncated_body = RetryState {
+            body_replayable: false,
+            ..state(1, true)
+        };
+        assert!(!should_retry(
+            &policy,
+            truncated_body,
+            AttemptFailure::Timeout
+        ));
+
+        let exhausted = RetryState {
+            untried_target_left: false,
+            ..state(1, true)
+        };
+        assert!(!should_retry(&policy, exhausted, AttemptFailure::Connect));
+    }
+}
@@ -0,0 +1,174 @@
+use super::balancer;
+use crate::auth;
+use crate::state::{LocationConfig, StickySessionConfig, UpstreamTarget};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::OnceLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bytes of the HMAC kept in the cookie.
+const SIGNATURE_LEN: usize = 16;
+
+/// Label binding keys derived from the JWT secret to affinity cookies.
+const KEY_LABEL: &[u8] = b"ppm sticky session cookie v1";
+
+/// Signing key for affinity cookies: `PPM_STICKY_SECRET`, or a key derived
+/// from the configured JWT secret so the admin token key itself never signs
+/// client-visible data. Without either, a random key is used and pins reset
+/// on restart.
+fn secret() -> &'static [u8] {
+    static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+    SECRET.get_or_init(|| {
+        if let Ok(secret) = std::env::var("PPM_STICKY_SECRET") {
+            return secret.into_bytes();
+        }
+        match auth::configured_jwt_secret() {
+            Some(jwt_secret) => derive_key(&jwt_secret),
+            None => {
+                tracing::warn!(
+                    "Neither PPM_STICKY_SECRET nor JWT_SECRET is set; sticky session cookies use a random key"
+                );
+                rand::random::<[u8; 32]>().to_vec()
+            }
+        }
+    })
+}
+
+fn derive_key(jwt_secret: &[u8]) -> Vec<u8> {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, jwt_secret)
+        .expand(KEY_LABEL, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key.to_vec()
+}
+
+/// Opaque identifier of a target so the cookie does not expose upstream addresses.
+fn target_id(addr: &str) -> String {
+    format!("{:016x}", balancer::hash_bytes(addr.as_bytes()))
+}
+
+fn mac(pool_key: &str, id: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret()).expect("HMAC accepts any key length");
+    mac.update(pool_key.as_bytes());
+    mac.update(b"|");
+    mac.update(id.as_bytes());
+    mac
+}
+
+/// Cookie value pinning a client to `addr` within `pool_key`: `<id>.<signature>`.
+pub fn cookie_value(pool_key: &str, addr: &str) -> String {
+    let id = target_id(addr);
+    let tag = mac(pool_key, &id).finalize().into_bytes();
+    format!("{}.{}", id, URL_SAFE_NO_PAD.encode(&tag[..SIGNATURE_LEN]))
+}
+
+/// Verifies a cookie value and returns the index of the pinned target, if it
+/// is still part of `targets`.
+pub fn pinned_target(value: &str, pool_key: &str, targets: &[UpstreamTarget]) -> Option<usize> {
+    let (id, signature) = value.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    if signature.len() != SIGNATURE_LEN {
+        return None;
+    }
+    mac(pool_key, id).verify_truncated_left(&signature).ok()?;
+
+    targets.iter().position(|t| target_id(&t.addr) == id)
+}
+
+/// Path the affinity cookie is scoped to, so a host and its locations keep
+/// separate pins under the same cookie name. Regex locations have no literal
+/// path and use `/`.
+pub fn cookie_path(location: Option<&LocationConfig>) -> &str {
+    match location {
+        Some(loc)
+            if !loc.match_type.is_regex()
+                && loc.path.starts_with('/')
+                && loc.path.bytes().all(|b| b.is_ascii_graphic() && b != b';') =>
+        {
+            &loc.path
+        }
+        _ => "/",
+    }
+}
+
+/// Builds the `Set-Cookie` header value for a pinned target.
+pub fn set_cookie(config: &StickySessionConfig, value: &str, path: &str, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path={}; HttpOnly; SameSite=Lax",
+        config.cookie, value, path
+    );
+    if let Some(max_age) = config.max_age_secs {
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_cookie_round_trips_and_rejects_tampering() {
+        let targets: Vec<UpstreamTarget> = vec!["10.0.0.1:80".into(), "10.0.0.2:80".into()];
+        let value = cookie_value("7", "10.0.0.2:80");
+
+        assert!(!value.contains("10.0.0.2"));
+        assert_eq!(pinned_target(&value, "7", &targets), Some(1));
+        // Bound to the pool it was issued for
+        assert_eq!(pinned_target(&value, "8", &targets), None);
+
+        let (id, signature) = value.split_once('.').unwrap();
+        let forged = format!("{}.{}", target_id("10.0.0.1:80"), signature);
+        assert_eq!(pinned_target(&forged, "7", &targets), None);
+        assert_eq!(pinned_target(id, "7", &targets), None);
+
+        // A target removed from the pool is no longer pinned
+        assert_eq!(pinned_target(&value, "7", &targets[..1]), None);
+    }
+
+    #[test]
+    fn set_cookie_includes_optional_attributes() {
+        let config = StickySessionConfig {
+            cookie: "route".to_string(),
+            max_age_secs: Some(600),
+        };
+
+        assert_eq!(
+            set_cookie(&config, "abc.def", "/", true),
+            "route=abc.def; Path=/; HttpOnly; SameSite=Lax; Max-Age=600; Secure"
+        );
+    }
+
+    #[test]
+    fn cookies_are_scoped_to_literal_location_paths() {
+        let location: LocationConfig = serde_json::from_value(serde_json::json!({
+            "path": "/api",
+            "targets": [],
+            "scheme": "http"
+        }))
+        .unwrap();
+        assert_eq!(cookie_path(Some(&location)), "/api");
+        assert_eq!(cookie_path(None), "/");
+
+        let regex = LocationConfig {
+            match_type: crate::state::LocationMatchType::Regex,
+            path: "^/v[0-9]+/".to_string(),
+            ..location
+        };
+        assert_eq!(cookie_path(Some(&regex)), "/");
+    }
+
+    #[test]
+    fn derived_key_differs_from_the_jwt_secret() {
+        let key = derive_key(b"jwt-secret");
+        assert_eq!(key.len(), 32);
+        assert_ne!(key, b"jwt-secret".to_vec());
+        assert_eq!(key, derive_key(b"jwt-secret"));
+    }
+}
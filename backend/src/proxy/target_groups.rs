@@ -0,0 +1,198 @@
+use super::balancer;
+use crate::state::{
+    GroupOverride, GroupOverrideSource, HostConfig, LocationConfig, TargetGroup,
+    TargetGroupsConfig, UpstreamTarget, DEFAULT_TARGET_GROUP,
+};
+use pingora::http::RequestHeader;
+
+/// Groups apply to the targets they sit next to: a matched location only
+/// uses its own groups, never the host's.
+pub fn effective_config<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Option<&'a TargetGroupsConfig> {
+    match location {
+        Some(loc) => loc.target_groups.as_ref(),
+        None => host.target_groups.as_ref(),
+    }
+}
+
+/// Balancer and health check key of a named group's pool.
+pub fn pool_key(base: &str, group: &str) -> String {
+    format!("{}@{}", base, group)
+}
+
+/// Pool key of the group a request was routed to.
+pub fn selected_pool_key(
+    host: &HostConfig,
+    location: Option<&LocationConfig>,
+    group: Option<&str>,
+) -> String {
+    let base = balancer::pool_key(host, location);
+    match group {
+        Some(name) if name != DEFAULT_TARGET_GROUP => pool_key(&base, name),
+        _ => base,
+    }
+}
+
+/// Every target pool of a host or location: the regular targets first, then
+/// each named group, with their pool keys.
+pub fn pools<'a>(
+    host: &'a HostConfig,
+    location: Option<&'a LocationConfig>,
+) -> Vec<(Option<&'a str>, String, &'a [UpstreamTarget])> {
+    let base = balancer::pool_key(host, location);
+    let targets = location.map_or(&host.targets, |loc| &loc.targets);
+    let groups = effective_config(host, location).map_or(&[][..], |config| &config.groups);
+
+    let mut pools = vec![(None, base.clone(), targets.as_slice())];
+    pools.extend(groups.iter().map(|group| {
+        (
+            Some(group.name.as_str()),
+            pool_key(&base, &group.name),
+            group.targets.as_slice(),
+        )
+    }));
+    pools
+}
+
+fn query_value<'a>(req: &'a RequestHeader, name: &str) -> Option<&'a str> {
+    req.uri
+        .query()?
+        .split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+fn override_matches(rule: &GroupOverride, req: &RequestHeader) -> bool {
+    let value = match rule.source {
+        GroupOverrideSource::Header => req
+            .headers
+            .get(rule.name.as_str())
+            .and_then(|v| v.to_str().ok()),
+        GroupOverrideSource::Cookie => balancer::cookie_value(req, &rule.name),
+        GroupOverrideSource::Query => query_value(req, &rule.name),
+    };
+    match (value, &rule.value) {
+        (Some(value), Some(expected)) => value.trim() == expected,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Picks the group of a request: the first matching override wins, then
+/// `roll` (`0..100`) falls into a group's share. `None` is the default group.
+pub fn select<'a>(
+    config: &'a TargetGroupsConfig,
+    req: &RequestHeader,
+    roll: u8,
+) -> Option<&'a TargetGroup> {
+    if let Some(rule) = config.overrides.iter().find(|r| override_matches(r, req)) {
+        return config.group(&rule.group);
+    }
+
+    let mut upper: u32 = 0;
+    config.groups.iter().find(|group| {
+        upper += u32::from(group.weight);
+        u32::from(roll) < upper
+    })
+}
+
+/// Name recorded in the access log for a selection.
+pub fn name(group: Option<&TargetGroup>) -> &str {
+    group.map_or(DEFAULT_TARGET_GROUP, |g| g.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TargetGroupsConfig {
+        TargetGroupsConfig {
+            groups: vec![TargetGroup {
+                name: "canary".to_string(),
+                weight: 5,
+                targets: vec![UpstreamTarget::new("10.0.1.5:3000")],
+            }],
+            overrides: vec![
+                GroupOverride {
+                    source: GroupOverrideSource::Header,
+                    name: "X-Canary".to_string(),
+                    value: Some("always".to_string()),
+                    group: "canary".to_string(),
+                },
+                GroupOverride {
+                    source: GroupOverrideSource::Cookie,
+                    name: "pin".to_string(),
+                    value: Some("stable".to_string()),
+                    group: DEFAULT_TARGET_GROUP.to_string(),
+                },
+                GroupOverride {
+                    source: GroupOverrideSource::Query,
+                    name: "canary".to_string(),
+                    value: None,
+                    group: "canary".to_string(),
+                },
+            ],
+        }
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", uri.as_bytes(), None).unwrap();
+        for (name, value) in headers {
+            req.insert_header(name.to_string(), *value).unwrap();
+        }
+        req
+    }
+
+    #[test]
+    fn weights_split_rolls_between_groups() {
+        let config = config();
+        let req = request("/", &[]);
+        assert_eq!(name(select(&config, &req, 0)), "canary");
+        assert_eq!(name(select(&config, &req, 4)), "canary");
+        assert_eq!(name(select(&config, &req, 5)), DEFAULT_TARGET_GROUP);
+        assert_eq!(name(select(&config, &req, 99)), DEFAULT_TARGET_GROUP);
+    }
+
+    #[test]
+    fn overrides_force_a_group() {
+        let config = config();
+        let pinned = request("/", &[("X-Canary", "always")]);
+        assert_eq!(name(select(&config, &pinned, 99)), "canary");
+
+        let other_value = request("/", &[("X-Canary", "never")]);
+        assert_eq!(
+            name(select(&config, &other_value, 99)),
+            DEFAULT_TARGET_GROUP
+        );
+
+        let stable = request("/", &[("Cookie", "a=1; pin=stable")]);
+        assert_eq!(name(select(&config, &stable, 0)), DEFAULT_TARGET_GROUP);
+
+        let query = request("/items?page=2&canary", &[]);
+        assert_eq!(name(select(&config, &query, 99)), "canary");
+    }
+
+    #[test]
+    fn validation_rejects_bad_groups() {
+        assert!(config().validate().is_ok());
+
+        let mut over = config();
+        over.groups[0].weight = 101;
+        assert!(over.validate().is_err());
+
+        let mut reserved = config();
+        reserved.groups[0].name = DEFAULT_TARGET_GROUP.to_string();
+        assert!(reserved.validate().is_err());
+
+        let mut unknown = config();
+        unknown.overrides[0].group = "beta".to_string();
+        assert!(unknown.validate().is_err());
+
+        let mut empty = config();
+        empty.groups[0].targets.clear();
+        assert!(empty.validate().is_err());
+    }
+}
@@ -1,15 +1,1363 @@
+use crate::proxy::balancer::Balancer;
+use crate::proxy::filters::rate_limit::RateLimiter;
+use crate::proxy::health::HealthRegistry;
+use crate::proxy::outlier::OutlierDetector;
 use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use pingora::tls::pkey::PKey;
+use pingora::tls::x509::X509;
+use pingora::utils::tls::CertKey;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// How a location's `path` is matched against the request path.
+///
+/// Precedence follows nginx: an exact match wins outright, then the first
+/// matching regex in declaration order, then the longest matching prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationMatchType {
+    #[default]
+    Prefix,
+    Exact,
+    Regex,
+    RegexInsensitive,
+}
+
+impl LocationMatchType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LocationMatchType::Prefix => "prefix",
+            LocationMatchType::Exact => "exact",
+            LocationMatchType::Regex => "regex",
+            LocationMatchType::RegexInsensitive => "regex_insensitive",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "prefix" => Some(LocationMatchType::Prefix),
+            "exact" => Some(LocationMatchType::Exact),
+            "regex" => Some(LocationMatchType::Regex),
+            "regex_insensitive" => Some(LocationMatchType::RegexInsensitive),
+            _ => None,
+        }
+    }
+
+    pub fn is_regex(&self) -> bool {
+        matches!(
+            self,
+            LocationMatchType::Regex | LocationMatchType::RegexInsensitive
+        )
+    }
+
+    /// Compiles `pattern` for regex match types. Returns `Ok(None)` for
+    /// prefix and exact locations.
+    pub fn compile(&self, pattern: &str) -> Result<Option<Regex>, regex::Error> {
+        match self {
+            LocationMatchType::Regex => Regex::new(pattern).map(Some),
+            LocationMatchType::RegexInsensitive => regex::RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Upper bound for a target weight; keeps the weighted schedules small.
+pub const MAX_TARGET_WEIGHT: u32 = 100;
+
+/// Prefix of targets that are Unix domain sockets, e.g. `unix:/run/app.sock`.
+pub const UNIX_TARGET_PREFIX: &str = "unix:";
+
+/// Longest socket path that fits `sockaddr_un.sun_path`, minus the NUL byte.
+const MAX_UNIX_SOCKET_PATH: usize = 107;
+
+/// One upstream address inside a host or location target list.
+///
+/// Target lists are stored as comma-separated text where each entry is either
+/// `addr` or `addr weight=N`. `addr` is `host:port` or `unix:/path/to.sock`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpstreamTarget {
+    pub addr: String,
+    #[serde(default = "default_target_weight")]
+    pub weight: u32,
+}
+
+fn default_target_weight() -> u32 {
+    1
+}
+
+impl UpstreamTarget {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            weight: 1,
+        }
+    }
+
+    /// Socket path of a `unix:` target.
+    pub fn unix_socket_path(&self) -> Option<&str> {
+        self.addr.strip_prefix(UNIX_TARGET_PREFIX)
+    }
+
+    pub fn validate_addr(addr: &str) -> Result<(), String> {
+        let Some(path) = addr.strip_prefix(UNIX_TARGET_PREFIX) else {
+            return Self::validate_host_port(addr);
+        };
+        if !path.starts_with('/') {
+            return Err(format!(
+                "Unix socket target '{}' must use an absolute path (unix:/path/to.sock)",
+                addr
+            ));
+        }
+        if path.len() > MAX_UNIX_SOCKET_PATH {
+            return Err(format!(
+                "Unix socket path of target '{}' is longer than {} bytes",
+                addr, MAX_UNIX_SOCKET_PATH
+            ));
+        }
+        Ok(())
+    }
+
+    /// `host:port`, with IPv6 hosts in brackets (`[::1]:8080`).
+    fn validate_host_port(addr: &str) -> Result<(), String> {
+        let invalid = |reason: &str| format!("Invalid upstream target '{}': {}", addr, reason);
+        if addr.contains('/') {
+            return Err(invalid("expected host:port without a scheme or path"));
+        }
+        let (host, port) = addr
+            .rsplit_once(':')
+            .ok_or_else(|| invalid("missing port (expected host:port)"))?;
+        let host = match host.strip_prefix('[') {
+            Some(ip) => ip
+                .strip_suffix(']')
+                .filter(|ip| ip.parse::<std::net::Ipv6Addr>().is_ok())
+                .ok_or_else(|| invalid("invalid IPv6 address"))?,
+            None if host.contains(':') => {
+                return Err(invalid("IPv6 addresses must be in brackets ([::1]:8080)"))
+            }
+            None => host,
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        port.parse::<u16>()
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(|| invalid("port must be 1-65535"))?;
+        Ok(())
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut parts = raw.split_whitespace();
+        let addr = parts
+            .next()
+            .ok_or_else(|| "Empty upstream target".to_string())?;
+        Self::validate_addr(addr)?;
+        let mut target = Self::new(addr);
+
+        for option in parts {
+            let weight = option
+                .strip_prefix("weight=")
+                .ok_or_else(|| format!("Unknown option '{}' for target {}", option, addr))?;
+            target.weight = weight
+                .parse::<u32>()
+                .ok()
+                .filter(|w| (1..=MAX_TARGET_WEIGHT).contains(w))
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid weight '{}' for target {} (expected 1-{})",
+                        weight, addr, MAX_TARGET_WEIGHT
+                    )
+                })?;
+        }
+
+        Ok(target)
+    }
+
+    /// Parses a comma-separated target list, ignoring empty entries.
+    pub fn parse_list(raw: &str) -> Result<Vec<Self>, String> {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Inverse of [`UpstreamTarget::parse_list`].
+    pub fn join(targets: &[Self]) -> String {
+        targets
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl std::fmt::Display for UpstreamTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weight == 1 {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{} weight={}", self.addr, self.weight)
+        }
+    }
+}
+
+impl From<&str> for UpstreamTarget {
+    fn from(addr: &str) -> Self {
+        Self::new(addr)
+    }
+}
+
+/// Strategy used to pick one of several upstream targets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LbMethod {
+    /// Weighted random pick (the historical behaviour).
+    #[default]
+    Random,
+    RoundRobin,
+    /// Round robin over each target repeated by its weight.
+    WeightedRoundRobin,
+    /// Fewest in-flight requests relative to weight.
+    LeastRequests,
+    /// FNV hash of the client IP.
+    IpHash,
+    /// Consistent hashing on the header or cookie named by `lb_hash_key`.
+    ConsistentHash,
+}
+
+impl LbMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LbMethod::Random => "random",
+            LbMethod::RoundRobin => "round_robin",
+            LbMethod::WeightedRoundRobin => "weighted_round_robin",
+            LbMethod::LeastRequests => "least_requests",
+            LbMethod::IpHash => "ip_hash",
+            LbMethod::ConsistentHash => "consistent_hash",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "random" => Some(LbMethod::Random),
+            "round_robin" => Some(LbMethod::RoundRobin),
+            "weighted_round_robin" => Some(LbMethod::WeightedRoundRobin),
+            "least_requests" => Some(LbMethod::LeastRequests),
+            "ip_hash" => Some(LbMethod::IpHash),
+            "consistent_hash" => Some(LbMethod::ConsistentHash),
+            _ => None,
+        }
+    }
+}
+
+/// HTTP version spoken to the upstream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamProtocol {
+    #[default]
+    Http1,
+    /// HTTP/2 negotiated via ALPN over TLS, falling back to HTTP/1.1.
+    H2,
+    /// Cleartext HTTP/2 with prior knowledge.
+    H2c,
+}
+
+impl UpstreamProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpstreamProtocol::Http1 => "http1",
+            UpstreamProtocol::H2 => "h2",
+            UpstreamProtocol::H2c => "h2c",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "http1" => Some(UpstreamProtocol::Http1),
+            "h2" => Some(UpstreamProtocol::H2),
+            "h2c" => Some(UpstreamProtocol::H2c),
+            _ => None,
+        }
+    }
+
+    /// `h2` needs TLS for ALPN, and `h2c` is cleartext only.
+    pub fn validate_scheme(&self, scheme: &str) -> Result<(), String> {
+        match (self, scheme) {
+            (UpstreamProtocol::H2, "http") => Err(
+                "upstream_protocol 'h2' needs scheme 'https'; use 'h2c' for cleartext".to_string(),
+            ),
+            (UpstreamProtocol::H2c, "https") => {
+                Err("upstream_protocol 'h2c' needs scheme 'http'; use 'h2' with TLS".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Request attribute hashed by [`LbMethod::ConsistentHash`], written as
+/// `header:<name>` or `cookie:<name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LbHashKey {
+    Header(String),
+    Cookie(String),
+}
+
+impl LbHashKey {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (kind, name) = s.split_once(':')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        match kind.trim() {
+            "header" => Some(LbHashKey::Header(name.to_string())),
+            "cookie" => Some(LbHashKey::Cookie(name.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LbHashKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LbHashKey::Header(name) => write!(f, "header:{}", name),
+            LbHashKey::Cookie(name) => write!(f, "cookie:{}", name),
+        }
+    }
+}
+
+/// Name of the group formed by the regular `targets` of a host or location.
+pub const DEFAULT_TARGET_GROUP: &str = "default";
+
+/// A named set of targets receiving `weight` percent of the traffic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetGroup {
+    pub name: String,
+    pub weight: u8,
+    pub targets: Vec<UpstreamTarget>,
+}
+
+/// Where a [`GroupOverride`] reads its value from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupOverrideSource {
+    Header,
+    Cookie,
+    Query,
+}
+
+/// Forces a group for requests carrying a header, cookie or query parameter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupOverride {
+    pub source: GroupOverrideSource,
+    pub name: String,
+    /// Value the request must carry; any value matches when unset.
+    pub value: Option<String>,
+    pub group: String,
+}
+
+/// Splits traffic between the regular targets and named groups such as a
+/// canary. The regular targets form the `default` group and receive the
+/// share the named groups leave.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetGroupsConfig {
+    pub groups: Vec<TargetGroup>,
+    /// Checked in order before the weighted pick; the first match wins.
+    #[serde(default)]
+    pub overrides: Vec<GroupOverride>,
+}
+
+impl TargetGroupsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.groups.is_empty() {
+            return Err("target_groups.groups must not be empty".to_string());
+        }
+        let mut total: u32 = 0;
+        for (i, group) in self.groups.iter().enumerate() {
+            let valid_name = !group.name.is_empty()
+                && group
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name || group.name == DEFAULT_TARGET_GROUP {
+                return Err(format!("Invalid target group name '{}'", group.name));
+            }
+            if self.groups[..i].iter().any(|g| g.name == group.name) {
+                return Err(format!("Duplicate target group '{}'", group.name));
+            }
+            if group.targets.is_empty() {
+                return Err(format!("Target group '{}' has no targets", group.name));
+            }
+            for target in &group.targets {
+                UpstreamTarget::validate_addr(&target.addr)?;
+                if target.addr.trim().is_empty()
+                    || !(1..=MAX_TARGET_WEIGHT).contains(&target.weight)
+                {
+                    return Err(format!(
+                        "Invalid target '{}' in group '{}'",
+                        target, group.name
+                    ));
+                }
+            }
+            total += u32::from(group.weight);
+        }
+        if total > 100 {
+            return Err("target_groups weights must add up to at most 100".to_string());
+        }
+        for rule in &self.overrides {
+            if rule.name.trim().is_empty() {
+                return Err("target_groups override name must not be empty".to_string());
+            }
+            if rule.group != DEFAULT_TARGET_GROUP && self.group(&rule.group).is_none() {
+                return Err(format!(
+                    "target_groups override refers to unknown group '{}'",
+                    rule.group
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn group(&self, name: &str) -> Option<&TargetGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+}
+
+/// Probe type used by active health checks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheckKind {
+    /// Succeeds when a TCP (or TLS for https pools) connection can be opened.
+    Tcp,
+    #[default]
+    Http,
+}
+
+/// Active health check settings for a host or location target pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    #[serde(default, rename = "type")]
+    pub kind: HealthCheckKind,
+    #[serde(default = "default_health_check_path")]
+    pub path: String,
+    #[serde(default = "default_health_check_status")]
+    pub expected_status: u16,
+    #[serde(default = "default_health_check_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_health_check_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive successes needed to mark an unhealthy target healthy again.
+    #[serde(default = "default_health_check_rise")]
+    pub rise: u32,
+    /// Consecutive failures needed to mark a healthy target unhealthy.
+    #[serde(default = "default_health_check_fall")]
+    pub fall: u32,
+}
+
+impl HealthCheckConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.kind == HealthCheckKind::Http && !self.path.starts_with('/') {
+            return Err("health_check.path must start with '/'".to_string());
+        }
+        if !(100..=599).contains(&self.expected_status) {
+            return Err("health_check.expected_status must be between 100 and 599".to_string());
+        }
+        if self.interval_ms < 1000 {
+            return Err("health_check.interval_ms must be at least 1000".to_string());
+        }
+        if self.timeout_ms == 0 || self.timeout_ms > self.interval_ms {
+            return Err("health_check.timeout_ms must be between 1 and interval_ms".to_string());
+        }
+        if self.rise == 0 || self.fall == 0 {
+            return Err("health_check.rise and health_check.fall must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_health_check_path() -> String {
+    "/".to_string()
+}
+
+fn default_health_check_status() -> u16 {
+    200
+}
+
+fn default_health_check_interval_ms() -> u64 {
+    5000
+}
+
+fn default_health_check_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_health_check_rise() -> u32 {
+    2
+}
+
+fn default_health_check_fall() -> u32 {
+    3
+}
+
+/// Cookie based session affinity for a host or location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StickySessionConfig {
+    #[serde(default = "default_sticky_cookie")]
+    pub cookie: String,
+    /// Lifetime of the affinity cookie; `None` issues a browser-session cookie.
+    pub max_age_secs: Option<u64>,
+}
+
+impl StickySessionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_name = !self.cookie.is_empty()
+            && self
+                .cookie
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !valid_name {
+            return Err(format!(
+                "Invalid sticky_session.cookie name '{}'",
+                self.cookie
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_sticky_cookie() -> String {
+    "ppm_affinity".to_string()
+}
+
+/// Upper bound for `RetryPolicy::max_attempts`.
+pub const MAX_RETRY_ATTEMPTS: u32 = 10;
+
+/// Failure kinds that may trigger a retry on another target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryCondition {
+    /// The upstream could not be connected to; the request was never sent.
+    ConnectError,
+    /// The upstream did not respond within the read/write or per-try timeout.
+    Timeout,
+}
+
+/// Retry policy for a host or location target pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total tries including the first one.
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryCondition>,
+    /// Upstream response statuses that are retried, e.g. `[502, 503, 504]`.
+    #[serde(default)]
+    pub retry_on_status: Vec<u16>,
+    /// Only retry requests that were already sent upstream when the method is
+    /// idempotent. Connect errors are always safe to retry.
+    #[serde(default = "default_true")]
+    pub idempotent_only: bool,
+    /// Caps how long each attempt waits on the upstream.
+    pub per_try_timeout_ms: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            retry_on: default_retry_on(),
+            retry_on_status: Vec::new(),
+            idempotent_only: true,
+            per_try_timeout_ms: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_RETRY_ATTEMPTS).contains(&self.max_attempts) {
+            return Err(format!(
+                "retry_policy.max_attempts must be between 1 and {}",
+                MAX_RETRY_ATTEMPTS
+            ));
+        }
+        if let Some(status) = self
+            .retry_on_status
+            .iter()
+            .find(|s| !(400..=599).contains(*s))
+        {
+            return Err(format!(
+                "retry_policy.retry_on_status contains {}, expected a 4xx or 5xx status",
+                status
+            ));
+        }
+        if self.per_try_timeout_ms == Some(0) {
+            return Err("retry_policy.per_try_timeout_ms must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_on() -> Vec<RetryCondition> {
+    vec![RetryCondition::ConnectError]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Request attribute that makes a request skip the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBypass {
+    /// The request carries this header.
+    Header(String),
+    /// The request carries this cookie.
+    Cookie(String),
+    PathPrefix(String),
+}
+
+/// Request parts that make up the cache key besides host and path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKeyConfig {
+    #[serde(default = "default_true")]
+    pub include_query: bool,
+    /// Request headers whose values are added to the key.
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Cookies whose values are added to the key.
+    #[serde(default)]
+    pub cookies: Vec<String>,
+}
+
+impl Default for CacheKeyConfig {
+    fn default() -> Self {
+        Self {
+            include_query: true,
+            headers: Vec::new(),
+            cookies: Vec::new(),
+        }
+    }
+}
+
+/// Response caching for a host or location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Lets a location turn off caching inherited from its host.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Freshness for responses without `Cache-Control` or `Expires`; 0 leaves
+    /// them uncached.
+    #[serde(default)]
+    pub default_ttl_secs: u32,
+    #[serde(default = "default_cache_max_object_bytes")]
+    pub max_object_bytes: u64,
+    #[serde(default)]
+    pub key: CacheKeyConfig,
+    #[serde(default)]
+    pub bypass: Vec<CacheBypass>,
+    /// Used when the response has no `stale-while-revalidate` directive.
+    #[serde(default)]
+    pub stale_while_revalidate_secs: u32,
+    /// Used when the response has no `stale-if-error` directive.
+    #[serde(default)]
+    pub stale_if_error_secs: u32,
+    /// Memory budget of this host's or location's cache; defaults to
+    /// `PPM_CACHE_MEMORY_MB`.
+    #[serde(default)]
+    pub max_memory_bytes: Option<u64>,
+    /// Size of the disk tier; defaults to `PPM_CACHE_DISK_MB`, and 0 keeps
+    /// the cache in memory only.
+    #[serde(default)]
+    pub max_disk_bytes: Option<u64>,
+}
+
+impl CacheConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_object_bytes == 0 {
+            return Err("cache.max_object_bytes must be greater than 0".to_string());
+        }
+        if let Some(name) = self
+            .key
+            .headers
+            .iter()
+            .find(|name| http::HeaderName::from_bytes(name.as_bytes()).is_err())
+        {
+            return Err(format!("Invalid cache.key header name '{}'", name));
+        }
+        for rule in &self.bypass {
+            match rule {
+                CacheBypass::Header(name)
+                    if http::HeaderName::from_bytes(name.as_bytes()).is_err() =>
+                {
+                    return Err(format!("Invalid cache.bypass header name '{}'", name));
+                }
+                CacheBypass::Cookie(name) if name.is_empty() => {
+                    return Err("cache.bypass cookie name must not be empty".to_string());
+                }
+                CacheBypass::PathPrefix(prefix) if !prefix.starts_with('/') => {
+                    return Err(format!(
+                        "cache.bypass path_prefix '{}' must start with '/'",
+                        prefix
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_cache_max_object_bytes() -> u64 {
+    crate::constants::cache::DEFAULT_MAX_OBJECT_BYTES
+}
+
+/// Content encoding the proxy can apply to responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionAlgorithm {
+    Br,
+    Gzip,
+}
+
+impl CompressionAlgorithm {
+    /// Token used in `Accept-Encoding` / `Content-Encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Br => "br",
+            CompressionAlgorithm::Gzip => "gzip",
+        }
+    }
+}
+
+/// Response compression for a host or location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    /// Lets a location turn off compression inherited from its host.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// In order of preference when the client accepts several.
+    #[serde(default = "default_compression_algorithms")]
+    pub algorithms: Vec<CompressionAlgorithm>,
+    /// Responses with a smaller `Content-Length` are sent as is.
+    #[serde(default = "default_compression_min_size_bytes")]
+    pub min_size_bytes: u64,
+    /// MIME types to compress; `type/*` matches a whole family.
+    #[serde(default = "default_compression_mime_types")]
+    pub mime_types: Vec<String>,
+    /// 1-11; gzip caps it at 9.
+    #[serde(default = "default_compression_level")]
+    pub level: u32,
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.algorithms.is_empty() {
+            return Err("compression.algorithms must not be empty".to_string());
+        }
+        if !(1..=11).contains(&self.level) {
+            return Err("compression.level must be between 1 and 11".to_string());
+        }
+        if let Some(mime) = self.mime_types.iter().find(|mime| {
+            mime.split_once('/')
+                .is_none_or(|(ty, sub)| ty.is_empty() || sub.is_empty())
+        }) {
+            return Err(format!("Invalid compression MIME type '{}'", mime));
+        }
+        Ok(())
+    }
+}
+
+fn default_compression_algorithms() -> Vec<CompressionAlgorithm> {
+    vec![CompressionAlgorithm::Br, CompressionAlgorithm::Gzip]
+}
+
+fn default_compression_min_size_bytes() -> u64 {
+    crate::constants::compression::MIN_SIZE_BYTES
+}
+
+fn default_compression_mime_types() -> Vec<String> {
+    crate::constants::compression::MIME_TYPES
+        .iter()
+        .map(|mime| mime.to_string())
+        .collect()
+}
+
+fn default_compression_level() -> u32 {
+    crate::constants::compression::LEVEL
+}
+
+/// Time unit of a rate limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitPeriod {
+    #[default]
+    Second,
+    Minute,
+}
+
+impl RateLimitPeriod {
+    pub fn as_secs(&self) -> u64 {
+        match self {
+            RateLimitPeriod::Second => 1,
+            RateLimitPeriod::Minute => 60,
+        }
+    }
+}
+
+/// What identifies a client for rate limiting. Requests without the header
+/// or credentials fall back to the client IP.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    ClientIp,
+    Header(String),
+    BasicAuthUser,
+}
+
+/// Per-client request rate limit for a host or location (token bucket).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Lets a location turn off a limit inherited from its host.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Sustained number of requests allowed per `per`.
+    pub requests: u32,
+    #[serde(default)]
+    pub per: RateLimitPeriod,
+    /// Requests allowed at once on top of `requests`.
+    #[serde(default)]
+    pub burst: u32,
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+impl RateLimitConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.requests == 0 {
+            return Err("rate_limit.requests must be greater than 0".to_string());
+        }
+        if let RateLimitKey::Header(name) = &self.key {
+            if http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!("Invalid rate_limit key header name '{}'", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a host treats forwarding headers sent by the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardedMode {
+    /// Keeps incoming values and appends this hop.
+    Append,
+    /// Keeps incoming values only from trusted proxies.
+    #[default]
+    ReplaceUntrusted,
+    /// Always discards incoming values.
+    Replace,
+}
+
+/// Forwarding header the proxy can send to upstreams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardedHeader {
+    XForwardedFor,
+    XForwardedProto,
+    XForwardedHost,
+    XRealIp,
+    /// RFC 7239 `Forwarded`.
+    Forwarded,
+}
+
+impl ForwardedHeader {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForwardedHeader::XForwardedFor => "X-Forwarded-For",
+            ForwardedHeader::XForwardedProto => "X-Forwarded-Proto",
+            ForwardedHeader::XForwardedHost => "X-Forwarded-Host",
+            ForwardedHeader::XRealIp => "X-Real-IP",
+            ForwardedHeader::Forwarded => "Forwarded",
+        }
+    }
+}
+
+/// Forwarding headers added to requests sent to a host's upstreams.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForwardedHeadersConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub mode: ForwardedMode,
+    #[serde(default = "default_forwarded_headers")]
+    pub headers: Vec<ForwardedHeader>,
+}
+
+impl ForwardedHeadersConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.headers.is_empty() {
+            return Err("forwarded_headers.headers must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_forwarded_headers() -> Vec<ForwardedHeader> {
+    vec![
+        ForwardedHeader::XForwardedFor,
+        ForwardedHeader::XForwardedProto,
+        ForwardedHeader::XForwardedHost,
+        ForwardedHeader::XRealIp,
+    ]
+}
+
+/// Takes a host offline without touching its targets: requests get a `503`
+/// page with `Retry-After` instead of being proxied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// RFC 3339 start of a scheduled window. Unset means immediately.
+    pub starts_at: Option<String>,
+    /// RFC 3339 end of a scheduled window. Unset means until disabled.
+    pub ends_at: Option<String>,
+    /// `Retry-After` seconds when `ends_at` is unset.
+    #[serde(default = "default_maintenance_retry_after")]
+    pub retry_after_secs: u64,
+    /// Clients allowed by this access list's IP rules still reach the backend.
+    pub allow_access_list_id: Option<i64>,
+    /// Page template, with the error page placeholders. Falls back to the
+    /// host's `503` error page.
+    pub page_html: Option<String>,
+}
+
+fn parse_rfc3339(value: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+    value
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| format!("Invalid maintenance time '{}': {}", v, e))
+        })
+        .transpose()
+}
+
+impl MaintenanceConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let starts_at = parse_rfc3339(self.starts_at.as_deref())?;
+        let ends_at = parse_rfc3339(self.ends_at.as_deref())?;
+        if let (Some(start), Some(end)) = (starts_at, ends_at) {
+            if end <= start {
+                return Err("maintenance.ends_at must be after starts_at".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether maintenance applies at `now`: enabled and inside the window.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let started = match parse_rfc3339(self.starts_at.as_deref()) {
+            Ok(start) => start.is_none_or(|start| now >= start),
+            Err(_) => false,
+        };
+        let ended = match parse_rfc3339(self.ends_at.as_deref()) {
+            Ok(end) => end.is_some_and(|end| now >= end),
+            Err(_) => true,
+        };
+        started && !ended
+    }
+
+    /// Seconds until the window ends, or `retry_after_secs` without an end.
+    pub fn retry_after(&self, now: DateTime<Utc>) -> u64 {
+        match parse_rfc3339(self.ends_at.as_deref()) {
+            Ok(Some(end)) => (end - now).num_seconds().max(1) as u64,
+            _ => self.retry_after_secs,
+        }
+    }
+}
+
+fn default_maintenance_retry_after() -> u64 {
+    300
+}
+
+/// Sends a copy of proxied requests to a shadow upstream. Its responses are
+/// discarded and its failures never reach the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// `host:port` or `unix:/path` of the shadow upstream.
+    pub target: String,
+    #[serde(default = "default_mirror_scheme")]
+    pub scheme: String,
+    #[serde(default = "default_true")]
+    pub verify_ssl: bool,
+    /// Share of requests mirrored, 0-100.
+    #[serde(default = "default_mirror_percent")]
+    pub percent: u8,
+    /// Requests with larger bodies are not mirrored.
+    #[serde(default = "default_mirror_max_body_bytes")]
+    pub max_body_bytes: u64,
+    #[serde(default = "default_mirror_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl MirrorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        UpstreamTarget::validate_addr(&self.target).map_err(|e| format!("mirror.target: {}", e))?;
+        if self.scheme != "http" && self.scheme != "https" {
+            return Err("mirror.scheme must be 'http' or 'https'".to_string());
+        }
+        if self.percent > 100 {
+            return Err("mirror.percent must be between 0 and 100".to_string());
+        }
+        if self.max_body_bytes == 0 {
+            return Err("mirror.max_body_bytes must be at least 1".to_string());
+        }
+        if self.timeout_ms == 0 {
+            return Err("mirror.timeout_ms must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_mirror_scheme() -> String {
+    "http".to_string()
+}
+
+fn default_mirror_percent() -> u8 {
+    100
+}
+
+fn default_mirror_max_body_bytes() -> u64 {
+    1024 * 1024
+}
+
+fn default_mirror_timeout_ms() -> u64 {
+    5_000
+}
+
+/// TLS protocol versions accepted as `upstream_tls.min_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls10,
+    #[serde(rename = "1.1")]
+    Tls11,
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    /// Parses an OpenSSL protocol name such as `TLSv1.2`.
+    pub fn from_protocol(name: &str) -> Option<Self> {
+        match name {
+            "TLSv1" => Some(TlsVersion::Tls10),
+            "TLSv1.1" => Some(TlsVersion::Tls11),
+            "TLSv1.2" => Some(TlsVersion::Tls12),
+            "TLSv1.3" => Some(TlsVersion::Tls13),
+            _ => None,
+        }
+    }
+}
+
+/// Client certificate and trusted CAs loaded from the uploaded files.
+#[derive(Clone, Default)]
+pub struct UpstreamTlsMaterial {
+    pub client_cert_key: Option<Arc<CertKey>>,
+    pub ca: Option<Arc<Box<[X509]>>>,
+}
+
+impl std::fmt::Debug for UpstreamTlsMaterial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UpstreamTlsMaterial")
+            .field("client_cert_key", &self.client_cert_key.is_some())
+            .field("ca", &self.ca.as_ref().map(|ca| ca.len()))
+            .finish()
+    }
+}
+
+/// TLS settings for `https` upstreams: a client certificate for mutual TLS,
+/// a private CA and a minimum protocol version. Certificates are referenced
+/// by the name they were uploaded under (`POST /certs/upstream`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamTlsConfig {
+    /// Client certificate and key pair presented to the upstream.
+    pub client_cert: Option<String>,
+    /// CA bundle trusted instead of the system roots.
+    pub ca_bundle: Option<String>,
+    pub min_version: Option<TlsVersion>,
+    /// Files named above, read by the config loader.
+    #[serde(skip)]
+    pub material: UpstreamTlsMaterial,
+}
+
+impl UpstreamTlsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for name in self.client_cert.iter().chain(&self.ca_bundle) {
+            validate_upstream_cert_name(name)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the referenced certificates from `dir`, failing when a file is
+    /// missing, malformed or the key does not belong to the certificate.
+    pub fn load_material(&self, dir: &Path) -> Result<UpstreamTlsMaterial, String> {
+        let read = |file: &str| {
+            fs::read(dir.join(file)).map_err(|e| format!("Failed to read {}: {}", file, e))
+        };
+
+        let client_cert_key = match &self.client_cert {
+            Some(name) => {
+                let (cert_file, key_file) = upstream_client_cert_files(name);
+                let cert_key = parse_upstream_client_cert(&read(&cert_file)?, &read(&key_file)?)
+                    .map_err(|e| format!("Client certificate '{}': {}", name, e))?;
+                Some(Arc::new(cert_key))
+            }
+            None => None,
+        };
+        let ca = match &self.ca_bundle {
+            Some(name) => {
+                let certs = parse_pem_certs(&read(&upstream_ca_bundle_file(name))?)
+                    .map_err(|e| format!("CA bundle '{}': {}", name, e))?;
+                Some(Arc::new(certs.into_boxed_slice()))
+            }
+            None => None,
+        };
+        Ok(UpstreamTlsMaterial {
+            client_cert_key,
+            ca,
+        })
+    }
+}
+
+/// Parses a PEM certificate chain and the private key of its first
+/// certificate.
+pub fn parse_upstream_client_cert(cert_pem: &[u8], key_pem: &[u8]) -> Result<CertKey, String> {
+    let certs = parse_pem_certs(cert_pem)?;
+    let key =
+        PKey::private_key_from_pem(key_pem).map_err(|e| format!("invalid private key: {}", e))?;
+    let matches = certs[0]
+        .public_key()
+        .is_ok_and(|public| public.public_eq(&key));
+    if !matches {
+        return Err("private key does not match the certificate".to_string());
+    }
+    Ok(CertKey::new(certs, key))
+}
+
+/// Parses one or more PEM certificates.
+pub fn parse_pem_certs(pem: &[u8]) -> Result<Vec<X509>, String> {
+    let certs = X509::stack_from_pem(pem).map_err(|e| format!("invalid certificate: {}", e))?;
+    if certs.is_empty() {
+        return Err("no certificate found".to_string());
+    }
+    Ok(certs)
+}
+
+/// Upstream certificate names become file names, so they are limited to
+/// letters, digits, `-` and `_`.
+pub fn validate_upstream_cert_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid upstream certificate name '{}': use up to 64 letters, digits, '-' or '_'",
+            name
+        ))
+    }
+}
+
+/// Certificate and key file names of an upstream client certificate.
+pub fn upstream_client_cert_files(name: &str) -> (String, String) {
+    (format!("{}.crt", name), format!("{}.key", name))
+}
+
+/// File name of an upstream CA bundle.
+pub fn upstream_ca_bundle_file(name: &str) -> String {
+    format!("{}.ca.crt", name)
+}
+
+/// TCP keepalive probes sent on upstream connections, so the kernel notices
+/// dead peers and middleboxes keep idle connections open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpKeepaliveConfig {
+    /// Idle time before the first probe.
+    pub idle_secs: u64,
+    #[serde(default = "default_tcp_keepalive_interval_secs")]
+    pub interval_secs: u64,
+    /// Unanswered probes before the connection is dropped.
+    #[serde(default = "default_tcp_keepalive_count")]
+    pub count: u32,
+    /// Linux only: drops the connection when sent data stays unacknowledged
+    /// this long (`TCP_USER_TIMEOUT`). Unset keeps the kernel default.
+    #[serde(default)]
+    pub user_timeout_ms: Option<u64>,
+}
+
+fn default_tcp_keepalive_interval_secs() -> u64 {
+    5
+}
+
+fn default_tcp_keepalive_count() -> u32 {
+    3
+}
+
+/// Reuse and socket settings for upstream connections. On a location, each
+/// unset field falls back to the host's setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpstreamConnectionConfig {
+    /// Pooled connections idle for longer are closed instead of reused;
+    /// 0 disables reuse. Keep it below the upstream's own keepalive timeout.
+    pub idle_timeout_ms: Option<u64>,
+    pub tcp_keepalive: Option<TcpKeepaliveConfig>,
+    /// On unless disabled.
+    pub tcp_nodelay: Option<bool>,
+    pub tcp_fast_open: Option<bool>,
+}
+
+impl UpstreamConnectionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(keepalive) = &self.tcp_keepalive {
+            if keepalive.idle_secs == 0 {
+                return Err(
+                    "upstream_connection.tcp_keepalive.idle_secs must be at least 1".to_string(),
+                );
+            }
+            if keepalive.interval_secs == 0 {
+                return Err(
+                    "upstream_connection.tcp_keepalive.interval_secs must be at least 1"
+                        .to_string(),
+                );
+            }
+            if keepalive.count == 0 {
+                return Err(
+                    "upstream_connection.tcp_keepalive.count must be at least 1".to_string()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serves a location from a local directory instead of proxying it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticFilesConfig {
+    /// Absolute directory the request path is resolved against.
+    pub root: String,
+    /// Files tried, in order, for directory requests.
+    #[serde(default = "default_static_index")]
+    pub index: Vec<String>,
+    /// Serves the root's index file for paths that match no file, for
+    /// single-page apps with client-side routing.
+    #[serde(default)]
+    pub spa_fallback: bool,
+}
+
+impl StaticFilesConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !std::path::Path::new(&self.root).is_absolute() {
+            return Err("static_files.root must be an absolute path".to_string());
+        }
+        if let Some(name) = self
+            .index
+            .iter()
+            .find(|name| name.is_empty() || name.contains(['/', '\\']) || name.as_str() == "..")
+        {
+            return Err(format!("Invalid static_files index file '{}'", name));
+        }
+        Ok(())
+    }
+}
+
+fn default_static_index() -> Vec<String> {
+    vec!["index.html".to_string()]
+}
+
+/// What happens after a rewrite rule matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewriteFlag {
+    /// Stops all further rewriting.
+    Last,
+    /// Stops the remaining rules of the same list; the location's rules still
+    /// run after a host rule breaks.
+    Break,
+    /// Answers with a `302` redirect to the rewritten URI.
+    Redirect,
+    /// Answers with a `301` redirect to the rewritten URI.
+    Permanent,
+}
+
+/// Replaces request URIs whose path matches `pattern`. `$1`-`$9` in
+/// `replacement` refer to capture groups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewriteRule {
+    pub pattern: String,
+    pub replacement: String,
+    /// Without a flag, later rules see the rewritten URI.
+    pub flag: Option<RewriteFlag>,
+    /// Compiled `pattern`, built by the config loader.
+    #[serde(skip)]
+    pub regex: Option<Regex>,
+}
+
+impl RewriteRule {
+    pub fn validate(&self) -> Result<(), String> {
+        Regex::new(&self.pattern)
+            .map_err(|e| format!("Invalid rewrite pattern '{}': {}", self.pattern, e))?;
+        if !self.replacement.starts_with('/') && !self.is_absolute_url() {
+            return Err(format!(
+                "Rewrite replacement '{}' must start with '/', http:// or https://",
+                self.replacement
+            ));
+        }
+        Ok(())
+    }
+
+    /// Absolute replacements always redirect.
+    pub fn is_absolute_url(&self) -> bool {
+        self.replacement.starts_with("http://") || self.replacement.starts_with("https://")
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocationConfig {
     pub path: String,
+    #[serde(default)]
+    pub match_type: LocationMatchType,
+    /// Precompiled pattern for regex locations, built by the config loader.
+    #[serde(skip)]
+    pub regex: Option<Regex>,
     // Changed: target -> targets for load balancing
-    pub targets: Vec<String>,
+    pub targets: Vec<UpstreamTarget>,
+    /// Overrides the host's balancing strategy when set.
+    pub lb_method: Option<LbMethod>,
+    pub lb_hash_key: Option<LbHashKey>,
     pub scheme: String,
     #[serde(default)]
     pub rewrite: bool,
@@ -19,7 +1367,45 @@ pub struct LocationConfig {
     pub connection_timeout_ms: Option<u64>,
     pub read_timeout_ms: Option<u64>,
     pub write_timeout_ms: Option<u64>,
+    /// Closes upgraded connections when the upstream sends nothing for this long.
+    pub websocket_idle_timeout_ms: Option<u64>,
+    /// Closes upgraded connections this long after they were opened.
+    pub websocket_max_lifetime_ms: Option<u64>,
     pub max_request_body_bytes: Option<u64>,
+    /// Probes this location's own targets; not inherited from the host.
+    pub health_check: Option<HealthCheckConfig>,
+    /// Falls back to the host's setting when unset.
+    pub sticky_session: Option<StickySessionConfig>,
+    /// Falls back to the host's policy, then `RetryPolicy::default()`.
+    pub retry_policy: Option<RetryPolicy>,
+    /// Falls back to the host's setting when unset.
+    pub cache: Option<CacheConfig>,
+    /// Falls back to the host's setting when unset.
+    pub compression: Option<CompressionConfig>,
+    /// Falls back to the host's limit when unset.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Replaces the host's access list when set.
+    #[serde(default)]
+    pub access_list_id: Option<i64>,
+    /// Replaces the host's redirect when set.
+    #[serde(default)]
+    pub redirect_to: Option<String>,
+    /// Falls back to the host's redirect status when unset.
+    #[serde(default)]
+    pub redirect_status: Option<u16>,
+    /// Run after the host's rules.
+    #[serde(default)]
+    pub rewrite_rules: Vec<RewriteRule>,
+    /// Serves files from disk instead of proxying to `targets`.
+    pub static_files: Option<StaticFilesConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    /// TLS client certificate, CA and version settings for `https` upstreams.
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    #[serde(default)]
+    pub upstream_protocol: UpstreamProtocol,
+    /// Connection reuse, TCP keepalive and socket options for the upstream.
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
 }
 
 /// Configuration for a specific virtual host.
@@ -27,7 +1413,10 @@ pub struct LocationConfig {
 pub struct HostConfig {
     pub id: i64,
     // Changed: target -> targets for load balancing
-    pub targets: Vec<String>,
+    pub targets: Vec<UpstreamTarget>,
+    #[serde(default)]
+    pub lb_method: LbMethod,
+    pub lb_hash_key: Option<LbHashKey>,
     pub scheme: String, // "http" or "https"
     #[serde(default)]
     pub locations: Vec<LocationConfig>,
@@ -39,6 +1428,10 @@ pub struct HostConfig {
     pub connection_timeout_ms: Option<u64>,
     pub read_timeout_ms: Option<u64>,
     pub write_timeout_ms: Option<u64>,
+    /// Closes upgraded connections when the upstream sends nothing for this long.
+    pub websocket_idle_timeout_ms: Option<u64>,
+    /// Closes upgraded connections this long after they were opened.
+    pub websocket_max_lifetime_ms: Option<u64>,
     pub max_request_body_bytes: Option<u64>,
     pub redirect_to: Option<String>,
     #[serde(default = "default_redirect_status")]
@@ -46,6 +1439,109 @@ pub struct HostConfig {
     pub access_list_id: Option<i64>,
     #[serde(default)]
     pub headers: Vec<HeaderConfig>,
+    /// Serves requests whose Host matches no other entry.
+    #[serde(default)]
+    pub is_default: bool,
+    pub health_check: Option<HealthCheckConfig>,
+    pub sticky_session: Option<StickySessionConfig>,
+    pub retry_policy: Option<RetryPolicy>,
+    pub cache: Option<CacheConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub forwarded_headers: Option<ForwardedHeadersConfig>,
+    #[serde(default)]
+    pub rewrite_rules: Vec<RewriteRule>,
+    pub maintenance: Option<MaintenanceConfig>,
+    pub mirror: Option<MirrorConfig>,
+    pub target_groups: Option<TargetGroupsConfig>,
+    /// TLS client certificate, CA and version settings for `https` upstreams.
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    #[serde(default)]
+    pub upstream_protocol: UpstreamProtocol,
+    /// Connection reuse, TCP keepalive and socket options for the upstream.
+    pub upstream_connection: Option<UpstreamConnectionConfig>,
+}
+
+/// Matches the serde defaults, so code can build configs with
+/// `..Default::default()` and get what an omitted JSON field would give.
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            targets: Vec::new(),
+            lb_method: LbMethod::default(),
+            lb_hash_key: None,
+            scheme: "http".to_string(),
+            locations: Vec::new(),
+            ssl_forced: false,
+            verify_ssl: default_verify_ssl(),
+            upstream_sni: None,
+            connection_timeout_ms: None,
+            read_timeout_ms: None,
+            write_timeout_ms: None,
+            websocket_idle_timeout_ms: None,
+            websocket_max_lifetime_ms: None,
+            max_request_body_bytes: None,
+            redirect_to: None,
+            redirect_status: default_redirect_status(),
+            access_list_id: None,
+            headers: Vec::new(),
+            is_default: false,
+            health_check: None,
+            sticky_session: None,
+            retry_policy: None,
+            cache: None,
+            compression: None,
+            rate_limit: None,
+            forwarded_headers: None,
+            rewrite_rules: Vec::new(),
+            maintenance: None,
+            mirror: None,
+            target_groups: None,
+            upstream_tls: None,
+            upstream_protocol: UpstreamProtocol::default(),
+            upstream_connection: None,
+        }
+    }
+}
+
+impl Default for LocationConfig {
+    fn default() -> Self {
+        Self {
+            path: "/".to_string(),
+            match_type: LocationMatchType::default(),
+            regex: None,
+            targets: Vec::new(),
+            lb_method: None,
+            lb_hash_key: None,
+            scheme: "http".to_string(),
+            rewrite: false,
+            verify_ssl: default_verify_ssl(),
+            upstream_sni: None,
+            connection_timeout_ms: None,
+            read_timeout_ms: None,
+            write_timeout_ms: None,
+            websocket_idle_timeout_ms: None,
+            websocket_max_lifetime_ms: None,
+            max_request_body_bytes: None,
+            health_check: None,
+            sticky_session: None,
+            retry_policy: None,
+            cache: None,
+            compression: None,
+            rate_limit: None,
+            access_list_id: None,
+            redirect_to: None,
+            redirect_status: None,
+            rewrite_rules: Vec::new(),
+            static_files: None,
+            mirror: None,
+            target_groups: None,
+            upstream_tls: None,
+            upstream_protocol: UpstreamProtocol::default(),
+            upstream_connection: None,
+        }
+    }
 }
 
 fn default_redirect_status() -> u16 {
@@ -84,6 +1580,135 @@ pub struct HeaderConfig {
     pub name: String,
     pub value: String,
     pub target: String, // request/response
+    #[serde(default)]
+    pub operation: HeaderOperation,
+    /// Location the rule is scoped to; `None` applies it to the whole host.
+    #[serde(default)]
+    pub location: Option<HeaderLocation>,
+}
+
+/// Identifies a location by the same path and match type it is stored under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderLocation {
+    pub path: String,
+    #[serde(default)]
+    pub match_type: LocationMatchType,
+}
+
+impl HeaderLocation {
+    pub fn matches(&self, location: &LocationConfig) -> bool {
+        self.path == location.path && self.match_type == location.match_type
+    }
+}
+
+/// What a custom header rule does to an existing header of the same name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderOperation {
+    /// Replaces any existing values.
+    #[default]
+    Set,
+    /// Adds a value next to the existing ones.
+    Append,
+    /// Strips the header; the rule's value is ignored.
+    Remove,
+    /// Sets the header only when it is absent.
+    SetIfMissing,
+}
+
+impl HeaderOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderOperation::Set => "set",
+            HeaderOperation::Append => "append",
+            HeaderOperation::Remove => "remove",
+            HeaderOperation::SetIfMissing => "set_if_missing",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "set" => Some(HeaderOperation::Set),
+            "append" => Some(HeaderOperation::Append),
+            "remove" => Some(HeaderOperation::Remove),
+            "set_if_missing" => Some(HeaderOperation::SetIfMissing),
+            _ => None,
+        }
+    }
+}
+
+/// Prefix marking a host entry as a regular expression (nginx style `~pattern`).
+pub const REGEX_HOST_PREFIX: char = '~';
+/// Prefix marking a host entry as a leading wildcard (`*.example.com`).
+pub const WILDCARD_HOST_PREFIX: &str = "*.";
+
+/// Lookup tables for host entries that cannot be resolved by an exact
+/// `HashMap` hit. Built once per config load.
+#[derive(Debug, Clone, Default)]
+pub struct HostIndex {
+    /// `(".example.com", "*.example.com")` pairs, longest suffix first.
+    wildcards: Vec<(String, String)>,
+    /// Regex hosts in declaration (host id) order.
+    regexes: Vec<(Regex, String)>,
+    /// Domain of the host flagged as the default server.
+    default_host: Option<String>,
+}
+
+impl HostIndex {
+    pub fn build(hosts: &HashMap<String, HostConfig>) -> Self {
+        let mut by_id: Vec<(&String, &HostConfig)> = hosts.iter().collect();
+        by_id.sort_by_key(|(_, host)| host.id);
+
+        let mut wildcards = Vec::new();
+        let mut regexes = Vec::new();
+        let mut default_host = None;
+
+        for (domain, host) in by_id {
+            if let Some(rest) = domain.strip_prefix(WILDCARD_HOST_PREFIX) {
+                wildcards.push((format!(".{}", rest.to_ascii_lowercase()), domain.clone()));
+            } else if let Some(pattern) = domain.strip_prefix(REGEX_HOST_PREFIX) {
+                match Regex::new(pattern) {
+                    Ok(re) => regexes.push((re, domain.clone())),
+                    Err(e) => {
+                        tracing::error!("Ignoring host with invalid regex '{}': {}", domain, e)
+                    }
+                }
+            }
+
+            if host.is_default && default_host.is_none() {
+                default_host = Some(domain.clone());
+            }
+        }
+
+        wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+
+        Self {
+            wildcards,
+            regexes,
+            default_host,
+        }
+    }
+}
+
+/// Validates a host entry name: `*.` wildcards need a suffix and `~` entries
+/// must be valid regexes.
+pub fn validate_host_pattern(domain: &str) -> Result<(), String> {
+    if let Some(pattern) = domain.strip_prefix(REGEX_HOST_PREFIX) {
+        return Regex::new(pattern)
+            .map(|_| ())
+            .map_err(|e| format!("Invalid host regex: {}", e));
+    }
+    if let Some(suffix) = domain.strip_prefix(WILDCARD_HOST_PREFIX) {
+        if suffix.is_empty() || suffix.contains('*') {
+            return Err(format!("Invalid wildcard host '{}'", domain));
+        }
+    } else if domain.contains('*') {
+        return Err(format!(
+            "Wildcards are only supported as a leading '*.' label: '{}'",
+            domain
+        ));
+    }
+    Ok(())
 }
 
 /// Proxy routing and security configuration.
@@ -91,12 +1716,46 @@ pub struct HeaderConfig {
 pub struct ProxyConfig {
     /// Domain name to host configuration mapping.
     pub hosts: HashMap<String, HostConfig>,
+    /// Wildcard, regex and default host lookups derived from `hosts`.
+    #[serde(skip)]
+    pub host_index: HostIndex,
     /// Access List ID to configuration mapping.
     #[serde(skip)]
     pub access_lists: HashMap<i64, AccessListConfig>,
     /// Host ID to header configurations mapping.
     #[serde(skip)]
     pub headers: HashMap<i64, Vec<HeaderConfig>>,
+    /// Error page overrides keyed by `(host_id, status_code)`; `None` acts as a wildcard.
+    #[serde(skip)]
+    pub error_pages: HashMap<(Option<i64>, Option<u16>), String>,
+}
+
+impl ProxyConfig {
+    /// Resolves a request host: exact match, then the longest matching
+    /// wildcard, then the first matching regex, then the default host.
+    pub fn find_host(&self, host: &str) -> Option<&HostConfig> {
+        if let Some(config) = self.hosts.get(host) {
+            return Some(config);
+        }
+
+        let host = host.to_ascii_lowercase();
+        let index = &self.host_index;
+
+        index
+            .wildcards
+            .iter()
+            .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+            .map(|(_, domain)| domain)
+            .or_else(|| {
+                index
+                    .regexes
+                    .iter()
+                    .find(|(re, _)| re.is_match(&host))
+                    .map(|(_, domain)| domain)
+            })
+            .or(index.default_host.as_ref())
+            .and_then(|domain| self.hosts.get(domain))
+    }
 }
 
 /// Real-time traffic metrics using atomic counters.
@@ -137,24 +1796,42 @@ pub struct AppState {
 
     /// HTML template for custom error pages.
     pub error_template: Arc<ArcSwap<String>>,
+
+    /// Round robin cursors and in-flight counters for upstream selection.
+    pub balancer: Arc<Balancer>,
+
+    /// Results of active upstream health checks.
+    pub health: Arc<HealthRegistry>,
+
+    /// Passive failure tracking that ejects misbehaving targets.
+    pub outliers: Arc<OutlierDetector>,
+
+    /// Token buckets of per-client rate limits.
+    pub rate_limits: Arc<RateLimiter>,
 }
 
 impl AppState {
     pub fn new() -> Self {
-        let error_template_str = fs::read_to_string("data/templates/error.html")
-            .unwrap_or_else(|_| "<h1>502 Bad Gateway</h1><p>Pingora Proxy Manager</p>".to_string());
+        let error_template_str =
+            fs::read_to_string("data/templates/error.html").unwrap_or_else(|_| {
+                "<h1>{{status_code}} {{status_text}}</h1><p>Pingora Proxy Manager</p>".to_string()
+            });
 
         Self {
             config: Arc::new(ArcSwap::from_pointee(ProxyConfig::default())),
             metrics: Arc::new(Metrics::new()),
             error_template: Arc::new(ArcSwap::from_pointee(error_template_str)),
+            balancer: Arc::new(Balancer::new()),
+            health: Arc::new(HealthRegistry::new()),
+            outliers: Arc::new(OutlierDetector::new()),
+            rate_limits: Arc::new(RateLimiter::new()),
         }
     }
 
-    /// 특정 호스트에 대한 설정을 조회합니다.
+    /// 특정 호스트에 대한 설정을 조회합니다. (exact > wildcard > regex > default)
     pub fn get_host_config(&self, host: &str) -> Option<HostConfig> {
         let config = self.config.load();
-        config.hosts.get(host).cloned()
+        config.find_host(host).cloned()
     }
 
     pub fn get_access_list(&self, id: i64) -> Option<AccessListConfig> {
@@ -168,8 +1845,26 @@ impl AppState {
         config.headers.get(&host_id).cloned().unwrap_or_default()
     }
 
+    /// 에러 페이지 템플릿을 조회합니다.
+    /// (host+status > host > status > 전역 템플릿 순으로 우선 적용)
+    pub fn get_error_template(&self, host_id: Option<i64>, status: u16) -> String {
+        let config = self.config.load();
+        let candidates = [
+            (host_id, Some(status)),
+            (host_id, None),
+            (None, Some(status)),
+        ];
+
+        candidates
+            .iter()
+            .find_map(|key| config.error_pages.get(key).cloned())
+            .unwrap_or_else(|| self.error_template.load().as_ref().clone())
+    }
+
     /// 설정을 통째로 교체합니다. (Atomic)
     pub fn update_config(&self, new_config: ProxyConfig) {
+        self.balancer.retain(&new_config);
+        crate::proxy::cache::retain(&new_config);
         self.config.store(Arc::new(new_config));
     }
 
@@ -178,3 +1873,261 @@ impl AppState {
         self.error_template.store(Arc::new(new_template));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_host(id: i64, target: &str, is_default: bool) -> HostConfig {
+        HostConfig {
+            id,
+            targets: vec![target.into()],
+            is_default,
+            ..Default::default()
+        }
+    }
+
+    fn test_config(entries: Vec<(&str, HostConfig)>) -> ProxyConfig {
+        let hosts: HashMap<String, HostConfig> = entries
+            .into_iter()
+            .map(|(domain, host)| (domain.to_string(), host))
+            .collect();
+        let host_index = HostIndex::build(&hosts);
+        ProxyConfig {
+            hosts,
+            host_index,
+            ..Default::default()
+        }
+    }
+
+    fn resolved_target(config: &ProxyConfig, host: &str) -> Option<String> {
+        config.find_host(host).map(|h| h.targets[0].addr.clone())
+    }
+
+    #[test]
+    fn find_host_prefers_exact_then_wildcard_then_regex_then_default() {
+        let config = test_config(vec![
+            ("app.preview.example.com", test_host(1, "exact", false)),
+            ("*.preview.example.com", test_host(2, "wildcard", false)),
+            ("*.example.com", test_host(3, "wildcard-short", false)),
+            (r"~^pr-[0-9]+\.example\.org$", test_host(4, "regex", false)),
+            ("fallback.local", test_host(5, "default", true)),
+        ]);
+
+        assert_eq!(
+            resolved_target(&config, "app.preview.example.com").as_deref(),
+            Some("exact")
+        );
+        assert_eq!(
+            resolved_target(&config, "feature-x.preview.example.com").as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(
+            resolved_target(&config, "a.b.preview.example.com").as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(
+            resolved_target(&config, "www.example.com").as_deref(),
+            Some("wildcard-short")
+        );
+        assert_eq!(
+            resolved_target(&config, "pr-42.example.org").as_deref(),
+            Some("regex")
+        );
+        assert_eq!(
+            resolved_target(&config, "example.com").as_deref(),
+            Some("default")
+        );
+        assert_eq!(
+            resolved_target(&config, "unknown.test").as_deref(),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn find_host_without_default_returns_none_for_unknown_hosts() {
+        let config = test_config(vec![("*.example.com", test_host(1, "wildcard", false))]);
+
+        assert!(config.find_host("example.com").is_none());
+        assert!(config.find_host("notexample.com").is_none());
+        assert_eq!(
+            resolved_target(&config, "API.Example.com").as_deref(),
+            Some("wildcard")
+        );
+    }
+
+    #[test]
+    fn validate_host_pattern_rejects_malformed_entries() {
+        assert!(validate_host_pattern("example.com").is_ok());
+        assert!(validate_host_pattern("*.example.com").is_ok());
+        assert!(validate_host_pattern(r"~^pr-\d+\.example\.com$").is_ok());
+        assert!(validate_host_pattern("*.").is_err());
+        assert!(validate_host_pattern("api.*.example.com").is_err());
+        assert!(validate_host_pattern("~(unclosed").is_err());
+    }
+
+    #[test]
+    fn upstream_target_list_round_trips_weights() {
+        let targets =
+            UpstreamTarget::parse_list("10.0.0.1:80 weight=3, 10.0.0.2:80,, backend:8080 weight=1")
+                .unwrap();
+
+        assert_eq!(
+            targets,
+            vec![
+                UpstreamTarget {
+                    addr: "10.0.0.1:80".to_string(),
+                    weight: 3
+                },
+                UpstreamTarget::new("10.0.0.2:80"),
+                UpstreamTarget::new("backend:8080"),
+            ]
+        );
+        assert_eq!(
+            UpstreamTarget::join(&targets),
+            "10.0.0.1:80 weight=3,10.0.0.2:80,backend:8080"
+        );
+        assert!(UpstreamTarget::parse_list("10.0.0.1:80 weight=0").is_err());
+        assert!(UpstreamTarget::parse_list("10.0.0.1:80 backup").is_err());
+    }
+
+    #[test]
+    fn unix_socket_targets_need_absolute_paths() {
+        let targets =
+            UpstreamTarget::parse_list("unix:/run/php/fpm.sock weight=2, 127.0.0.1:9000").unwrap();
+        assert_eq!(targets[0].unix_socket_path(), Some("/run/php/fpm.sock"));
+        assert_eq!(targets[0].weight, 2);
+        assert_eq!(targets[1].unix_socket_path(), None);
+        assert_eq!(
+            UpstreamTarget::join(&targets),
+            "unix:/run/php/fpm.sock weight=2,127.0.0.1:9000"
+        );
+
+        assert!(UpstreamTarget::parse("unix:run/app.sock").is_err());
+        assert!(UpstreamTarget::parse("unix:").is_err());
+        assert!(UpstreamTarget::parse(&format!("unix:/{}", "a".repeat(120))).is_err());
+    }
+
+    #[test]
+    fn mirror_targets_are_validated_like_upstream_targets() {
+        let mirror = |target: &str| -> MirrorConfig {
+            serde_json::from_value(serde_json::json!({ "target": target })).unwrap()
+        };
+        assert!(mirror("10.0.0.9:8080").validate().is_ok());
+        assert!(mirror("unix:/run/shadow.sock").validate().is_ok());
+        for invalid in ["foo:bar", ":80", "host:99999", "unix:relative.sock", " "] {
+            assert!(mirror(invalid).validate().is_err(), "{}", invalid);
+        }
+
+        let mut no_body = mirror("10.0.0.9:8080");
+        no_body.max_body_bytes = 0;
+        assert!(no_body.validate().is_err());
+    }
+
+    #[test]
+    fn network_targets_need_a_host_and_valid_port() {
+        for valid in ["backend:8080", "10.0.0.1:1", "[::1]:65535", "[fe80::1]:80"] {
+            assert!(UpstreamTarget::validate_addr(valid).is_ok(), "{}", valid);
+        }
+        for invalid in [
+            "backend",
+            "backend:",
+            ":8080",
+            "backend:0",
+            "backend:65536",
+            "backend:http",
+            "::1:80",
+            "[::1]",
+            "[]:80",
+            "[not-ip]:80",
+            "http://backend:80",
+        ] {
+            assert!(
+                UpstreamTarget::validate_addr(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn upstream_tls_checks_names_and_versions() {
+        let config: UpstreamTlsConfig = serde_json::from_value(serde_json::json!({
+            "client_cert": "billing-client",
+            "ca_bundle": "internal_ca",
+            "min_version": "1.3"
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.min_version, Some(TlsVersion::Tls13));
+        assert!(config.load_material(Path::new("/nonexistent")).is_err());
+
+        assert!(validate_upstream_cert_name("../etc/passwd").is_err());
+        assert!(validate_upstream_cert_name("client.ca").is_err());
+        assert!(validate_upstream_cert_name("").is_err());
+
+        assert!(TlsVersion::from_protocol("TLSv1.2").unwrap() < TlsVersion::Tls13);
+        assert_eq!(TlsVersion::from_protocol("SSLv3"), None);
+    }
+
+    #[test]
+    fn upstream_connection_rejects_empty_keepalive_probes() {
+        let config: UpstreamConnectionConfig = serde_json::from_value(serde_json::json!({
+            "idle_timeout_ms": 0,
+            "tcp_keepalive": { "idle_secs": 30 }
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.tcp_keepalive.unwrap().count, 3);
+
+        let config: UpstreamConnectionConfig = serde_json::from_value(serde_json::json!({
+            "tcp_keepalive": { "idle_secs": 30, "count": 0 }
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn header_operations_round_trip_through_strings() {
+        for op in [
+            HeaderOperation::Set,
+            HeaderOperation::Append,
+            HeaderOperation::Remove,
+            HeaderOperation::SetIfMissing,
+        ] {
+            assert_eq!(HeaderOperation::parse(op.as_str()), Some(op));
+        }
+        assert_eq!(HeaderOperation::parse("replace"), None);
+    }
+
+    #[test]
+    fn maintenance_applies_inside_its_window() {
+        let at = |t: &str| DateTime::parse_from_rfc3339(t).unwrap().with_timezone(&Utc);
+        let mut maintenance = MaintenanceConfig {
+            enabled: true,
+            starts_at: Some("2026-03-01T02:00:00Z".to_string()),
+            ends_at: Some("2026-03-01T04:00:00Z".to_string()),
+            retry_after_secs: 300,
+            allow_access_list_id: None,
+            page_html: None,
+        };
+        assert!(maintenance.validate().is_ok());
+
+        assert!(!maintenance.is_active(at("2026-03-01T01:59:59Z")));
+        assert!(maintenance.is_active(at("2026-03-01T02:00:00Z")));
+        assert!(!maintenance.is_active(at("2026-03-01T04:00:00Z")));
+        assert_eq!(maintenance.retry_after(at("2026-03-01T03:00:00Z")), 3600);
+
+        maintenance.ends_at = None;
+        assert!(maintenance.is_active(at("2030-01-01T00:00:00Z")));
+        assert_eq!(maintenance.retry_after(at("2030-01-01T00:00:00Z")), 300);
+
+        maintenance.enabled = false;
+        assert!(!maintenance.is_active(at("2026-03-01T03:00:00Z")));
+
+        maintenance.ends_at = Some("2026-03-01T01:00:00Z".to_string());
+        assert!(maintenance.validate().is_err());
+        maintenance.ends_at = Some("tomorrow".to_string());
+        assert!(maintenance.validate().is_err());
+    }
+}